@@ -1,35 +1,16 @@
 # story_to_assetbundle.py
 
-# TODO: Cleanup
+# Called through the UnityPy bridge in src/unitypy.rs.
+# Nothing is kept between calls, every function is given the bundle or object it works on.
 
-import os
 import UnityPy
 import UnityPy.config
 
 from PIL import Image
 import io
 
-import json
-
 UnityPy.config.FALLBACK_UNITY_VERSION = "2022.3.21f1"
 
-story_asset_path = ""
-target_path_id = ""
-target_object = None
-env = None
-
-def set_asset_path(asset_path):
-    global story_asset_path
-
-    print("Setting asset path to "+str(asset_path))
-    story_asset_path = asset_path
-
-def set_target_path_id(path_id):
-    global target_path_id
-
-    print("Setting target path id to "+str(path_id))
-    target_path_id = path_id
-
 def print_typetree(data, indent=0):
     prefix = " " * indent
     if isinstance(data, dict):
@@ -43,10 +24,7 @@ def print_typetree(data, indent=0):
     else:
         print(f"{prefix}{repr(data)}")
 
-def list_assets():
-    global story_asset_path
-
-    env = UnityPy.load(story_asset_path)
+def list_assets(env):
     for obj in env.objects:
         print(f"\nName: [{obj.type.name}] PathID: {obj.path_id}")
         try:
@@ -64,132 +42,74 @@ def list_assets():
         except Exception as e:
             print(f"Could not read object: {e}")
 
-# Returns the current typetree TODO: Use name instead of path_id
-def return_typetree():
-    global story_asset_path, target_path_id, target_object, env
-
-    env = UnityPy.load(story_asset_path)
+def open_bundle(asset_path):
+    return UnityPy.load(asset_path)
 
+# Returns the first object of the type `type_name` named `name`, or None
+def find_object(env, type_name, name):
     for obj in env.objects:
-        if obj.path_id == target_path_id:
-            target_object = obj
-            break
-
-    if target_object is None:
-        print(f"No object found with path_id {target_path_id}")
-        return
-
-    if target_object.type.name != "MonoBehaviour":
-        print("Object is not an MonoBehaviour.")
-
-    typetree = target_object.read_typetree()
+        if obj.type.name != type_name:
+            continue
 
-    # print(typetree)
-    # print(json.dumps(typetree, sort_keys=True, indent=4))
-    # list_assets()
+        if getattr(obj.read(), "m_Name", None) != name:
+            continue
 
-    return typetree
+        return obj
 
-def return_texture2d_img(object_name):
-    global story_asset_path, target_path_id, target_object, env
-
-    env = UnityPy.load(story_asset_path)
-
-    print("Obtaining correct asset object")
-    if target_object is None or target_object.read().m_Name != object_name:
-        # Cache the object after the first lookup
-        for obj in env.objects:
-            data = obj.read()
-            if data.m_Name == object_name and obj.type.name == "Texture2D":
-                target_object = obj
-                break
-        else:
-            print(f"No object found with name {object_name}")
-            return
+    return None
 
-    if target_object.type.name != "Texture2D":
-        print("Object is not an Texture2D.")
-
-    data = target_object.read()
+# Returns the MonoBehaviour running the script `script` named `name`, or the first one if `name` is None. Returns None if there is no such MonoBehaviour.
+# Scripts stored in another bundle can't be read, so MonoBehaviours having every field in `fields` match instead
+def find_mono_behaviour(env, script, name, fields):
+    for obj in env.objects:
+        if obj.type.name != "MonoBehaviour":
+            continue
 
-    img_byte_arr = io.BytesIO()
-    data.image.save(img_byte_arr, format='PNG')
+        typetree = obj.read_typetree()
+        class_name = script_class(obj, typetree)
 
-    return img_byte_arr.getvalue()
-
-#TODO Cleanup/make more efficient
-def save_texture2d_img(object_name, new_image_path, write_changes):
-    global target_object, env, story_asset_path
-
-    if env == None:
-        env = UnityPy.load(story_asset_path)
-
-    print("Obtaining correct asset object")
-    if target_object is None or target_object.read().m_Name != object_name:
-        # Cache the object after the first lookup
-        for obj in env.objects:
-            data = obj.read()
-            if data.m_Name == object_name and obj.type.name == "Texture2D":
-                target_object = obj
-                break
+        if class_name is None:
+            matched = all(field in typetree for field in fields)
         else:
-            print(f"No object found with name {object_name}")
-            return
+            matched = class_name == script
 
-    print("Opening image")
-    image = Image.open(new_image_path)
-    print("Saving image")
-    data.image = image
-    data.save()
+        if not matched:
+            continue
 
-    if write_changes:
-        print("Writing changes")
-        with open(story_asset_path, "wb") as f:
-            f.write(env.file.save())
+        if name is None or typetree.get("m_Name") == name:
+            return obj
 
-#TODO Cleanup/make more efficient
-def save_sprite_img(object_name, new_image_path, write_changes):
-    global target_object, env, story_asset_path
+    return None
 
-    if env == None:
-        env = UnityPy.load(story_asset_path)
+# The class name of the script a MonoBehaviour runs, if its MonoScript is in the same file
+def script_class(obj, typetree):
+    script = typetree.get("m_Script", {})
+    if script.get("m_FileID") != 0:
+        return None
 
-    print("Obtaining correct asset object")
-    for obj in env.objects:
-      if target_object is None or target_object.read().m_Name != object_name:
-        # Cache the object after the first lookup
-        for obj in env.objects:
-            data = obj.read()
-            if data.m_Name == object_name and obj.type.name == "Sprite":
-                target_object = obj
-                break
-        else:
-            print(f"No object found with name {object_name}")
-            return
-
-    data = target_object.read()
-    
-    print("Saving new logo into sprite")
-    data.image.save(new_image_path)
+    mono_script = obj.assets_file.objects.get(script.get("m_PathID"))
+    if mono_script is None or mono_script.type.name != "MonoScript":
+        return None
 
-    # data.save()
+    return mono_script.read_typetree().get("m_ClassName")
 
-    if write_changes:
-        print("Writing changes")
-        with open(story_asset_path, "wb") as f:
-            f.write(env.file.save())
+def read_typetree(obj):
+    return obj.read_typetree()
 
+def save_typetree(obj, typetree):
+    obj.save_typetree(typetree)
 
-def save_typetree(typetree):
-    global target_object, env
+def replace_texture(obj, new_image_path):
+    data = obj.read()
+    data.image = Image.open(new_image_path)
+    data.save()
 
-    if target_object is None:
-        print("Target object was not set. Something has gone wrong!")
-        return
+def export_texture(obj):
+    img_byte_arr = io.BytesIO()
+    obj.read().image.save(img_byte_arr, format='PNG')
 
-    target_object.save_typetree(typetree)
+    return img_byte_arr.getvalue()
 
-    with open(story_asset_path, "wb") as f:
+def save_bundle(env, asset_path):
+    with open(asset_path, "wb") as f:
         f.write(env.file.save())
-
-    return "Success"
\ No newline at end of file
@@ -1,8 +1,7 @@
 use std::{
-    ffi::CString,
     fs::{self, File},
     io::{Read, Write},
-    path::{Path, PathBuf},
+    path::Path,
 };
 
 // Credit for reverse engineering and decryption method of assetbundle info goes to https://github.com/mos9527/sssekai
@@ -11,18 +10,20 @@ use anyhow::{Context, Result};
 use block_padding::Pkcs7;
 use cbc::cipher::{BlockDecryptMut, BlockEncryptMut, KeyIvInit};
 use chrono::{Datelike, Local, Timelike};
-use log::{debug, error, info, warn};
-use pyo3::{
-    Python,
-    types::{PyAnyMethods, PyModule},
-};
+use image::{DynamicImage, ImageFormat};
+use log::{debug, info, warn};
 use rand::Rng;
 use serde::Serialize;
-use walkdir::WalkDir;
 
 use crate::{
-    mods::{CacheInvalidDuration, ModData},
-    scenario::PY_CODE,
+    mods::{CacheInvalidDuration, load_mods, resolve_active_mods},
+    unity::{
+        bundle::UnityBundle,
+        texture::{
+            UnsupportedTextureFormat, fit_to_texture, replace_texture2d, texture_dimensions,
+        },
+    },
+    unitypy::UnityPyBundle,
     utils::{ABInfoRoot, Config},
 };
 
@@ -65,94 +66,76 @@ pub fn decrypt_aes_cbc(encrypted: &[u8], key: &[u8], iv: &[u8]) -> Result<Vec<u8
 /// in case of permanently invalid hash and
 /// FakePlaceholderHash0000000000000
 /// as the "new" hash
+///
+/// Always starts from the upstream copy of the assetbundle info, so bundles belonging to inactive mods get their original hash back.
 pub fn reload_assetbundle_info(config: &Config, asset_version: &String) -> Result<()> {
-    let mod_path = Path::new("mods");
-
     let assetbundle_info_path = &format!(
         "{}/api/version/{}/os/{}",
         config.advanced.assets.asset_path, asset_version, config.platform
     );
 
-    debug!("reading {assetbundle_info_path}");
-    let mut assetbundle_info = File::open(assetbundle_info_path)?;
+    // Untouched copy of the assetbundle info as downloaded, removed by update_assets whenever a new one is downloaded
+    let upstream_assetbundle_info_path = &format!("{assetbundle_info_path}.upstream");
 
-    let mut byte_buffer = Vec::new();
-    assetbundle_info
-        .read_to_end(&mut byte_buffer)
-        .context("Reading assetbundle info file")?;
+    if !Path::new(upstream_assetbundle_info_path).exists() {
+        debug!("Backing up upstream assetbundle info to {upstream_assetbundle_info_path}");
+        fs::copy(assetbundle_info_path, upstream_assetbundle_info_path)
+            .context("Backing up upstream assetbundle info")?;
+    }
 
-    let key = get_apimanager_keys(&config.region).unwrap();
-    let decrypted_abinfo = decrypt_aes_cbc(&byte_buffer, key.0, key.1)?;
+    let mut abinfo = read_assetbundle_info(Path::new(upstream_assetbundle_info_path), config)?;
 
-    let mut abinfo: ABInfoRoot = rmp_serde::from_slice(&decrypted_abinfo)?;
+    let mods = load_mods();
+    let active = resolve_active_mods(&mods, config, asset_version);
 
     // Loop through and modify abinfo to have an invalid hash for each asset
-    for entry in WalkDir::new(mod_path) {
-        match entry {
-            Ok(entry) => {
-                if entry.file_type().is_file()
-                    && entry.path().extension().and_then(|e| e.to_str()) == Some("toml")
-                {
-                    let entry_data = fs::read_to_string(entry.path()).unwrap_or_else(|_| {
-                            panic!(
-                                "Could not read {}! Please try redownloading mods and fixing permissions.",
-                                entry.path().display()
-                            )
-                        });
-
-                    let mod_data: ModData = toml::from_str(&entry_data).unwrap_or_else(|_| {
-                        panic!(
-                            "{} is not formatted properly! Check if MikuMikuLoader is out of date.",
-                            entry.path().display()
-                        )
-                    });
-
-                    for asset in mod_data.invalidated_assets {
-                        debug!("Invalidating cache for {}", asset.resource_path);
-                        match abinfo.bundles.get_mut(&asset.resource_path) {
-                            Some(bundle) => match asset.duration {
-                                CacheInvalidDuration::PermanentlyInvalid => {
-                                    let now = Local::now();
-
-                                    let formatted = format!(
-                                        "FakePlaceholderHash{:04}{:02}{:02}{:02}{:03}",
-                                        now.year(),
-                                        now.month(),
-                                        now.day(),
-                                        now.second(),
-                                        now.timestamp_subsec_millis()
-                                    );
-
-                                    debug!("{formatted}");
-                                    bundle.hash = formatted;
-                                    bundle.category = "StartApp".to_string(); // Force redownload on app start (I think)
-                                    bundle.paths[0] =
-                                        bundle.paths[0].replace("OnDemand", "StartApp"); // Download this asset on game startup
-                                    bundle.crc = rand::rng().random_range(500..10000); // Needed to actually force the game to redownload for some reason
-                                    bundle.file_size = rand::rng().random_range(500..10000);
-                                }
-                                CacheInvalidDuration::InitiallyInvalid => {
-                                    bundle.hash = "FakePlaceholderHash0000000000000".to_string(); // TODO: Track if this asset has already been injected
-                                    bundle.category = "StartApp".to_string();
-                                    bundle.paths[0] =
-                                        bundle.paths[0].replace("OnDemand", "StartApp");
-                                    bundle.crc = rand::rng().random_range(500..10000); // Needed to actually force the game to redownload for some reason
-                                    bundle.file_size = rand::rng().random_range(500..10000);
-                                }
-                            },
-                            None => {
-                                warn!(
-                                    "No matching ABInfo asset found for asset {}, it's cache will not be invalidated",
-                                    asset.resource_path
-                                );
-                            }
-                        }
+    for ((_, mod_data), active) in mods.into_iter().zip(active) {
+        if !active {
+            debug!(
+                "{} is not active, leaving its assets at the upstream hash",
+                mod_data.mod_name
+            );
+            continue;
+        }
+
+        for asset in mod_data.invalidated_assets {
+            debug!("Invalidating cache for {}", asset.resource_path);
+            match abinfo.bundles.get_mut(&asset.resource_path) {
+                Some(bundle) => match asset.duration {
+                    CacheInvalidDuration::PermanentlyInvalid => {
+                        let now = Local::now();
+
+                        let formatted = format!(
+                            "FakePlaceholderHash{:04}{:02}{:02}{:02}{:03}",
+                            now.year(),
+                            now.month(),
+                            now.day(),
+                            now.second(),
+                            now.timestamp_subsec_millis()
+                        );
+
+                        debug!("{formatted}");
+                        bundle.hash = formatted;
+                        bundle.category = "StartApp".to_string(); // Force redownload on app start (I think)
+                        bundle.paths[0] = bundle.paths[0].replace("OnDemand", "StartApp"); // Download this asset on game startup
+                        bundle.crc = rand::rng().random_range(500..10000); // Needed to actually force the game to redownload for some reason
+                        bundle.file_size = rand::rng().random_range(500..10000);
+                    }
+                    CacheInvalidDuration::InitiallyInvalid => {
+                        bundle.hash = "FakePlaceholderHash0000000000000".to_string(); // TODO: Track if this asset has already been injected
+                        bundle.category = "StartApp".to_string();
+                        bundle.paths[0] = bundle.paths[0].replace("OnDemand", "StartApp");
+                        bundle.crc = rand::rng().random_range(500..10000); // Needed to actually force the game to redownload for some reason
+                        bundle.file_size = rand::rng().random_range(500..10000);
                     }
+                },
+                None => {
+                    warn!(
+                        "No matching ABInfo asset found for asset {}, it's cache will not be invalidated",
+                        asset.resource_path
+                    );
                 }
             }
-            Err(ref e) => {
-                error!("Could not read {entry:?}, skipping scan. Err: {e}");
-            }
         }
     }
 
@@ -160,6 +143,7 @@ pub fn reload_assetbundle_info(config: &Config, asset_version: &String) -> Resul
     let mut se = rmp_serde::encode::Serializer::new(&mut buf).with_struct_map();
     abinfo.serialize(&mut se)?;
 
+    let key = get_apimanager_keys(&config.region).unwrap();
     let encrypted_abinfo = encrypt_aes_cbc(&buf, key.0, key.1)?;
 
     // Recreate the assetbundle info with newly invalid hashes
@@ -174,69 +158,119 @@ pub fn reload_assetbundle_info(config: &Config, asset_version: &String) -> Resul
     Ok(())
 }
 
-/// Accepts `Option<PathBuf>` and generates an screen_image assetbundle from it.
+/// Loads the assetbundle info as the game servers sent it, without any of the changes made for mods.
+pub fn load_assetbundle_info(config: &Config, asset_version: &str) -> Result<ABInfoRoot> {
+    let assetbundle_info_path = format!(
+        "{}/api/version/{}/os/{}",
+        config.advanced.assets.asset_path, asset_version, config.platform
+    );
+    let upstream_assetbundle_info_path = format!("{assetbundle_info_path}.upstream");
+
+    // Only mods change the assetbundle info, so it is still upstream if it was never backed up
+    if Path::new(&upstream_assetbundle_info_path).exists() {
+        read_assetbundle_info(Path::new(&upstream_assetbundle_info_path), config)
+    } else {
+        read_assetbundle_info(Path::new(&assetbundle_info_path), config)
+    }
+}
+
+fn read_assetbundle_info(path: &Path, config: &Config) -> Result<ABInfoRoot> {
+    debug!("reading {}", path.display());
+    let mut assetbundle_info =
+        File::open(path).with_context(|| format!("Could not open {}", path.display()))?;
+
+    let mut byte_buffer = Vec::new();
+    assetbundle_info
+        .read_to_end(&mut byte_buffer)
+        .context("Reading assetbundle info file")?;
+
+    let key = get_apimanager_keys(&config.region).unwrap();
+    let decrypted_abinfo = decrypt_aes_cbc(&byte_buffer, key.0, key.1)?;
+
+    Ok(rmp_serde::from_slice(&decrypted_abinfo)?)
+}
+
+/// Replaces the images of the screen_image AssetBundle at `assetbundle_path` in place, resized to the template's dimensions.
 /// If an image is not set, it will not be modified, and will be the default in the template.
-/// Requires passing the path as in UnityPy saving from an buffer in memory is incredibly slow
 pub fn generate_screen_image(
-    assetbundle_path: &String,
-    banner_event_story: Option<PathBuf>,
-    story_bg: Option<PathBuf>,
-    story_title: Option<PathBuf>,
+    assetbundle_path: &Path,
+    banner_event_story: Option<DynamicImage>,
+    story_bg: Option<DynamicImage>,
+    story_title: Option<DynamicImage>,
 ) -> Result<()> {
-    Python::attach(|py| {
-        let filename = CString::new("story_to_assetbundle.py").unwrap();
-        let modname = CString::new("story_to_assetbundle").unwrap();
+    replace_textures(
+        assetbundle_path,
+        ["story_bg", "banner_event_story", "story_title"]
+            .into_iter()
+            .zip([story_bg, banner_event_story, story_title])
+            .filter_map(|(name, image)| Some((name, image?)))
+            .collect(),
+    )
+}
 
-        let module = PyModule::from_code(py, &CString::new(PY_CODE).unwrap(), &filename, &modname)?;
+/// Replaces the logo texture and sprite of the logo AssetBundle at `assetbundle_path` in place.
+pub fn generate_logo(assetbundle_path: &Path, logo: DynamicImage) -> Result<()> {
+    replace_textures(assetbundle_path, vec![("logo", logo)])
+}
 
-        module
-            .getattr("set_asset_path")?
-            .call1((&assetbundle_path,))?;
+/// Replaces episode title textures, named like event_whip_2024_01, of the episode_image AssetBundle at `assetbundle_path` in place.
+pub fn generate_episode_images(
+    assetbundle_path: &Path,
+    images: Vec<(&str, DynamicImage)>,
+) -> Result<()> {
+    replace_textures(assetbundle_path, images)
+}
 
-        for img in ["story_bg", "banner_event_story", "story_title"]
-            .iter()
-            .zip([story_bg, banner_event_story, story_title])
-            .enumerate()
-        {
-            if let Some(image_path) = img.1.1 {
-                info!("Saving texture2d for {}", img.1.0);
-                info!("calling with: {}, {}, {}", img.1.0, image_path.display(), {
-                    img.0 == 2
-                });
-                module.getattr("save_texture2d_img")?.call1((
-                    img.1.0,
-                    image_path.display().to_string(),
-                    { img.0 == 2 },
-                ))?;
+/// Replaces the named textures of an AssetBundle natively where their format allows it, and through UnityPy otherwise.
+fn replace_textures(assetbundle_path: &Path, images: Vec<(&str, DynamicImage)>) -> Result<()> {
+    let mut bundle = UnityBundle::open(assetbundle_path)?;
+    let mut unsupported = Vec::new();
+
+    for (name, image) in images {
+        info!("Saving texture2d for {name}");
+
+        match replace_texture2d(&mut bundle, name, &image.to_rgba8()) {
+            Ok(true) => {}
+            Ok(false) => warn!("{} has no texture named {name}", assetbundle_path.display()),
+            Err(e) if e.downcast_ref::<UnsupportedTextureFormat>().is_some() => {
+                info!("{e}, falling back to UnityPy for {name}");
+
+                // UnityPy keeps the size of the image, so resize it like replace_texture2d would
+                let image = match texture_dimensions(&bundle, name)? {
+                    Some((width, height)) => {
+                        fit_to_texture(&image.to_rgba8(), name, width, height).into()
+                    }
+                    None => image,
+                };
+                unsupported.push((name, image));
             }
+            Err(e) => return Err(e),
         }
+    }
 
-        Ok(())
-    })
-}
+    fs::write(assetbundle_path, bundle.to_bytes()?)?;
 
-/// Accepts `Option<DynamicImage>` and generates an logo assetbundle from it
-/// If an image is not set, it will not be modified, and will be the default in the template.
-/// Requires an path since UnityPy only accepts an file path when modifying a sprite.
-pub fn generate_logo(assetbundle_path: String, logo_path: PathBuf) -> Result<()> {
-    Python::attach(|py| {
-        let filename = CString::new("story_to_assetbundle.py").unwrap();
-        let modname = CString::new("story_to_assetbundle").unwrap();
+    if !unsupported.is_empty() {
+        replace_textures_with_unitypy(assetbundle_path, unsupported)?;
+    }
 
-        let module = PyModule::from_code(py, &CString::new(PY_CODE).unwrap(), &filename, &modname)?;
+    Ok(())
+}
 
-        module
-            .getattr("set_asset_path")?
-            .call1((&assetbundle_path,))?;
+fn replace_textures_with_unitypy(
+    assetbundle_path: &Path,
+    images: Vec<(&str, DynamicImage)>,
+) -> Result<()> {
+    let bundle = UnityPyBundle::open(assetbundle_path)?;
 
-        module
-            .getattr("save_texture2d_img")?
-            .call1(("logo", logo_path.clone(), true))?;
+    for (name, image) in images {
+        // Deleted when dropped, thus it needs to live until UnityPy is done with it
+        let image_file = tempfile::Builder::new().suffix(".png").tempfile()?;
+        image.save_with_format(image_file.path(), ImageFormat::Png)?;
 
-        module
-            .getattr("save_sprite_img")?
-            .call1(("logo", logo_path, true))?;
+        bundle.replace_texture(name, image_file.path())?;
+    }
 
-        Ok(())
-    })
+    // Only write the AssetBundle once every texture is replaced
+    bundle.save(assetbundle_path)
 }
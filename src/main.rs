@@ -1,13 +1,16 @@
 mod assetbundle;
 mod mods;
+mod pack;
 mod routes;
 mod scenario;
+mod story;
+mod unity;
+mod unitypy;
 mod utils;
 
 use std::{
     collections::BTreeMap,
     error::Error,
-    ffi::CString,
     fs::{self, File, OpenOptions, create_dir, create_dir_all},
     io::{Cursor, Read, Write},
     net::{IpAddr, Ipv4Addr, SocketAddr},
@@ -24,28 +27,21 @@ use axum::{
     extract::DefaultBodyLimit,
     http::{StatusCode, header},
     response::{IntoResponse, Response},
-    routing::{get, post},
+    routing::{delete, get, post},
 };
 use colored::Colorize;
 use futures::{StreamExt, stream::FuturesUnordered};
 use gumdrop::Options;
 use hyper_rustls::HttpsConnectorBuilder;
 use hyper_util::{client::legacy::Client, rt::TokioExecutor};
-use image::ImageReader;
 use indicatif::ProgressBar;
 use local_ip_address::local_ip;
 use log::{debug, error, info, warn};
 #[cfg(not(debug_assertions))]
 use notify_rust::Notification;
-use pyo3::{
-    Python,
-    types::{PyAnyMethods, PyModule},
-};
-use pythonize::depythonize;
-use routes::static_handler;
+use routes::{WebState, static_handler};
 use rust_embed::Embed;
-use sekai_injector::{Config, Domain, Manager, ServerStatistics, load_injection_maps, serve};
-use serde::Deserialize;
+use sekai_injector::{Config, Domain, Manager, ServerStatistics, serve};
 use simple_dns_server::{Config as DConfig, RecordInfo, RecordType, SimpleDns};
 use tokio::{
     sync::RwLock,
@@ -53,15 +49,22 @@ use tokio::{
 };
 use tracing_appender::rolling::{RollingFileAppender, Rotation};
 use tracing_subscriber::{EnvFilter, Layer, fmt, layer::SubscriberExt, util::SubscriberInitExt};
-use utils::Config as MMLConfig;
+use utils::{Config as MMLConfig, load_asset_version, load_versions};
 
 use crate::{
     assetbundle::{
         decrypt_aes_cbc, encrypt_aes_cbc, generate_logo, generate_screen_image,
         get_apimanager_keys, reload_assetbundle_info,
     },
-    mods::ModData,
-    scenario::{PY_CODE, create_assetbundle},
+    mods::{
+        find_conflicts, load_mods, migrate_mods, read_mod, rebuild_injection_hashmap,
+        reload_mod_files, remove_mod, resolve_active_mods, scan_mods, watch_mods, with_reload_lock,
+    },
+    pack::{pack_mod, unpack_mod},
+    scenario::{CustomStory, SCENARIO_TEMPLATE_PATH, create_assetbundle},
+    story::{export_story_mod, import_scenario, rebuild_story_mods},
+    unity::bundle::{UnityBundle, decrypt_bundle},
+    unitypy::UnityPyBundle,
 };
 
 #[derive(Debug, Options)]
@@ -77,20 +80,6 @@ struct CommandOptions {
     command: Option<Command>,
 }
 
-#[derive(Deserialize)]
-#[serde(rename_all = "camelCase")]
-#[allow(dead_code)] // All fields are needed to deserialize, but only two are actually read
-struct Versions {
-    app_hash: String,
-    system_profile: String,
-    app_version: String,
-    multi_play_version: String,
-    asset_version: String,
-    app_version_status: String,
-    data_version: String,
-    asset_hash: String,
-}
-
 #[derive(Debug, Options)]
 enum Command {
     #[options(help = "decrypt assetbundle")]
@@ -108,11 +97,40 @@ enum Command {
     #[options(help = "generate assetbundle from modpack")]
     GenAssetBundle(GenAssetBundle),
 
+    #[options(help = "check that an assetbundle is written back unchanged after being read")]
+    VerifyAssetBundle(VerifyAssetBundle),
+
     #[options(help = "generate screen_image and logo with specified images")]
     GenStoryImageBundles(GenStoryImageBundles),
 
     #[options(help = "reload assetbundle info cache to force asset reloading in game")]
     ReloadAbInfo(ReloadAbInfo),
+
+    #[options(help = "list resource paths injected by more than one enabled mod")]
+    ModConflicts(ModConflicts),
+
+    #[options(help = "check every mod in the mods directory for errors")]
+    ValidateMods(ValidateMods),
+
+    #[options(help = "remove a mod along with its AssetBundles and injections")]
+    RemoveMod(RemoveModOptions),
+
+    #[options(help = "rebuild every story mod's AssetBundles from its source story")]
+    RebuildMods(RebuildMods),
+
+    #[options(help = "compile a story file into a mod, optionally targeting another event")]
+    ExportStory(ExportStory),
+
+    #[options(
+        help = "convert a decrypted scenario assetbundle into a story for the custom story page"
+    )]
+    ImportScenario(ImportScenario),
+
+    #[options(help = "pack a mod and its AssetBundles into a single .mmlpack file")]
+    Pack(PackOptions),
+
+    #[options(help = "install a .mmlpack file into the mods directory")]
+    Unpack(UnpackOptions),
 }
 
 #[derive(Debug, Options)]
@@ -142,6 +160,39 @@ struct GenAssetBundle {
     output: PathBuf,
 }
 
+#[derive(Debug, Options)]
+struct VerifyAssetBundle {
+    #[options(help = "path to assetbundle, uses the scenario template if not set")]
+    assetbundle_path: Option<PathBuf>,
+}
+
+#[derive(Debug, Options)]
+struct ExportStory {
+    #[options(
+        help = "path to story json, as sent by the custom story page",
+        required
+    )]
+    story_path: PathBuf,
+
+    #[options(help = "event to replace the story of, such as event_whip_2024")]
+    event: Option<String>,
+
+    #[options(help = "scenario id of the episode to replace, such as event_129_01")]
+    scenario_id: Option<String>,
+}
+
+#[derive(Debug, Options)]
+struct ImportScenario {
+    #[options(help = "path to decrypted scenario assetbundle", required)]
+    scenario_path: PathBuf,
+
+    #[options(help = "name of the episode to import, uses the first one if not set")]
+    name: Option<String>,
+
+    #[options(help = "output file", required)]
+    output: PathBuf,
+}
+
 #[derive(Debug, Options)]
 struct GenStoryImageBundles {
     #[options(help = "path to story_bg to insert")]
@@ -172,6 +223,45 @@ struct GenStoryImageBundles {
 #[derive(Debug, Options)]
 struct ReloadAbInfo {}
 
+#[derive(Debug, Options)]
+struct ModConflicts {}
+
+#[derive(Debug, Options)]
+struct ValidateMods {}
+
+#[derive(Debug, Options)]
+struct RemoveModOptions {
+    #[options(
+        help = "file name (without .toml) or name of the mod to remove",
+        required
+    )]
+    name: String,
+}
+
+#[derive(Debug, Options)]
+struct RebuildMods {}
+
+#[derive(Debug, Options)]
+struct PackOptions {
+    #[options(help = "path to mod toml", required)]
+    mod_path: PathBuf,
+
+    #[options(help = "output .mmlpack file", required)]
+    output: PathBuf,
+
+    #[options(help = "optional source story JSON to include")]
+    story: Option<PathBuf>,
+
+    #[options(help = "optional image to include, may be repeated")]
+    image: Vec<PathBuf>,
+}
+
+#[derive(Debug, Options)]
+struct UnpackOptions {
+    #[options(help = ".mmlpack file to install", required)]
+    archive: PathBuf,
+}
+
 #[tokio::main]
 async fn main() {
     let opts = CommandOptions::parse_args_default_or_exit();
@@ -388,22 +478,27 @@ async fn main() {
             options.assetbundle_path.display()
         );
 
-        let modpack_data = fs::read_to_string(&options.assetbundle_path).unwrap_or_else(|_| {
-            panic!(
-                "Could not read {}! Please try redownloading mods and fixing permissions.",
-                options.assetbundle_path.display()
-            )
+        let mod_data = read_mod(&options.assetbundle_path).unwrap_or_else(|e| {
+            panic!("{e}! Check if MikuMikuLoader is out of date.");
         });
 
-        let mod_data: ModData = toml::from_str(&modpack_data).unwrap_or_else(|_| {
-            panic!(
-                "{} is not formatted properly! Check if MikuMikuLoader is out of date.",
-                options.assetbundle_path.display()
-            )
-        });
+        create_assetbundle(&mod_data, Some(options.output), true).unwrap();
 
-        create_assetbundle(mod_data, Some(options.output), true).unwrap();
+        return;
+    } else if let Some(Command::VerifyAssetBundle(options)) = opts.command {
+        let assetbundle_path = options
+            .assetbundle_path
+            .unwrap_or(PathBuf::from(SCENARIO_TEMPLATE_PATH));
+
+        if let Err(e) = unity::verify_round_trip(&assetbundle_path) {
+            error!("{e:#}");
+            std::process::exit(1);
+        }
 
+        info!(
+            "{} is written back byte for byte!",
+            assetbundle_path.display()
+        );
         return;
     } else if let Some(Command::GenStoryImageBundles(options)) = opts.command {
         // TODO: Something other than many unwraps, this is very messy
@@ -414,90 +509,92 @@ async fn main() {
                 error!("{} is not an directory!", extraction_path.display());
                 return;
             } else {
-                Python::attach(|py| {
-                    let filename = CString::new("story_to_assetbundle.py").unwrap();
-                    let modname = CString::new("story_to_assetbundle").unwrap();
-
-                    let module = PyModule::from_code(
-                        py,
-                        &CString::new(PY_CODE).unwrap(),
-                        &filename,
-                        &modname,
-                    )
-                    .unwrap();
-
-                    let assetbundle_array =
-                        if let Some(logo_assetbundle_path) = options.result_logo_assetbundle_path {
-                            [
-                                options.result_screen_image_assetbundle_path,
-                                Some(logo_assetbundle_path),
-                            ]
-                        } else {
-                            [options.result_screen_image_assetbundle_path, None]
-                        };
-
-                    // Contains all possible names for screen_image and episode_image, and just ignores missing names. TODO: At some point it should probably support more than just event_whip...
-                    for ref assetbundle_path in assetbundle_array.into_iter().flatten() {
-                        for img_name in [
-                            "story_bg",
-                            "banner_event_story",
-                            "story_title",
-                            "event_whip_2024_01",
-                            "event_whip_2024_02",
-                            "event_whip_2024_03",
-                            "event_whip_2024_04",
-                            "event_whip_2024_05",
-                            "event_whip_2024_06",
-                            "event_whip_2024_07",
-                            "event_whip_2024_08",
-                            "logo",
-                        ] {
-                            module
-                                .getattr("set_asset_path")
-                                .unwrap()
-                                .call1((&options
-                                    .screen_image_path
-                                    .clone()
-                                    .unwrap_or(assetbundle_path.to_string()),))
-                                .unwrap();
-
-                            match depythonize::<Vec<u8>>(
-                                &module
-                                    .getattr("return_texture2d_img")
-                                    .unwrap()
-                                    .call1((&img_name,))
-                                    .unwrap()
-                                    .extract()
-                                    .unwrap(),
-                            ) {
-                                Ok(loaded_image) => {
-                                    if !loaded_image.is_empty() {
-                                        let img = ImageReader::new(Cursor::new(loaded_image))
-                                            .with_guessed_format()
-                                            .unwrap()
-                                            .decode()
-                                            .unwrap();
-
-                                        let mut dest_path = extraction_path.clone();
-                                        dest_path.push(format!("{img_name}.png"));
-                                        img.save(&dest_path).unwrap();
+                let assetbundle_array =
+                    if let Some(logo_assetbundle_path) = options.result_logo_assetbundle_path {
+                        [
+                            options.result_screen_image_assetbundle_path,
+                            Some(logo_assetbundle_path),
+                        ]
+                    } else {
+                        [options.result_screen_image_assetbundle_path, None]
+                    };
+
+                // Contains all possible names for screen_image and episode_image, and just ignores missing names. TODO: At some point it should probably support more than just event_whip...
+                for ref assetbundle_path in assetbundle_array.into_iter().flatten() {
+                    let assetbundle_path = options
+                        .screen_image_path
+                        .clone()
+                        .unwrap_or(assetbundle_path.to_string());
+
+                    let bundle = match UnityBundle::open(Path::new(&assetbundle_path)) {
+                        Ok(bundle) => bundle,
+                        Err(e) => {
+                            error!("Could not open {assetbundle_path}! Err: {e:#}");
+                            continue;
+                        }
+                    };
+
+                    // Only opened once a texture can't be decoded natively
+                    let mut unitypy_bundle = None;
+
+                    for img_name in [
+                        "story_bg",
+                        "banner_event_story",
+                        "story_title",
+                        "event_whip_2024_01",
+                        "event_whip_2024_02",
+                        "event_whip_2024_03",
+                        "event_whip_2024_04",
+                        "event_whip_2024_05",
+                        "event_whip_2024_06",
+                        "event_whip_2024_07",
+                        "event_whip_2024_08",
+                        "logo",
+                    ] {
+                        let mut dest_path = extraction_path.clone();
+                        dest_path.push(format!("{img_name}.png"));
+
+                        match bundle.texture(img_name) {
+                            Ok(Some(img)) => {
+                                img.save(&dest_path).unwrap();
+
+                                info!("Saved image to {}", dest_path.display());
+                            }
+                            Ok(None) => debug!("No {img_name} in {assetbundle_path}, skipping"),
+                            Err(e) => {
+                                info!("{e:#}, falling back to UnityPy for {img_name}");
+
+                                let exported = unitypy_bundle
+                                    .get_or_insert_with(|| {
+                                        UnityPyBundle::open(Path::new(&assetbundle_path))
+                                    })
+                                    .as_ref()
+                                    .map_err(|e| anyhow::anyhow!("{e:#}"))
+                                    .and_then(|unitypy_bundle| {
+                                        unitypy_bundle.export_texture(img_name)
+                                    });
+
+                                match exported {
+                                    Ok(png) => {
+                                        fs::write(&dest_path, png).unwrap();
 
                                         info!("Saved image to {}", dest_path.display());
-                                    } else {
-                                        warn!("{img_name} returned no bytes! Skipping export.");
                                     }
+                                    Err(e) => warn!(
+                                        "Could not export {img_name}! Skipping export. Err: {e:#}"
+                                    ),
                                 }
-                                Err(_e) => {} // TODO: Match this error to ensure its an invalid type error
                             }
                         }
                     }
-                });
+                }
 
                 return;
             }
         }
 
-        // This will assume all paths passed are PNGs since this isn't really meant to be used by the user.
+        // Any format the image crate can read is accepted
+        let open_image = |path: Option<PathBuf>| path.map(|path| image::open(path).unwrap());
 
         if let Some(screen_image_path) = options.screen_image_path {
             info!("Generating screen_image!");
@@ -505,12 +602,16 @@ async fn main() {
                 .await
                 .unwrap();
 
+            let banner_event_story = open_image(options.banner_event_story);
+            let story_bg = open_image(options.story_bg);
+            let story_title = open_image(options.story_title);
+
             spawn_blocking(move || {
                 generate_screen_image(
-                    &screen_image_path,
-                    options.banner_event_story,
-                    options.story_bg,
-                    options.story_title,
+                    Path::new(&screen_image_path),
+                    banner_event_story,
+                    story_bg,
+                    story_title,
                 )
                 .unwrap();
             })
@@ -527,17 +628,175 @@ async fn main() {
                 .await
                 .unwrap();
 
+            let logo = open_image(options.logo_path).expect("New logo path must be set");
+
             spawn_blocking(move || {
-                generate_logo(
-                    logo_assetbundle_path,
-                    options.logo_path.expect("New logo path must be set"),
-                )
-                .unwrap();
+                generate_logo(Path::new(&logo_assetbundle_path), logo).unwrap();
             })
             .await
             .expect("generate_logo blocking task failed");
         }
 
+        return;
+    } else if let Some(Command::ModConflicts(_)) = opts.command {
+        let asset_version = load_asset_version(&config_holder);
+
+        let mods = load_mods();
+        let active = resolve_active_mods(&mods, &config_holder, &asset_version);
+        let conflicts = find_conflicts(&mods, &active);
+
+        if conflicts.is_empty() {
+            info!("No conflicting mods found!");
+        }
+
+        for conflict in conflicts {
+            warn!(
+                "{} is injected by multiple mods! {} overrides {}",
+                conflict.resource_path,
+                conflict.winner,
+                conflict.overridden.join(", ")
+            );
+        }
+
+        return;
+    } else if let Some(Command::ValidateMods(_)) = opts.command {
+        migrate_mods();
+        let (mods, errors) = scan_mods();
+
+        for (path, mod_data) in &mods {
+            info!("{} ({}) is valid", mod_data.mod_name, path.display());
+        }
+
+        if errors.is_empty() {
+            info!("All {} mods are valid!", mods.len());
+            return;
+        }
+
+        for e in &errors {
+            error!("{e}");
+        }
+
+        error!(
+            "{} of {} mods are broken",
+            errors.len(),
+            mods.len() + errors.len()
+        );
+        std::process::exit(1);
+    } else if let Some(Command::RemoveMod(options)) = opts.command {
+        let cloned_config_holder = config_holder.clone();
+        if let Err(e) =
+            with_reload_lock(move || remove_mod(&options.name, &cloned_config_holder)).await
+        {
+            error!("{e:#}");
+            std::process::exit(1);
+        }
+
+        let asset_version = load_asset_version(&config_holder);
+
+        // Restores the assetbundle info entries the mod invalidated
+        if let Err(e) = reload_mod_files(&config_holder, &asset_version).await {
+            error!("Removed the mod, but failed to reload mods! Err: {e:#}");
+            std::process::exit(1);
+        }
+
+        return;
+    } else if let Some(Command::RebuildMods(_)) = opts.command {
+        let asset_version = load_asset_version(&config_holder);
+
+        match rebuild_story_mods(&config_holder, &asset_version) {
+            Ok(rebuilt) => info!("Rebuilt {} mods", rebuilt.len()),
+            Err(e) => {
+                error!("{e:#}");
+                std::process::exit(1);
+            }
+        }
+
+        reload_mod_files(&config_holder, &asset_version)
+            .await
+            .unwrap();
+
+        return;
+    } else if let Some(Command::ExportStory(options)) = opts.command {
+        let mut story: CustomStory = match File::open(&options.story_path)
+            .map_err(anyhow::Error::from)
+            .and_then(|file| Ok(serde_json::from_reader(file)?))
+        {
+            Ok(story) => story,
+            Err(e) => {
+                error!("Could not read {}: {e:#}", options.story_path.display());
+                std::process::exit(1);
+            }
+        };
+
+        if let Some(event) = options.event {
+            story.target.event = event;
+        }
+        if options.scenario_id.is_some() {
+            story.target.scenario_id = options.scenario_id;
+        }
+
+        let asset_version = load_asset_version(&config_holder);
+
+        if let Err(e) = export_story_mod(&story, &config_holder, &asset_version) {
+            error!("{e:#}");
+            std::process::exit(1);
+        }
+
+        reload_mod_files(&config_holder, &asset_version)
+            .await
+            .unwrap();
+
+        return;
+    } else if let Some(Command::ImportScenario(options)) = opts.command {
+        match import_scenario(
+            &options.scenario_path,
+            options.name.as_deref(),
+            &options.output,
+        ) {
+            Ok(scenes) => info!("Imported {scenes} scenes into {}", options.output.display()),
+            Err(e) => {
+                error!("{e:#}");
+                std::process::exit(1);
+            }
+        }
+
+        return;
+    } else if let Some(Command::Pack(options)) = opts.command {
+        info!(
+            "Packing {} into {}",
+            options.mod_path.display(),
+            options.output.display()
+        );
+
+        let result = File::create(&options.output)
+            .map_err(anyhow::Error::from)
+            .and_then(|file| {
+                pack_mod(
+                    &options.mod_path,
+                    options.story.as_deref(),
+                    &options.image,
+                    file,
+                )
+            });
+
+        match result {
+            Ok(_) => info!("Output saved to {}", options.output.display()),
+            Err(e) => error!("Could not pack {}: {e}", options.mod_path.display()),
+        }
+
+        return;
+    } else if let Some(Command::Unpack(options)) = opts.command {
+        info!("Installing {}", options.archive.display());
+
+        let result = File::open(&options.archive)
+            .map_err(anyhow::Error::from)
+            .and_then(|file| unpack_mod(file, Path::new("mods")));
+
+        match result {
+            Ok(mod_path) => info!("Mod installed to {}", mod_path.display()),
+            Err(e) => error!("Could not install {}: {e}", options.archive.display()),
+        }
+
         return;
     }
 
@@ -647,11 +906,9 @@ async fn main() {
             "Reloading assetbundle info hashes! This may trigger multiple redownloads within the game."
         );
 
-        spawn_blocking(move || {
-            reload_assetbundle_info(&config_holder, &asset_version).unwrap();
-        })
-        .await
-        .expect("reload_assetbundle_info blocking task failed");
+        with_reload_lock(move || reload_assetbundle_info(&config_holder, &asset_version))
+            .await
+            .unwrap();
         return;
     }
 
@@ -666,14 +923,15 @@ async fn main() {
 
     let cloned_config_holder = config_holder.clone();
     let cloned_asset_version = asset_version.clone();
-    spawn_blocking(move || {
-        match reload_assetbundle_info(&cloned_config_holder, &cloned_asset_version) {
-        Ok(_) => {}
-        Err(e) => error!(
+    if let Err(e) = with_reload_lock(move || {
+        reload_assetbundle_info(&cloned_config_holder, &cloned_asset_version)
+    })
+    .await
+    {
+        error!(
             "Failed to reload assetbundle info. The game may not redownload required assets or start properly! Err: {e}"
-        ),
+        );
     }
-    }).await.expect("reload_assetbundle_info blocking task failed");
 
     let mut sekai_injector_enabled = true;
 
@@ -715,33 +973,6 @@ async fn main() {
 
     // We build the manager here so that it can be reused in other routes.
 
-    let mut injection_hashmap = load_injection_maps(&injector_config_holder);
-
-    debug!("injection_hashmap: {injection_hashmap:?}");
-
-    // For assetbundle info domain, set the resource path to contian the correct version and platform
-
-    let assetbundle_info_path = &format!(
-        "/api/version/{}/os/{}",
-        asset_version, config_holder.platform
-    );
-
-    if let Some(inner_map) = injection_hashmap.get_mut(&config_holder.advanced.assetbundle_info_url)
-        && let Some(entry) = inner_map.get_mut(assetbundle_info_path)
-    {
-        let asset_path = format!(
-            "{}{}",
-            config_holder.advanced.assets.asset_path, assetbundle_info_path
-        );
-
-        debug!("Setting asset_path to {asset_path}");
-        entry.0 = asset_path;
-    } else {
-        warn!("Could not assign correct asset path for assetbundle info request path!");
-    }
-
-    debug!("injection_hashmap: {injection_hashmap:?}");
-
     // Create a client to handle making HTTPS requests
     let https = HttpsConnectorBuilder::new()
         .with_webpki_roots()
@@ -752,15 +983,32 @@ async fn main() {
     let client = Client::builder(TokioExecutor::new()).build::<_, Body>(https);
 
     // Create manager containing our config and injection_hashmap and HTTPS client
-    let manager = Arc::new(RwLock::new(Manager {
-        injection_hashmap,
+    let mut manager = Manager {
+        injection_hashmap: Default::default(),
         config: injector_config_holder,
         client,
         statistics: ServerStatistics {
             request_count: (0, 0),
             requests: Vec::new(),
         },
-    }));
+    };
+
+    rebuild_injection_hashmap(&mut manager, &config_holder, &asset_version);
+
+    let manager = Arc::new(RwLock::new(manager));
+
+    // Pick up toggled, exported and hand edited mods without a restart
+    task::spawn(watch_mods(
+        Arc::clone(&manager),
+        config_holder.clone(),
+        asset_version.clone(),
+    ));
+
+    let web_state = WebState {
+        manager: Arc::clone(&manager),
+        config: config_holder,
+        asset_version,
+    };
 
     let static_routes = Router::new()
         .route_service("/", get(routes::index_handler))
@@ -780,12 +1028,20 @@ async fn main() {
         .route("/generate-cert", post(routes::gen_cert))
         .route(
             "/export-custom-story",
-            post(move |body| routes::export_story_to_modpack(config_holder, asset_version, body)),
+            post(routes::export_story_to_modpack),
         )
         .route("/local-ip", get(routes::return_local_ip))
         .route("/version", get(routes::return_version))
         .route("/mod-list", get(routes::mod_list))
-        .with_state(Arc::clone(&manager))
+        .route("/mod-conflicts", get(routes::mod_conflicts))
+        .route("/mod-dependencies", get(routes::mod_dependencies))
+        .route("/mod-errors", get(routes::mod_errors))
+        .route("/mods/{:param}", delete(routes::remove_mod))
+        .route("/export-mod/{:param}", get(routes::export_mod))
+        .route("/import-mod", post(routes::import_mod))
+        .route("/reload-mods", get(routes::reload_mods))
+        .route("/rebuild-mods", get(routes::rebuild_mods))
+        .with_state(web_state)
         .layer(DefaultBodyLimit::max(31457280)); // 30 MiB
 
     let webui_app = static_routes.merge(api_routes);
@@ -892,10 +1148,7 @@ pub fn update_injection_appversion(
     injector_config: &mut sekai_injector::Config,
     mml_config: &MMLConfig,
 ) -> Result<(String, String, String)> {
-    let versions_path = format!("{}/versions.json", mml_config.advanced.assets.asset_path);
-    let version_file = fs::File::open(versions_path)?;
-
-    let versions: Versions = serde_json::from_reader(version_file)?;
+    let versions = load_versions(mml_config)?;
 
     if let Some(assetbundle_domain) = injector_config
         .domains
@@ -1067,14 +1320,20 @@ pub async fn update_assets(
                 let mut file = std::fs::File::create(format!("{}/{asset}", asset_config.asset_path)).expect("Failed to write asset to file");
                 let mut content = Cursor::new(resp.bytes().await.unwrap());
                 std::io::copy(&mut content, &mut file).expect("Failed to write asset to file");
+
+                // Any upstream copy kept by reload_assetbundle_info is now stale
+                let upstream_path = format!("{}/{asset}.upstream", asset_config.asset_path);
+                if Path::new(&upstream_path).exists() && let Err(e) = fs::remove_file(&upstream_path) {
+                    error!("Failed to remove stale {upstream_path}! Old hashes may be served. Err: {e}");
+                }
             }
 
-            if etag_needs_update && new_etag_val.is_some() {
+            if etag_needs_update && let Some(new_etag_val) = new_etag_val {
                 // Update etag
                 debug!("Storing etag for {asset}");
 
                 match File::create(format!("{}/{asset}.etag", asset_config.asset_path)) {
-                    Ok(mut etag_file) => match write!(etag_file, "{}", new_etag_val.unwrap()) {
+                    Ok(mut etag_file) => match write!(etag_file, "{}", new_etag_val) {
                         Ok(_) => {}
                         Err(e) => {
                             error!(
@@ -1108,37 +1367,7 @@ pub async fn update_assets(
 }
 
 fn decrypt(infile: &Path, outfile: &Path) -> std::io::Result<()> {
-    // All credit for figuring out decryption goes to https://github.com/mos9527
-
-    let mut fin = File::open(infile)?;
-    let mut magic = [0u8; 4];
-    fin.read_exact(&mut magic)?;
-
-    let mut fout = File::create(outfile)?;
-
-    if magic == [0x10, 0x00, 0x00, 0x00] {
-        for _ in (0..128).step_by(8) {
-            let mut block = [0u8; 8];
-            fin.read_exact(&mut block)?;
-            (0..5).for_each(|i| {
-                block[i] = !block[i];
-            });
-            fout.write_all(&block)?;
-        }
-        let mut buffer = [0u8; 8];
-        while fin.read_exact(&mut buffer).is_ok() {
-            fout.write_all(&buffer)?;
-        }
-    } else {
-        println!("copy {infile:?} -> {outfile:?}");
-        let mut buffer = [0u8; 8];
-        fout.write_all(&magic)?; // Write already-read magic
-        while fin.read_exact(&mut buffer).is_ok() {
-            fout.write_all(&buffer)?;
-        }
-    }
-
-    Ok(())
+    fs::write(outfile, decrypt_bundle(fs::read(infile)?))
 }
 
 fn encrypt(infile: &Path, outfile: &Path) -> std::io::Result<()> {
@@ -1,20 +1,42 @@
 use std::{
-    collections::HashMap,
-    fs::{File, read_to_string},
+    cmp::Ordering,
+    collections::{BTreeMap, HashMap},
+    fs::{self, File, read_to_string},
     io::{Read, Write},
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::Duration,
 };
 
-use anyhow::{Context, Result};
-use log::{debug, error, warn};
-use sekai_injector::{Config as SIConfig, InjectionMap};
+use anyhow::{Context, Result, bail};
+use log::{debug, error, info, warn};
+use notify::{Event, RecursiveMode, Watcher};
+use sekai_injector::{Config as SIConfig, InjectionMap, Manager, load_injection_maps};
+use semver::{Version, VersionReq};
 use serde::{Deserialize, Serialize};
+use tokio::{
+    sync::{Mutex, RwLock, mpsc},
+    task::{block_in_place, spawn_blocking},
+    time::sleep,
+};
+use toml::{Table, Value};
 use walkdir::WalkDir;
 
-use crate::{scenario::Scenario, utils::Config};
+use crate::{assetbundle::reload_assetbundle_info, scenario::Scenario, utils::Config};
+
+/// Schema version written into newly saved mods. Bump this and add an entry to `MIGRATIONS` whenever `ModData` (or anything it contains) changes in a way old mod files can't be read as.
+pub const MOD_SCHEMA_VERSION: u32 = 2;
+
+/// `MIGRATIONS[n]` upgrades a mod table from schema version `n` to `n + 1`.
+const MIGRATIONS: &[fn(&mut Table) -> Result<()>] = &[migrate_v0_to_v1, migrate_v1_to_v2];
+
+/// Held while mods are being reloaded so the watcher and the web API don't write the same files at once
+static RELOAD_LOCK: Mutex<()> = Mutex::const_new(());
 
 #[derive(Debug, Deserialize, Serialize)]
 pub enum ModType {
-    Story(Scenario),
+    /// Every episode of the story, in order
+    Story(Vec<Scenario>),
 }
 
 impl ModType {
@@ -27,6 +49,9 @@ impl ModType {
 
 #[derive(Debug, Deserialize, Serialize)]
 pub struct ModData {
+    /// Version of the format this mod was saved in. Mods without one predate versioning, and are version 0.
+    #[serde(default)]
+    pub schema_version: u32,
     /// Name of the mod
     pub mod_name: String,
     /// Is the mod active? Set by user
@@ -37,6 +62,188 @@ pub struct ModData {
     pub invalidated_assets: Vec<InvalidateCacheEntry>,
     /// HashMap containing all assets to be injected. Key is resource path to override, value is path to local AssetBundle file.
     pub injected_assets: HashMap<String, String>,
+    /// Load order of the mod. When several enabled mods inject the same resource path, the highest priority wins. Ties are broken by mod file path.
+    #[serde(default)]
+    pub priority: i32,
+    /// Information about the mod and what it can be loaded on
+    #[serde(default)]
+    pub metadata: ModMetadata,
+    /// Other mods that must be active for this mod to be loaded
+    #[serde(default)]
+    pub dependencies: Vec<ModDependency>,
+    /// Path to the SEKAI-Stories JSON a story mod was built from, so it can be edited and rebuilt
+    pub source: Option<String>,
+}
+
+/// A mod that another mod needs in order to work, such as a shared background pack.
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct ModDependency {
+    /// `mod_name` of the required mod
+    pub mod_name: String,
+    /// Versions of the required mod that are accepted (ex. ">=1.2, <2"). Any version is accepted if unset.
+    pub version: Option<VersionReq>,
+}
+
+impl ModDependency {
+    /// Returns why this dependency is not satisfied by `mods`, if it isn't.
+    /// `is_loaded` decides whether the mod at the given index of `mods` counts as loaded.
+    pub fn problem(
+        &self,
+        mods: &[(PathBuf, ModData)],
+        is_loaded: impl Fn(usize) -> bool,
+    ) -> Option<String> {
+        let candidates: Vec<usize> = mods
+            .iter()
+            .enumerate()
+            .filter(|(_, (_, mod_data))| mod_data.mod_name == self.mod_name)
+            .map(|(i, _)| i)
+            .collect();
+
+        if candidates.is_empty() {
+            return Some(format!("{} is not installed", self.mod_name));
+        }
+
+        let loaded: Vec<usize> = candidates.into_iter().filter(|i| is_loaded(*i)).collect();
+
+        if loaded.is_empty() {
+            return Some(format!("{} is not enabled", self.mod_name));
+        }
+
+        let Some(version_req) = &self.version else {
+            return None;
+        };
+
+        if loaded.iter().any(|i| {
+            mods[*i]
+                .1
+                .metadata
+                .version
+                .as_ref()
+                .is_some_and(|version| version_req.matches(version))
+        }) {
+            return None;
+        }
+
+        let versions: Vec<String> = loaded
+            .iter()
+            .map(|i| match &mods[*i].1.metadata.version {
+                Some(version) => version.to_string(),
+                None => "an unknown version".to_string(),
+            })
+            .collect();
+
+        Some(format!(
+            "{} {version_req} is required, but {} is installed",
+            self.mod_name,
+            versions.join("/")
+        ))
+    }
+}
+
+/// Optional information describing a mod. Empty compatibility lists mean the mod works everywhere.
+#[derive(Debug, Default, Deserialize, Serialize)]
+pub struct ModMetadata {
+    /// Who made the mod
+    #[serde(default)]
+    pub author: String,
+    /// Version of the mod itself
+    pub version: Option<Version>,
+    #[serde(default)]
+    pub description: String,
+    /// Path to an image shown in the mod manager
+    pub thumbnail: Option<String>,
+    /// Server regions (en, jp, tw, kr, cn) the mod was built for
+    #[serde(default)]
+    pub regions: Vec<String>,
+    /// Platforms (android, ios) the mod was built for
+    #[serde(default)]
+    pub platforms: Vec<String>,
+    /// Oldest asset version from versions.json the mod works with
+    pub min_asset_version: Option<String>,
+}
+
+impl ModData {
+    /// Returns why this mod can't be loaded with the current config and asset version, if anything.
+    /// An empty `asset_version` means it is unknown, and is not checked against.
+    pub fn incompatibility(&self, config: &Config, asset_version: &str) -> Option<String> {
+        let metadata = &self.metadata;
+
+        if !metadata.regions.is_empty() && !metadata.regions.contains(&config.region) {
+            return Some(format!(
+                "it was built for the {} server, not {}",
+                metadata.regions.join("/"),
+                config.region
+            ));
+        }
+
+        if !metadata.platforms.is_empty() && !metadata.platforms.contains(&config.platform) {
+            return Some(format!(
+                "it was built for {}, not {}",
+                metadata.platforms.join("/"),
+                config.platform
+            ));
+        }
+
+        if let Some(min_asset_version) = &metadata.min_asset_version
+            && !asset_version.is_empty()
+            && compare_asset_versions(asset_version, min_asset_version) == Ordering::Less
+        {
+            return Some(format!(
+                "it requires asset version {min_asset_version} or newer, but the game is on {asset_version}"
+            ));
+        }
+
+        None
+    }
+
+    /// Whether the mod should be injected, warning about why if it is enabled but incompatible.
+    pub fn is_active(&self, config: &Config, asset_version: &str) -> bool {
+        if !self.enabled {
+            return false;
+        }
+
+        match self.incompatibility(config, asset_version) {
+            Some(reason) => {
+                warn!("Not loading {} because {reason}", self.mod_name);
+                false
+            }
+            None => true,
+        }
+    }
+}
+
+/// Compares dot separated asset versions (ex. 5.1.0.30) numerically, falling back to string comparison for non numeric parts.
+fn compare_asset_versions(a: &str, b: &str) -> Ordering {
+    let mut a_parts = a.split('.');
+    let mut b_parts = b.split('.');
+
+    loop {
+        match (a_parts.next(), b_parts.next()) {
+            (None, None) => return Ordering::Equal,
+            (Some(_), None) => return Ordering::Greater,
+            (None, Some(_)) => return Ordering::Less,
+            (Some(a_part), Some(b_part)) => {
+                let ordering = match (a_part.parse::<u64>(), b_part.parse::<u64>()) {
+                    (Ok(a_num), Ok(b_num)) => a_num.cmp(&b_num),
+                    _ => a_part.cmp(b_part),
+                };
+
+                if ordering != Ordering::Equal {
+                    return ordering;
+                }
+            }
+        }
+    }
+}
+
+/// An resource path that is injected by more than one enabled mod.
+#[derive(Debug, Serialize)]
+pub struct ModConflict {
+    pub resource_path: String,
+    /// Name of the mod whose asset is actually injected
+    pub winner: String,
+    /// Names of the mods whose asset for this path is ignored, in load order
+    pub overridden: Vec<String>,
 }
 
 #[derive(Debug, Deserialize, Serialize)]
@@ -53,8 +260,364 @@ pub struct InvalidateCacheEntry {
     pub duration: CacheInvalidDuration,
 }
 
-/// Walks through mod dir and updates injections-ab.toml with necessary paths
-pub fn reload_injections(config: &Config) -> Result<()> {
+/// A mod file that could not be loaded, and why.
+#[derive(Debug, Serialize)]
+pub struct ModLoadError {
+    pub path: String,
+    /// 1-based line of the error, if it is a TOML error that points somewhere in the file
+    pub line: Option<usize>,
+    /// 1-based column of the error, if it is a TOML error that points somewhere in the file
+    pub column: Option<usize>,
+    pub message: String,
+}
+
+impl std::fmt::Display for ModLoadError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match (self.line, self.column) {
+            (Some(line), Some(column)) => {
+                write!(f, "{}:{line}:{column}: {}", self.path, self.message)
+            }
+            _ => write!(f, "{}: {}", self.path, self.message),
+        }
+    }
+}
+
+impl std::error::Error for ModLoadError {}
+
+/// Reads and parses a single mod file.
+/// Mods saved in an older schema are only upgraded in memory, `migrate_mods` saves them upgraded.
+pub fn read_mod(path: &Path) -> Result<ModData, ModLoadError> {
+    parse_mod(path).map(|(mod_data, _)| mod_data)
+}
+
+/// Parses a mod file, also returning the schema version it was upgraded from if it was saved in an older one.
+fn parse_mod(path: &Path) -> Result<(ModData, Option<u32>), ModLoadError> {
+    let load_error = |line: Option<usize>, column: Option<usize>, message: String| ModLoadError {
+        path: path.display().to_string(),
+        line,
+        column,
+        message,
+    };
+
+    let contents = read_to_string(path)
+        .map_err(|e| load_error(None, None, format!("Could not read mod: {e}")))?;
+
+    let toml_error = |e: toml::de::Error| {
+        // toml only gives a byte range, so work out where that is for the user
+        let position = e.span().map(|span| {
+            let before = &contents[..span.start];
+            let line = before.matches('\n').count() + 1;
+            let column = before.len() - before.rfind('\n').map_or(0, |i| i + 1) + 1;
+            (line, column)
+        });
+
+        load_error(
+            position.map(|(line, _)| line),
+            position.map(|(_, column)| column),
+            e.message().to_string(),
+        )
+    };
+
+    let table: Table = toml::from_str(&contents).map_err(toml_error)?;
+
+    let schema_version =
+        schema_version_of(&table).map_err(|e| load_error(None, None, e.to_string()))?;
+
+    // Parsed from the original text when possible, so errors point at the right line
+    if schema_version == MOD_SCHEMA_VERSION {
+        return toml::from_str(&contents)
+            .map(|mod_data| (mod_data, None))
+            .map_err(toml_error);
+    }
+
+    let mod_data = upgrade_mod(table).map_err(|e| load_error(None, None, format!("{e:#}")))?;
+
+    Ok((mod_data, Some(schema_version)))
+}
+
+/// Saves every mod still in an older schema upgraded, keeping the original as `<file>.v<version>.bak`.
+/// Writes to the mods dir, so only called while holding `RELOAD_LOCK` or by validate-mods. Mods that can't be loaded are left to `scan_mods` to report.
+pub fn migrate_mods() {
+    for entry in WalkDir::new("mods")
+        .into_iter()
+        .filter_map(|entry| entry.ok())
+    {
+        let path = entry.path();
+
+        if !entry.file_type().is_file() || path.extension().and_then(|e| e.to_str()) != Some("toml")
+        {
+            continue;
+        }
+
+        let Ok((mod_data, Some(schema_version))) = parse_mod(path) else {
+            continue;
+        };
+
+        let backup_path = format!("{}.v{schema_version}.bak", path.display());
+        let save = || -> Result<()> {
+            fs::copy(path, &backup_path)
+                .with_context(|| format!("Could not back up to {backup_path}"))?;
+            fs::write(
+                path,
+                toml::to_string_pretty(&mod_data).context("Failed to serialize mod into TOML")?,
+            )?;
+            Ok(())
+        };
+
+        match save() {
+            Ok(_) => info!(
+                "Upgraded {} from schema version {schema_version} to {MOD_SCHEMA_VERSION}, the original was saved to {backup_path}",
+                path.display()
+            ),
+            // The upgraded mod still works for now, it just gets upgraded again next time
+            Err(e) => warn!("Could not save upgraded {}: {e:#}", path.display()),
+        }
+    }
+}
+
+/// Reads `schema_version` out of an unparsed mod, defaulting to 0 for mods that predate it.
+fn schema_version_of(table: &Table) -> Result<u32> {
+    match table.get("schema_version") {
+        Some(value) => value
+            .as_integer()
+            .and_then(|version| u32::try_from(version).ok())
+            .context("schema_version is not a valid version"),
+        None => Ok(0),
+    }
+}
+
+/// Runs every migration needed to bring an unparsed mod up to `MOD_SCHEMA_VERSION`, then parses it.
+pub fn upgrade_mod(mut table: Table) -> Result<ModData> {
+    let schema_version = schema_version_of(&table)?;
+
+    if schema_version > MOD_SCHEMA_VERSION {
+        bail!(
+            "Mod was saved with schema version {schema_version}, but this MikuMikuLoader only supports up to {MOD_SCHEMA_VERSION}. Please update MikuMikuLoader."
+        );
+    }
+
+    for version in schema_version..MOD_SCHEMA_VERSION {
+        debug!(
+            "Migrating mod from schema version {version} to {}",
+            version + 1
+        );
+
+        MIGRATIONS[version as usize](&mut table)
+            .with_context(|| format!("Failed to migrate mod from schema version {version}"))?;
+        table.insert(
+            "schema_version".to_string(),
+            Value::Integer((version + 1).into()),
+        );
+    }
+
+    Value::Table(table)
+        .try_into()
+        .context("Mod is not formatted properly after migrating")
+}
+
+/// Version 1 added load order, metadata and dependencies. They all have defaults, but are written out so upgraded mods show what can be set.
+fn migrate_v0_to_v1(table: &mut Table) -> Result<()> {
+    table.entry("priority").or_insert_with(|| Value::Integer(0));
+    table
+        .entry("dependencies")
+        .or_insert_with(|| Value::Array(Vec::new()));
+    table
+        .entry("metadata")
+        .or_insert_with(|| Value::Table(Table::new()));
+
+    Ok(())
+}
+
+/// Story mods went from a single scenario to a list of episodes.
+fn migrate_v1_to_v2(table: &mut Table) -> Result<()> {
+    if let Some(Value::Table(mod_type)) = table.get_mut("mod_type")
+        && let Some(scenario) = mod_type.remove("Story")
+    {
+        let episodes = match scenario {
+            Value::Array(episodes) => episodes,
+            scenario => vec![scenario],
+        };
+        mod_type.insert("Story".to_string(), Value::Array(episodes));
+    }
+
+    Ok(())
+}
+
+/// Walks through mod dir and returns every mod that could be loaded, sorted into load order, along with every mod that couldn't.
+/// Load order is ascending `priority`, then mod file path, so later mods take precedence.
+pub fn scan_mods() -> (Vec<(PathBuf, ModData)>, Vec<ModLoadError>) {
+    let mut mods = Vec::new();
+    let mut errors = Vec::new();
+
+    for entry in WalkDir::new("mods") {
+        match entry {
+            Ok(entry) => {
+                if entry.file_type().is_file()
+                    && entry.path().extension().and_then(|e| e.to_str()) == Some("toml")
+                {
+                    debug!("trying {}", entry.path().display());
+
+                    match read_mod(entry.path()) {
+                        Ok(mod_data) => mods.push((entry.into_path(), mod_data)),
+                        Err(e) => errors.push(e),
+                    }
+                }
+            }
+            Err(e) => errors.push(ModLoadError {
+                path: e
+                    .path()
+                    .map_or_else(|| "mods".to_string(), |path| path.display().to_string()),
+                line: None,
+                column: None,
+                message: format!("Couldn't open an mod: {e}"),
+            }),
+        }
+    }
+
+    mods.sort_by(|a, b| a.1.priority.cmp(&b.1.priority).then_with(|| a.0.cmp(&b.0)));
+
+    (mods, errors)
+}
+
+/// Like `scan_mods`, but just logs and skips mods that couldn't be loaded.
+pub fn load_mods() -> Vec<(PathBuf, ModData)> {
+    let (mods, errors) = scan_mods();
+
+    for e in errors {
+        error!("Skipping broken mod {e}");
+    }
+
+    mods
+}
+
+/// Decides which of `mods` should be injected, returning one entry per mod.
+/// A mod is active if it is enabled, compatible, and every mod it depends on is active too.
+pub fn resolve_active_mods(
+    mods: &[(PathBuf, ModData)],
+    config: &Config,
+    asset_version: &str,
+) -> Vec<bool> {
+    let mut active: Vec<bool> = mods
+        .iter()
+        .map(|(_, mod_data)| mod_data.is_active(config, asset_version))
+        .collect();
+
+    // Deactivating a mod can break mods depending on it, so keep going until nothing changes
+    loop {
+        let mut changed = false;
+
+        for (i, (_, mod_data)) in mods.iter().enumerate() {
+            if !active[i] {
+                continue;
+            }
+
+            if let Some(problem) = mod_data
+                .dependencies
+                .iter()
+                .find_map(|dependency| dependency.problem(mods, |j| active[j]))
+            {
+                warn!("Not loading {} because {problem}", mod_data.mod_name);
+                active[i] = false;
+                changed = true;
+            }
+        }
+
+        if !changed {
+            return active;
+        }
+    }
+}
+
+/// Returns every resource path injected by more than one active mod, and which mod wins it.
+/// Expects `mods` to be in load order, as returned by `load_mods`, and `active` as returned by `resolve_active_mods`.
+pub fn find_conflicts(mods: &[(PathBuf, ModData)], active: &[bool]) -> Vec<ModConflict> {
+    let mut targets: BTreeMap<&String, Vec<&String>> = BTreeMap::new();
+
+    for (_, mod_data) in mods
+        .iter()
+        .zip(active)
+        .filter(|(_, active)| **active)
+        .map(|(entry, _)| entry)
+    {
+        for resource_path in mod_data.injected_assets.keys() {
+            targets
+                .entry(resource_path)
+                .or_default()
+                .push(&mod_data.mod_name);
+        }
+    }
+
+    targets
+        .into_iter()
+        .filter(|(_, mod_names)| mod_names.len() > 1)
+        .map(|(resource_path, mut mod_names)| {
+            let winner = mod_names.pop().unwrap().to_owned();
+
+            ModConflict {
+                resource_path: resource_path.to_owned(),
+                winner,
+                overridden: mod_names.into_iter().cloned().collect(),
+            }
+        })
+        .collect()
+}
+
+/// Walks through mod dir and updates injections-ab.toml with necessary paths.
+/// Injections belonging to disabled or incompatible mods, or mods with unmet dependencies, are removed from the map.
+pub fn reload_injections(config: &Config, asset_version: &str) -> Result<()> {
+    let (resource_config_path, mut injection_map) = load_injection_map(config);
+
+    debug!("walking through mods");
+    let mods = load_mods();
+    let active = resolve_active_mods(&mods, config, asset_version);
+
+    for ((_, mod_data), active) in mods.iter().zip(&active) {
+        if !active {
+            // Purge anything this mod injected while it was still active
+            debug!(
+                "{} is not active, removing its injections",
+                mod_data.mod_name
+            );
+            injection_map.map.retain(|existing_injection| {
+                mod_data.injected_assets.get(&existing_injection.0) != Some(&existing_injection.1)
+            });
+            continue;
+        }
+
+        // Mods are in load order, so higher priority mods overwrite lower priority ones
+        for injection in &mod_data.injected_assets {
+            let new_injection = (injection.0.clone(), injection.1.clone(), true);
+
+            if let Some(i) = injection_map
+                .map
+                .iter()
+                .position(|existing_injection| &existing_injection.0 == injection.0)
+            {
+                debug!(
+                    "Existing injection for {} exists, replacing it",
+                    injection.0
+                );
+                injection_map.map[i] = new_injection;
+            } else {
+                injection_map.map.push(new_injection);
+            }
+        }
+    }
+
+    for conflict in find_conflicts(&mods, &active) {
+        warn!(
+            "{} is injected by multiple mods! Using {} over {}",
+            conflict.resource_path,
+            conflict.winner,
+            conflict.overridden.join(", ")
+        );
+    }
+
+    save_injection_map(&resource_config_path, &injection_map)
+}
+
+/// Reads injections-ab.toml, returning its path along with the map.
+fn load_injection_map(config: &Config) -> (String, InjectionMap) {
     debug!("extracting sekai_injector config");
     let sekai_injector_conf: SIConfig =
         match File::open(config.advanced.sekai_injector_config_path.clone()) {
@@ -79,7 +642,7 @@ pub fn reload_injections(config: &Config) -> Result<()> {
             .find(|domain| domain.address == "assetbundle.sekai-en.com").expect("No config for assetbundle.sekai-en.com found in Sekai Injector config! Please fix or redownload the config.").resource_config;
 
     debug!("building injection map");
-    let mut injection_map: InjectionMap = match File::open(&resource_config_path) {
+    let injection_map: InjectionMap = match File::open(&resource_config_path) {
         Ok(mut file) => {
             let mut injection_map_file_contents = String::new();
             file.read_to_string(&mut injection_map_file_contents).expect("The config file contains non UTF-8 characters, what in the world did you put in it??");
@@ -92,58 +655,15 @@ pub fn reload_injections(config: &Config) -> Result<()> {
         }
     };
 
-    debug!("walking through mods");
-    for entry in WalkDir::new("mods") {
-        match entry {
-            Ok(entry) => {
-                if entry.file_type().is_file()
-                    && entry.path().extension().and_then(|e| e.to_str()) == Some("toml")
-                {
-                    debug!("trying {}", entry.path().display());
-                    let entry_data = read_to_string(entry.path()).unwrap_or_else(|_| {
-                            panic!(
-                                "Could not read {}! Please try redownloading mods and fixing permissions.",
-                                entry.path().display()
-                            )
-                        });
-
-                    let mod_data: ModData = toml::from_str(&entry_data).unwrap_or_else(|_| {
-                        panic!(
-                            "{} is not formatted properly! Check if MikuMikuLoader is out of date.",
-                            entry.path().display()
-                        )
-                    });
-
-                    for injection in mod_data.injected_assets {
-                        let new_injection = (injection.0.clone(), injection.1, true);
-
-                        if let Some(i) = injection_map
-                            .map
-                            .iter()
-                            .position(|existing_injection| existing_injection.0 == injection.0)
-                        {
-                            warn!(
-                                "Existing injection for {} exists, replacing it",
-                                injection.0
-                            );
-                            injection_map.map[i] = new_injection;
-                        } else {
-                            injection_map.map.push(new_injection);
-                        }
-                    }
-                }
-            }
-            Err(e) => {
-                error!("Couldn't open an mod: {e}")
-            }
-        }
-    }
+    (resource_config_path, injection_map)
+}
 
+fn save_injection_map(resource_config_path: &str, injection_map: &InjectionMap) -> Result<()> {
     debug!("Saving injection map");
-    let injection_map_toml = toml::to_string_pretty(&injection_map)
+    let injection_map_toml = toml::to_string_pretty(injection_map)
         .context("Error converting injection_map into toml")?;
 
-    let mut resource_config_file = File::create(&resource_config_path)
+    let mut resource_config_file = File::create(resource_config_path)
         .context(format!("Error opening {resource_config_path}"))?;
     resource_config_file
         .write_all(injection_map_toml.as_bytes())
@@ -152,3 +672,211 @@ pub fn reload_injections(config: &Config) -> Result<()> {
 
     Ok(())
 }
+
+/// Deletes a mod along with every AssetBundle it injects that no other mod uses, and its source files.
+/// `name` is either the file name of the mod without .toml, or its `mod_name`.
+/// Its injections are purged from injections-ab.toml, but the assetbundle info still needs to be reloaded to restore what it invalidated.
+/// Writes injections-ab.toml, so it has to be run through `with_reload_lock`.
+pub fn remove_mod(name: &str, config: &Config) -> Result<String> {
+    let mut mods = load_mods();
+
+    let index = mods
+        .iter()
+        .position(|(path, _)| path.file_stem().and_then(|stem| stem.to_str()) == Some(name))
+        .or_else(|| {
+            mods.iter()
+                .position(|(_, mod_data)| mod_data.mod_name == name)
+        })
+        .with_context(|| format!("No mod named {name} found"))?;
+
+    let (mod_path, mod_data) = mods.remove(index);
+
+    for (_, other) in &mods {
+        if other
+            .dependencies
+            .iter()
+            .any(|dependency| dependency.mod_name == mod_data.mod_name)
+        {
+            warn!(
+                "{} depends on {}, and will no longer be loaded",
+                other.mod_name, mod_data.mod_name
+            );
+        }
+    }
+
+    let (resource_config_path, mut injection_map) = load_injection_map(config);
+    injection_map.map.retain(|existing_injection| {
+        mod_data.injected_assets.get(&existing_injection.0) != Some(&existing_injection.1)
+    });
+    save_injection_map(&resource_config_path, &injection_map)?;
+
+    // A shared AssetBundle may still be used by another mod
+    for local_path in mod_data.injected_assets.values() {
+        if mods.iter().any(|(_, other)| {
+            other
+                .injected_assets
+                .values()
+                .any(|path| path == local_path)
+        }) {
+            info!("Keeping {local_path}, as another mod uses it");
+            continue;
+        }
+
+        debug!("Removing {local_path}");
+        if let Err(e) = fs::remove_file(local_path) {
+            warn!("Could not remove {local_path}: {e}");
+        }
+    }
+
+    if let Some(source) = &mod_data.source
+        && let Err(e) = fs::remove_file(source)
+    {
+        warn!("Could not remove {source}: {e}");
+    }
+
+    // Source files and images extracted from a .mmlpack, unless other mods live in there too
+    let source_dir = mod_path.with_extension("");
+    if source_dir.is_dir()
+        && !WalkDir::new(&source_dir)
+            .into_iter()
+            .flatten()
+            .any(|entry| entry.path().extension().and_then(|e| e.to_str()) == Some("toml"))
+    {
+        debug!("Removing {}", source_dir.display());
+        fs::remove_dir_all(&source_dir)
+            .with_context(|| format!("Could not remove {}", source_dir.display()))?;
+    }
+
+    fs::remove_file(&mod_path)
+        .with_context(|| format!("Could not remove {}", mod_path.display()))?;
+
+    info!("Removed {} ({})", mod_data.mod_name, mod_path.display());
+
+    Ok(mod_data.mod_name)
+}
+
+/// Reads the injection maps from disk into `manager.injection_hashmap`,
+/// pointing the assetbundle info request at the local (modified) assetbundle info.
+pub fn rebuild_injection_hashmap(manager: &mut Manager, config: &Config, asset_version: &str) {
+    let mut injection_hashmap = load_injection_maps(&manager.config);
+    point_assetbundle_info_at_local(&mut injection_hashmap, config, asset_version);
+    debug!("injection_hashmap: {injection_hashmap:?}");
+
+    manager.injection_hashmap = injection_hashmap;
+}
+
+/// For assetbundle info domain, set the resource path to contian the correct version and platform
+fn point_assetbundle_info_at_local<T>(
+    injection_hashmap: &mut HashMap<String, HashMap<String, (String, T)>>,
+    config: &Config,
+    asset_version: &str,
+) {
+    let assetbundle_info_path = &format!("/api/version/{}/os/{}", asset_version, config.platform);
+
+    if let Some(inner_map) = injection_hashmap.get_mut(&config.advanced.assetbundle_info_url)
+        && let Some(entry) = inner_map.get_mut(assetbundle_info_path)
+    {
+        let asset_path = format!(
+            "{}{}",
+            config.advanced.assets.asset_path, assetbundle_info_path
+        );
+
+        debug!("Setting asset_path to {asset_path}");
+        entry.0 = asset_path;
+    } else {
+        warn!("Could not assign correct asset path for assetbundle info request path!");
+    }
+}
+
+/// Runs `reload` on a blocking thread while holding `RELOAD_LOCK`.
+/// Everything that writes injections-ab.toml or the assetbundle info goes through here.
+pub async fn with_reload_lock<T: Send + 'static>(
+    reload: impl FnOnce() -> Result<T> + Send + 'static,
+) -> Result<T> {
+    let _guard = RELOAD_LOCK.lock().await;
+
+    spawn_blocking(reload)
+        .await
+        .context("reload blocking task failed")?
+}
+
+/// Regenerates injections-ab.toml and the assetbundle info from the mod directory.
+pub async fn reload_mod_files(config: &Config, asset_version: &str) -> Result<()> {
+    let config = config.clone();
+    let asset_version = asset_version.to_string();
+
+    with_reload_lock(move || {
+        migrate_mods();
+        reload_injections(&config, &asset_version)?;
+        reload_assetbundle_info(&config, &asset_version)
+    })
+    .await
+}
+
+/// Regenerates injections-ab.toml and the assetbundle info from the mod directory,
+/// then swaps the new injection maps into the running manager.
+pub async fn hot_reload_mods(
+    manager: &Arc<RwLock<Manager>>,
+    config: &Config,
+    asset_version: &str,
+) -> Result<()> {
+    reload_mod_files(config, asset_version).await?;
+
+    // Built under a read lock so requests keep being served, then swapped in at once so they never see a partially updated map
+    let mut injection_hashmap = {
+        let manager = manager.read().await;
+        block_in_place(|| load_injection_maps(&manager.config))
+    };
+    point_assetbundle_info_at_local(&mut injection_hashmap, config, asset_version);
+    debug!("injection_hashmap: {injection_hashmap:?}");
+
+    manager.write().await.injection_hashmap = injection_hashmap;
+
+    info!("Reloaded mods!");
+    Ok(())
+}
+
+/// Watches the mods directory and hot reloads mods whenever a mod file is created, changed or removed.
+pub async fn watch_mods(manager: Arc<RwLock<Manager>>, config: Config, asset_version: String) {
+    let (tx, mut rx) = mpsc::unbounded_channel();
+
+    let event_handler = move |res: notify::Result<Event>| match res {
+        Ok(event) => {
+            if !event.kind.is_access()
+                && event
+                    .paths
+                    .iter()
+                    .any(|path| path.extension().and_then(|e| e.to_str()) == Some("toml"))
+            {
+                let _ = tx.send(());
+            }
+        }
+        Err(e) => error!("Mod watcher error: {e}"),
+    };
+
+    let mut watcher = match notify::recommended_watcher(event_handler) {
+        Ok(watcher) => watcher,
+        Err(e) => {
+            error!("Could not create mod watcher, mods will only reload on request! Err: {e}");
+            return;
+        }
+    };
+
+    if let Err(e) = watcher.watch(Path::new("mods"), RecursiveMode::Recursive) {
+        error!("Could not watch mods directory, mods will only reload on request! Err: {e}");
+        return;
+    }
+
+    info!("Watching mods directory for changes");
+
+    while rx.recv().await.is_some() {
+        // Give whatever is writing the mod a moment to finish, and collapse the burst of events into one reload
+        sleep(Duration::from_millis(500)).await;
+        while rx.try_recv().is_ok() {}
+
+        info!("Mod change detected, reloading mods");
+        if let Err(e) = hot_reload_mods(&manager, &config, &asset_version).await {
+            error!("Failed to reload mods! Err: {e}");
+        }
+    }
+}
@@ -0,0 +1,378 @@
+use std::{
+    collections::HashMap,
+    ffi::OsStr,
+    fs::{self, File, create_dir_all},
+    io::{self, Read, Seek, Write},
+    path::{Path, PathBuf},
+};
+
+use anyhow::{Context, Result, anyhow, bail};
+use log::{debug, info};
+use zip::{ZipArchive, ZipWriter, write::SimpleFileOptions};
+
+use crate::mods::{ModData, read_mod, upgrade_mod};
+
+/// File extension used for packaged mods.
+pub const MMLPACK_EXTENSION: &str = "mmlpack";
+
+/// ModData of the packaged mod, with `injected_assets` pointing into `BUNDLE_DIR`.
+const MANIFEST_NAME: &str = "manifest.toml";
+/// Every AssetBundle referenced by `injected_assets`.
+const BUNDLE_DIR: &str = "bundles";
+/// Optional source story JSON the mod was built from.
+const SOURCE_DIR: &str = "source";
+/// Optional images the mod was built from.
+const IMAGE_DIR: &str = "images";
+
+/// Packs the mod at `mod_path` and every AssetBundle it injects into a single .mmlpack archive written to `writer`.
+/// Optionally includes the source story JSON and images used to build it, so the mod can be edited by whoever imports it.
+/// The source story the mod keeps track of is included if `story` isn't set.
+pub fn pack_mod<W: Write + Seek>(
+    mod_path: &Path,
+    story: Option<&Path>,
+    images: &[PathBuf],
+    writer: W,
+) -> Result<W> {
+    let mut mod_data = read_mod(mod_path)?;
+
+    let mut zip = ZipWriter::new(writer);
+    let options = SimpleFileOptions::default();
+
+    // Local AssetBundle path -> name inside the archive, as multiple resource paths may share one AssetBundle
+    let mut packed_bundles: HashMap<String, String> = HashMap::new();
+
+    for local_path in mod_data.injected_assets.values_mut() {
+        if let Some(archive_path) = packed_bundles.get(local_path.as_str()) {
+            *local_path = archive_path.clone();
+            continue;
+        }
+
+        let file_name = file_name_of(Path::new(local_path))?;
+        let archive_path = format!("{BUNDLE_DIR}/{file_name}");
+
+        if packed_bundles
+            .values()
+            .any(|packed| packed == &archive_path)
+        {
+            bail!(
+                "Multiple AssetBundles named {file_name} are referenced, rename one of them before packing"
+            );
+        }
+
+        debug!("Packing {local_path} as {archive_path}");
+        zip.start_file(&archive_path, options)?;
+        io::copy(
+            &mut File::open(&local_path)
+                .with_context(|| format!("Could not read AssetBundle {local_path}"))?,
+            &mut zip,
+        )?;
+
+        packed_bundles.insert(local_path.clone(), archive_path.clone());
+        *local_path = archive_path;
+    }
+
+    let story = story
+        .map(Path::to_path_buf)
+        .or_else(|| mod_data.source.as_ref().map(PathBuf::from));
+
+    // Only points at something inside the archive, or nothing
+    mod_data.source = None;
+
+    if let Some(story) = story {
+        let archive_path = format!("{SOURCE_DIR}/{}", file_name_of(&story)?);
+
+        debug!("Packing {} as {archive_path}", story.display());
+        zip.start_file(&archive_path, options)?;
+        io::copy(
+            &mut File::open(&story)
+                .with_context(|| format!("Could not read {}", story.display()))?,
+            &mut zip,
+        )?;
+
+        mod_data.source = Some(archive_path);
+    }
+
+    for image in images {
+        let archive_path = format!("{IMAGE_DIR}/{}", file_name_of(image)?);
+
+        debug!("Packing {} as {archive_path}", image.display());
+        zip.start_file(archive_path, options)?;
+        io::copy(
+            &mut File::open(image)
+                .with_context(|| format!("Could not read {}", image.display()))?,
+            &mut zip,
+        )?;
+    }
+
+    zip.start_file(MANIFEST_NAME, options)?;
+    zip.write_all(
+        toml::to_string_pretty(&mod_data)
+            .context("Failed to serialize manifest into TOML")?
+            .as_bytes(),
+    )?;
+
+    Ok(zip.finish()?)
+}
+
+/// Installs a .mmlpack archive into `mods_dir`, returning the path of the newly created mod file.
+/// AssetBundles are extracted next to the mod file, while source files and images go into `mods_dir/<mod>/`.
+/// Refuses to overwrite anything that already exists.
+/// Everything is extracted into a staging directory first and only moved into place once the whole archive was read, so a failed import leaves nothing behind.
+pub fn unpack_mod<R: Read + Seek>(reader: R, mods_dir: &Path) -> Result<PathBuf> {
+    let mut zip = ZipArchive::new(reader).context("Not a valid mod archive")?;
+
+    let mut mod_data: ModData = {
+        let mut manifest = String::new();
+        zip.by_name(MANIFEST_NAME)
+            .context("Mod archive contains no manifest")?
+            .read_to_string(&mut manifest)?;
+
+        // Archives made by older versions are upgraded, and installed in the current schema
+        upgrade_mod(toml::from_str(&manifest).context("Mod archive manifest is not valid TOML")?)
+            .context("Could not read mod archive manifest")?
+    };
+
+    let mod_stem: String = mod_data
+        .mod_name
+        .chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() || c == '-' || c == '_' {
+                c
+            } else {
+                '_'
+            }
+        })
+        .collect();
+
+    let mod_path = mods_dir.join(format!("{mod_stem}.toml"));
+
+    if mod_path.exists() {
+        bail!(
+            "{} already exists! Remove it before importing this mod.",
+            mod_path.display()
+        );
+    }
+
+    create_dir_all(mods_dir)?;
+
+    // Kept inside mods_dir so extracted files can be renamed into place
+    let staging = tempfile::Builder::new()
+        .prefix(".mmlpack-")
+        .tempdir_in(mods_dir)
+        .context("Could not create a staging directory for the mod")?;
+
+    // Staged file -> where it is installed
+    let mut staged_files: Vec<(PathBuf, PathBuf)> = Vec::new();
+
+    // Extracts `file` into the staging directory, to be installed at `destination`
+    let mut stage = |file: &mut dyn Read, destination: PathBuf| -> Result<()> {
+        if destination.exists()
+            || staged_files
+                .iter()
+                .any(|(_, staged)| staged == &destination)
+        {
+            bail!(
+                "{} already exists! Remove it before importing this mod.",
+                destination.display()
+            );
+        }
+
+        let staged = staging.path().join(staged_files.len().to_string());
+        io::copy(file, &mut File::create(&staged)?)?;
+        staged_files.push((staged, destination));
+
+        Ok(())
+    };
+
+    // Archive bundle name -> extracted path
+    let mut extracted_bundles: HashMap<String, String> = HashMap::new();
+
+    for local_path in mod_data.injected_assets.values_mut() {
+        if let Some(extracted) = extracted_bundles.get(local_path.as_str()) {
+            *local_path = extracted.clone();
+            continue;
+        }
+
+        let file_name = local_path
+            .strip_prefix(&format!("{BUNDLE_DIR}/"))
+            .filter(|name| Path::new(name).file_name() == Some(OsStr::new(name)))
+            .ok_or_else(|| {
+                anyhow!("Manifest references {local_path}, which is not a packed AssetBundle")
+            })?;
+
+        let destination = mods_dir.join(file_name);
+
+        debug!("Extracting {local_path} to {}", destination.display());
+        stage(
+            &mut zip
+                .by_name(local_path)
+                .with_context(|| format!("Mod archive is missing {local_path}"))?,
+            destination.clone(),
+        )?;
+
+        let destination = destination.display().to_string();
+        extracted_bundles.insert(local_path.clone(), destination.clone());
+        *local_path = destination;
+    }
+
+    let archive_source = mod_data.source.take();
+
+    // Source story and images aren't needed to inject the mod, so keep them out of the way
+    for i in 0..zip.len() {
+        let mut file = zip.by_index(i)?;
+
+        let Some(name) = file.enclosed_name() else {
+            continue;
+        };
+
+        if file.is_dir() || !(name.starts_with(SOURCE_DIR) || name.starts_with(IMAGE_DIR)) {
+            continue;
+        }
+
+        let destination = mods_dir.join(&mod_stem).join(&name);
+
+        if archive_source.as_deref() == Some(file.name()) {
+            mod_data.source = Some(destination.display().to_string());
+        }
+
+        debug!("Extracting {} to {}", name.display(), destination.display());
+        stage(&mut file, destination)?;
+    }
+
+    let manifest =
+        toml::to_string_pretty(&mod_data).context("Failed to serialize modpack into TOML")?;
+
+    // Every file was read, so move them into place. Should that still fail, whatever was already moved is removed again.
+    let mut installed: Vec<&Path> = Vec::new();
+    let install = (|| -> Result<()> {
+        for (staged, destination) in &staged_files {
+            if let Some(parent) = destination.parent() {
+                create_dir_all(parent)?;
+            }
+
+            fs::rename(staged, destination)
+                .with_context(|| format!("Could not install {}", destination.display()))?;
+            installed.push(destination);
+        }
+
+        // Written last, so a failed import never leaves behind a mod pointing at missing AssetBundles
+        fs::write(&mod_path, manifest)?;
+
+        Ok(())
+    })();
+
+    if let Err(e) = install {
+        for path in installed {
+            let _ = fs::remove_file(path);
+        }
+
+        return Err(e);
+    }
+
+    info!("Imported {} into {}", mod_data.mod_name, mod_path.display());
+
+    Ok(mod_path)
+}
+
+fn file_name_of(path: &Path) -> Result<String> {
+    path.file_name()
+        .and_then(|name| name.to_str())
+        .map(|name| name.to_owned())
+        .ok_or_else(|| anyhow!("{} is not a valid file path", path.display()))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use super::*;
+
+    /// Writes a mod injecting two resource paths from one AssetBundle into `dir`, returning its path.
+    fn write_test_mod(dir: &Path, mod_name: &str) -> PathBuf {
+        let bundle = dir.join("test-story.ab");
+        fs::write(&bundle, b"UnityFS test bundle").unwrap();
+
+        let mod_path = dir.join(format!("{mod_name}.toml"));
+        fs::write(
+            &mod_path,
+            format!(
+                r#"schema_version = {}
+mod_name = "{mod_name}"
+enabled = true
+mod_type = {{ Story = [] }}
+invalidated_assets = []
+
+[injected_assets]
+"event_story/event_test/scenario" = "{bundle}"
+"event_story/event_test/scenario_copy" = "{bundle}"
+"#,
+                crate::mods::MOD_SCHEMA_VERSION,
+                bundle = bundle.display(),
+            ),
+        )
+        .unwrap();
+
+        mod_path
+    }
+
+    #[test]
+    fn pack_then_unpack_round_trips() {
+        let source_dir = tempfile::tempdir().unwrap();
+        let mods_dir = tempfile::tempdir().unwrap();
+
+        let mod_path = write_test_mod(source_dir.path(), "Test Story");
+        let story = source_dir.path().join("story.json");
+        fs::write(&story, b"{}").unwrap();
+
+        let archive = pack_mod(&mod_path, Some(&story), &[], Cursor::new(Vec::new())).unwrap();
+        let installed_path =
+            unpack_mod(Cursor::new(archive.into_inner()), mods_dir.path()).unwrap();
+
+        assert_eq!(installed_path, mods_dir.path().join("Test_Story.toml"));
+
+        let installed = read_mod(&installed_path).unwrap();
+        assert_eq!(installed.mod_name, "Test Story");
+
+        let bundle = mods_dir.path().join("test-story.ab").display().to_string();
+        assert_eq!(installed.injected_assets.len(), 2);
+        assert!(
+            installed
+                .injected_assets
+                .values()
+                .all(|path| path == &bundle)
+        );
+        assert_eq!(fs::read(&bundle).unwrap(), b"UnityFS test bundle");
+
+        let source = mods_dir.path().join("Test_Story/source/story.json");
+        assert_eq!(installed.source, Some(source.display().to_string()));
+        assert_eq!(fs::read(source).unwrap(), b"{}");
+
+        // The staging directory is gone once the mod is installed
+        let mut entries: Vec<_> = fs::read_dir(mods_dir.path())
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name())
+            .collect();
+        entries.sort();
+        assert_eq!(entries, ["Test_Story", "Test_Story.toml", "test-story.ab"]);
+    }
+
+    #[test]
+    fn failed_unpack_leaves_nothing_behind() {
+        let source_dir = tempfile::tempdir().unwrap();
+        let mods_dir = tempfile::tempdir().unwrap();
+
+        let mod_path = write_test_mod(source_dir.path(), "Test Story");
+        let archive = pack_mod(&mod_path, None, &[], Cursor::new(Vec::new())).unwrap();
+
+        // Drop the AssetBundle from the archive, so extraction fails after the manifest was read
+        let mut broken = ZipWriter::new(Cursor::new(Vec::new()));
+        let mut zip = ZipArchive::new(archive).unwrap();
+        broken
+            .raw_copy_file(zip.by_name(MANIFEST_NAME).unwrap())
+            .unwrap();
+        let broken = broken.finish().unwrap();
+
+        assert!(unpack_mod(Cursor::new(broken.into_inner()), mods_dir.path()).is_err());
+        assert_eq!(fs::read_dir(mods_dir.path()).unwrap().count(), 0);
+    }
+}
@@ -1,46 +1,47 @@
-use std::{
-    collections::HashMap,
-    env,
-    fs::{self, File, create_dir},
-    io::Cursor,
-    path::{Path as fPath, Path as FPath},
-    sync::Arc,
-};
+use std::{env, io::Cursor, path::Path as fPath, sync::Arc};
 
-use anyhow::Result;
 use axum::{
     Json,
-    extract::{Path, State},
-    http::Uri,
-    response::IntoResponse,
-};
-use base64::prelude::*;
-use image::{
-    DynamicImage, ImageEncoder,
-    codecs::png::{CompressionType, FilterType, PngEncoder},
-    load_from_memory,
+    body::Bytes,
+    extract::{FromRef, Path, State},
+    http::{StatusCode, Uri, header},
+    response::{IntoResponse, Response},
 };
 use local_ip_address::local_ip;
-use log::{debug, error, info};
+use log::{debug, error, info, warn};
 use sekai_injector::{
     CertificateGenParams, Manager, RequestParams, generate_ca, new_self_signed_cert,
 };
-use serde::Deserialize;
-use tokio::{
-    sync::RwLock,
-    task::{self, spawn_blocking},
-};
-use walkdir::WalkDir;
+use serde::{Deserialize, Serialize};
+use tokio::{sync::RwLock, task::spawn_blocking};
 
 use crate::{
     StaticFile,
-    assetbundle::{generate_logo, generate_screen_image, reload_assetbundle_info},
-    encrypt,
-    mods::{CacheInvalidDuration, InvalidateCacheEntry, ModData, ModType, reload_injections},
-    scenario::{CustomStory, SCENARIO_PATH_ID, create_assetbundle, load_scenario_typetree},
+    mods::{
+        self, ModConflict, ModLoadError, ModMetadata, find_conflicts, hot_reload_mods, load_mods,
+        read_mod, reload_mod_files, resolve_active_mods, scan_mods, with_reload_lock,
+    },
+    pack::{MMLPACK_EXTENSION, pack_mod, unpack_mod},
+    scenario::CustomStory,
+    story::{export_story_mod, rebuild_story_mods},
     utils::{self},
 };
 
+/// Everything the web UI routes share.
+#[derive(Clone)]
+pub struct WebState {
+    pub manager: Arc<RwLock<Manager>>,
+    pub config: utils::Config,
+    /// Mods are checked against this, empty if versions.json couldn't be read
+    pub asset_version: String,
+}
+
+impl FromRef<WebState> for Arc<RwLock<Manager>> {
+    fn from_ref(state: &WebState) -> Self {
+        Arc::clone(&state.manager)
+    }
+}
+
 #[derive(Debug, Deserialize)]
 pub struct CertGenOptions {
     pub hostname: String,
@@ -52,6 +53,36 @@ pub struct CertGenOptions {
     pub cert_key_name: String,
 }
 
+#[derive(Debug, Serialize)]
+pub struct ModListEntry {
+    pub path: String,
+    pub mod_name: String,
+    pub mod_type: String,
+    pub enabled: bool,
+    pub priority: i32,
+    /// Why the mod won't be loaded even when enabled, if anything
+    pub incompatibility: Option<String>,
+    pub metadata: ModMetadata,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ModDependencyNode {
+    pub path: String,
+    pub mod_name: String,
+    /// Whether the mod is actually injected, after resolving dependencies
+    pub active: bool,
+    pub dependencies: Vec<ModDependencyEdge>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ModDependencyEdge {
+    pub mod_name: String,
+    /// Accepted versions of the dependency, if restricted
+    pub version: Option<String>,
+    /// Why the dependency isn't satisfied, if it isn't
+    pub problem: Option<String>,
+}
+
 #[derive(Debug, Deserialize)]
 pub struct CAGenOptions {
     pub ca_name: String,
@@ -96,28 +127,66 @@ pub async fn set_serve_param(
     "Success".to_string()
 }
 
-pub async fn toggle_mod(Path(param): Path<String>) -> impl IntoResponse {
-    // Reads current mod status and just flips it. Server restart re-reads all mods in folder, so this should be fine.
+pub async fn toggle_mod(
+    State(WebState {
+        config,
+        asset_version,
+        ..
+    }): State<WebState>,
+    Path(param): Path<String>,
+) -> impl IntoResponse {
+    // Reads current mod status and just flips it. The mod watcher picks up the change and reloads all mods, so this should be fine.
     let mod_path = fPath::new(&param);
 
     info!("Toggle {} requested by web", mod_path.display());
 
     if mod_path.exists() {
-        let mod_data = fs::read_to_string(mod_path).unwrap_or_else(|_| {
-            panic!(
-                "Could not read {}! Please try redownloading mods and fixing permissions.",
-                mod_path.display()
-            )
-        });
-
-        let mut mod_data: ModData = toml::from_str(&mod_data).unwrap_or_else(|_| {
-            panic!(
-                "{} is not formatted properly! Check if MikuMikuLoader is out of date.",
-                mod_path.display()
-            )
-        });
-
-        mod_data.enabled = !mod_data.enabled;
+        let mut mod_data = match read_mod(mod_path) {
+            Ok(mod_data) => mod_data,
+            Err(e) => {
+                let msg = format!("Can't toggle broken mod {e}");
+                error!("{msg}");
+                return (StatusCode::BAD_REQUEST, msg);
+            }
+        };
+
+        if mod_data.enabled {
+            mod_data.enabled = false;
+        } else {
+            // Refuse to enable a mod that would just be skipped, deciding it the same way reloading does
+            let mut mods = load_mods();
+            mods.retain(|(path, _)| !is_same_file(path, mod_path));
+
+            mod_data.enabled = true;
+            mods.push((mod_path.to_path_buf(), mod_data));
+
+            let active = resolve_active_mods(&mods, &config, &asset_version);
+            let (_, candidate) = mods.last().unwrap();
+
+            if !active[mods.len() - 1] {
+                let problems: Vec<String> = candidate
+                    .incompatibility(&config, &asset_version)
+                    .into_iter()
+                    .chain(
+                        candidate
+                            .dependencies
+                            .iter()
+                            .filter_map(|dependency| dependency.problem(&mods, |j| active[j])),
+                    )
+                    .collect();
+
+                let msg = format!(
+                    "Can't enable {} because {}",
+                    candidate.mod_name,
+                    problems.join(", ")
+                );
+                warn!("{msg}");
+                return (StatusCode::CONFLICT, msg);
+            }
+
+            // Now enabled
+            (_, mod_data) = mods.pop().unwrap();
+        }
 
         match toml::to_string_pretty(&mod_data) {
             Ok(toml) => match std::fs::write(mod_path, toml) {
@@ -126,61 +195,235 @@ pub async fn toggle_mod(Path(param): Path<String>) -> impl IntoResponse {
                         "Succesfully toggled {}",
                         mod_path.canonicalize().unwrap().display()
                     );
-                    format!("Toggled {}", mod_path.canonicalize().unwrap().display())
+                    (
+                        StatusCode::OK,
+                        format!("Toggled {}", mod_path.canonicalize().unwrap().display()),
+                    )
                 }
-                Err(e) => format!("Failed to write to {:?}: {e}", mod_path.canonicalize()),
+                Err(e) => (
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                    format!("Failed to write to {:?}: {e}", mod_path.canonicalize()),
+                ),
             },
-            Err(e) => format!("Failed to serialize modpack into TOML: {e}"),
+            Err(e) => (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                format!("Failed to serialize modpack into TOML: {e}"),
+            ),
         }
     } else {
-        format!("Couldn't find {} to toggle!", mod_path.display())
+        (
+            StatusCode::NOT_FOUND,
+            format!("Couldn't find {} to toggle!", mod_path.display()),
+        )
+    }
+}
+
+fn is_same_file(a: &fPath, b: &fPath) -> bool {
+    match (a.canonicalize(), b.canonicalize()) {
+        (Ok(a), Ok(b)) => a == b,
+        _ => a == b,
+    }
+}
+
+pub async fn reload_mods(
+    State(WebState {
+        manager,
+        config,
+        asset_version,
+    }): State<WebState>,
+) -> impl IntoResponse {
+    info!("Mod reload requested by web");
+
+    match hot_reload_mods(&manager, &config, &asset_version).await {
+        Ok(_) => "Reloaded mods".to_string(),
+        Err(e) => {
+            let msg = format!("Failed to reload mods! Err: {e}");
+            error!("{msg}");
+            msg
+        }
+    }
+}
+
+pub async fn remove_mod(
+    State(WebState {
+        manager,
+        config,
+        asset_version,
+    }): State<WebState>,
+    Path(param): Path<String>,
+) -> impl IntoResponse {
+    info!("Removal of {param} requested by web");
+
+    let cloned_config = config.clone();
+    let removed = match with_reload_lock(move || mods::remove_mod(&param, &cloned_config)).await {
+        Ok(removed) => removed,
+        Err(e) => {
+            let msg = format!("Failed to remove mod! Err: {e:#}");
+            error!("{msg}");
+            return (StatusCode::BAD_REQUEST, msg);
+        }
+    };
+
+    // Restores the assetbundle info entries the mod invalidated
+    match hot_reload_mods(&manager, &config, &asset_version).await {
+        Ok(_) => (StatusCode::OK, format!("Removed {removed}")),
+        Err(e) => {
+            let msg = format!("Removed {removed}, but failed to reload mods! Err: {e}");
+            error!("{msg}");
+            (StatusCode::INTERNAL_SERVER_ERROR, msg)
+        }
     }
 }
 
-pub async fn mod_list() -> impl IntoResponse {
+pub async fn mod_list(
+    State(WebState {
+        config,
+        asset_version,
+        ..
+    }): State<WebState>,
+) -> Json<Vec<ModListEntry>> {
     debug!("mod list requested by web");
-    let mod_path = fPath::new("mods");
 
-    let mut list: Vec<(String, String, String, bool)> = Vec::new(); // Path, name, type, enabled
+    let list: Vec<ModListEntry> = spawn_blocking(move || {
+        load_mods()
+            .into_iter()
+            .map(|(path, mod_data)| ModListEntry {
+                path: path.display().to_string(),
+                mod_type: mod_data.mod_type.variant_name().to_owned(),
+                enabled: mod_data.enabled,
+                priority: mod_data.priority,
+                incompatibility: mod_data.incompatibility(&config, &asset_version),
+                mod_name: mod_data.mod_name,
+                metadata: mod_data.metadata,
+            })
+            .collect()
+    })
+    .await
+    .expect("mod list blocking task failed");
 
-    if !mod_path.exists() {
-        Json(list)
-    } else {
-        for entry in WalkDir::new(mod_path) {
-            match entry {
-                Ok(entry) => {
-                    if entry.file_type().is_file()
-                        && entry.path().extension().and_then(|e| e.to_str()) == Some("toml")
-                    {
-                        let entry_data = fs::read_to_string(entry.path()).unwrap_or_else(|_| {
-                            panic!(
-                                "Could not read {}! Please try redownloading mods and fixing permissions.",
-                                entry.path().display()
-                            )
-                        });
-
-                        let mod_data: ModData = toml::from_str(&entry_data).unwrap_or_else(|_| {
-                            panic!(
-                                "{} is not formatted properly! Check if MikuMikuLoader is out of date.",
-                                entry.path().display()
-                            )
-                        });
-
-                        list.push((
-                            entry.path().display().to_string(),
-                            mod_data.mod_name,
-                            mod_data.mod_type.variant_name().to_owned(),
-                            mod_data.enabled,
-                        ));
-                    }
-                }
-                Err(ref e) => {
-                    error!("Could not read {entry:?}, skipping scan. Err: {e}");
-                }
-            }
+    debug!("Returning modlist: {list:?}");
+    Json(list)
+}
+
+pub async fn mod_errors() -> Json<Vec<ModLoadError>> {
+    debug!("mod errors requested by web");
+
+    let (_, errors) = spawn_blocking(scan_mods)
+        .await
+        .expect("scan_mods blocking task failed");
+
+    debug!("Returning mod errors: {errors:?}");
+    Json(errors)
+}
+
+pub async fn mod_conflicts(
+    State(WebState {
+        config,
+        asset_version,
+        ..
+    }): State<WebState>,
+) -> Json<Vec<ModConflict>> {
+    debug!("mod conflicts requested by web");
+
+    let conflicts = spawn_blocking(move || {
+        let mods = load_mods();
+        let active = resolve_active_mods(&mods, &config, &asset_version);
+        find_conflicts(&mods, &active)
+    })
+    .await
+    .expect("find_conflicts blocking task failed");
+
+    debug!("Returning mod conflicts: {conflicts:?}");
+    Json(conflicts)
+}
+
+pub async fn mod_dependencies(
+    State(WebState {
+        config,
+        asset_version,
+        ..
+    }): State<WebState>,
+) -> Json<Vec<ModDependencyNode>> {
+    debug!("mod dependencies requested by web");
+
+    let graph: Vec<ModDependencyNode> = spawn_blocking(move || {
+        let mods = load_mods();
+        let active = resolve_active_mods(&mods, &config, &asset_version);
+
+        mods.iter()
+            .zip(&active)
+            .map(|((path, mod_data), is_active)| ModDependencyNode {
+                path: path.display().to_string(),
+                mod_name: mod_data.mod_name.clone(),
+                active: *is_active,
+                dependencies: mod_data
+                    .dependencies
+                    .iter()
+                    .map(|dependency| ModDependencyEdge {
+                        mod_name: dependency.mod_name.clone(),
+                        version: dependency.version.as_ref().map(|req| req.to_string()),
+                        problem: dependency.problem(&mods, |j| active[j]),
+                    })
+                    .collect(),
+            })
+            .collect()
+    })
+    .await
+    .expect("mod dependencies blocking task failed");
+
+    debug!("Returning mod dependencies: {graph:?}");
+    Json(graph)
+}
+
+pub async fn export_mod(Path(param): Path<String>) -> Response {
+    info!("Export of {param} requested by web");
+
+    let mod_path = fPath::new(&param).to_path_buf();
+
+    let file_name = format!(
+        "{}.{MMLPACK_EXTENSION}",
+        mod_path
+            .file_stem()
+            .and_then(|s| s.to_str())
+            .unwrap_or("mod")
+    );
+
+    match spawn_blocking(move || pack_mod(&mod_path, None, &[], Cursor::new(Vec::new())))
+        .await
+        .expect("pack_mod blocking task failed")
+    {
+        Ok(archive) => (
+            [
+                (header::CONTENT_TYPE, "application/octet-stream".to_string()),
+                (
+                    header::CONTENT_DISPOSITION,
+                    format!("attachment; filename=\"{file_name}\""),
+                ),
+            ],
+            archive.into_inner(),
+        )
+            .into_response(),
+        Err(e) => {
+            let msg = format!("Failed to pack {param}: {e}");
+            error!("{msg}");
+            (StatusCode::BAD_REQUEST, msg).into_response()
+        }
+    }
+}
+
+pub async fn import_mod(body: Bytes) -> impl IntoResponse {
+    info!("Mod import requested by web");
+
+    match spawn_blocking(move || unpack_mod(Cursor::new(body), fPath::new("mods")))
+        .await
+        .expect("unpack_mod blocking task failed")
+    {
+        Ok(mod_path) => format!("Installed mod to {}", mod_path.display()),
+        Err(e) => {
+            let msg = format!("Failed to import mod: {e}");
+            error!("{msg}");
+            msg
         }
-        debug!("Returning modlist: {list:?}");
-        Json(list)
     }
 }
 
@@ -267,332 +510,76 @@ pub async fn gen_ca(Json(payload): Json<CAGenOptions>) -> impl IntoResponse {
 }
 
 pub async fn export_story_to_modpack(
-    config: utils::Config,
-    asset_version: String,
+    State(WebState {
+        config,
+        asset_version,
+        ..
+    }): State<WebState>,
     Json(payload): Json<CustomStory>,
 ) -> impl IntoResponse {
-    // TODO: Clean up, make more efficient, make this an impl
-    // TODO: Apply model transform
-    // TODO: Multi character support
     info!("Exporting story to modpack and generating AssetBundles");
 
-    // payload will be moved into closure, so we need to clone what we need now
-    let mod_name = payload.modpack_name.clone();
-    let payload_file_name = payload.file_name.clone();
-    let payload_logo = payload.logo.clone();
-    let payload_story_background = payload.story_background.clone();
-    let payload_title_background = payload.title_background.clone();
-    let payload_banner_image = payload.banner_image.clone();
-    let mod_ab_path = format!("mods/{mod_name}.ab");
-
-    let mut injected_assets = HashMap::new();
-    injected_assets.insert(
-        "event_story/event_whip_2024/scenario".to_string(),
-        mod_ab_path.clone(),
-    );
-
-    // Loads the template typetree which we will then modify
-    let scenario_typetree = match load_scenario_typetree(SCENARIO_PATH_ID) {
-        Ok(scenario_typetree) => scenario_typetree,
-        Err(e) => return format!("Failed to load typetree. Is UnityPy installed? Err: {e}"),
-    };
-
-    let mut modpack = task::spawn_blocking(move || {
-        let mut modpack = ModData {
-            mod_name: payload.modpack_name.clone(),
-            enabled: true,
-            mod_type: crate::mods::ModType::Story(scenario_typetree),
-            invalidated_assets: Vec::new(),
-            injected_assets,
-        };
-        let ModType::Story(adapter) = &mut modpack.mod_type;
-
-        adapter.generate_story_assetbundle(&payload);
+    let cloned_config = config.clone();
+    let cloned_asset_version = asset_version.clone();
+    if let Err(e) =
+        spawn_blocking(move || export_story_mod(&payload, &cloned_config, &cloned_asset_version))
+            .await
+            .expect("export story blocking task failed")
+    {
+        let msg = format!("{e:#}");
+        error!("{msg}");
+        return msg;
+    }
 
-        modpack
-    })
-    .await
-    .expect("generate_story_assetbundle blocking task failed");
-
-    info!("Creating associated AssetBundles...");
-
-    // Generate screen_image AssetBundle
-
-    // Deleted when dropped, thus we need it here
-    let banner_image_tmp_file = tempfile::Builder::new().suffix(".png").tempfile().unwrap();
-    let banner_image = spawn_blocking(move || match png_from_base64_str(&payload_banner_image) {
-        Ok(img) => {
-            if let Some(image) = img {
-                let img_path = banner_image_tmp_file.path().display().to_string();
-                save_png_best_compression(&image, &img_path).unwrap();
-                Some(FPath::new(&img_path).to_path_buf())
-            } else {
-                None
-            }
+    // Modifies injections-ab with required paths
+    info!(
+        "Reloading injections and assetbundle info hashes! This may trigger multiple redownloads within the game."
+    );
+    match reload_mod_files(&config, &asset_version).await {
+        Ok(_) => {
+            let msg = "Succesfully generated modpack and AssetBundle!".to_string();
+            info!("{msg}");
+            msg
         }
         Err(e) => {
-            error!("Banner image provided is not an valid image! Err: {e}");
-            None
-        }
-    })
-    .await
-    .expect("banner image from base64 blocking task failed");
-
-    let story_background_tmp_file = tempfile::Builder::new().suffix(".png").tempfile().unwrap();
-    let story_background =
-        spawn_blocking(
-            move || match png_from_base64_str(&payload_story_background) {
-                Ok(img) => {
-                    if let Some(image) = img {
-                        let img_path = story_background_tmp_file.path().display().to_string();
-                        save_png_best_compression(&image, &img_path).unwrap();
-                        Some(FPath::new(&img_path).to_path_buf())
-                    } else {
-                        None
-                    }
-                }
-                Err(e) => {
-                    error!("Banner image provided is not an valid image! Err: {e}");
-                    None
-                }
-            },
-        )
-        .await
-        .expect("story background image from base64 blocking task failed");
-
-    let title_background_tmp_file = tempfile::Builder::new().suffix(".png").tempfile().unwrap();
-    let title_background =
-        spawn_blocking(
-            move || match png_from_base64_str(&payload_title_background) {
-                Ok(img) => {
-                    if let Some(image) = img {
-                        let img_path = title_background_tmp_file.path().display().to_string();
-                        save_png_best_compression(&image, &img_path).unwrap();
-                        Some(FPath::new(&img_path).to_path_buf())
-                    } else {
-                        None
-                    }
-                }
-                Err(e) => {
-                    error!("Banner image provided is not an valid image! Err: {e}");
-                    None
-                }
-            },
-        )
-        .await
-        .expect("title background image from base64 blocking task failed");
-
-    // Copy template and generate new screen_image assetbundle in place of the copied original assetbundle
-    let screen_image_path = format!("mods/{mod_name}-screenImage.ab");
-    tokio::fs::copy("assets/story/screen_image/screen_image", &screen_image_path)
-        .await
-        .unwrap();
-
-    modpack.invalidated_assets.push(InvalidateCacheEntry {
-        resource_path: "event_story/event_whip_2024/screen_image".to_string(),
-        duration: CacheInvalidDuration::PermanentlyInvalid,
-    });
-
-    modpack.injected_assets.insert(
-        "event_story/event_whip_2024/screen_image".to_string(),
-        screen_image_path.clone(),
-    );
-
-    spawn_blocking(move || {
-        info!("Generating screen image");
-        match generate_screen_image(
-            &screen_image_path,
-            banner_image,
-            story_background,
-            title_background,
-        ) {
-            Ok(_) => {
-                // Encrypt new AssetBundle
-
-                info!("Encrypting new AssetBundle {}", &screen_image_path);
-
-                let screen_image_path = FPath::new(&screen_image_path);
-                match encrypt(screen_image_path, screen_image_path) {
-                    Ok(_) => {
-                        info!("Encrypted AssetBundle")
-                    }
-                    Err(e) => {
-                        error!("Could not encrypt {}: {}", screen_image_path.display(), e)
-                    }
-                };
-            }
-            Err(e) => error!("Failed to generate screen_image! Default will be used. Err: {e}"),
-        };
-
-        // Copy template and generate new logo assetbundle in place of the copied original assetbundle
-        let logo_ab_path = format!("mods/{mod_name}-logo.ab");
-        fs::copy("assets/event/logo/logo", &logo_ab_path)
-            .unwrap();
-
-        modpack.invalidated_assets.push(InvalidateCacheEntry {
-            resource_path: "event/event_whip_2024/logo".to_string(),
-            duration: CacheInvalidDuration::PermanentlyInvalid,
-        });
-
-        modpack.injected_assets.insert(
-            "event/event_whip_2024/logo".to_string(),
-            logo_ab_path.clone(),
-        );
-
-        // Save image to temp path since generate_logo requires an path (which is needed because UnityPy requires it)
-
-        let logo = {
-            match png_from_base64_str(&payload_logo) {
-                Ok(img) => img,
-                Err(e) => {
-                    return format!("Banner image provided is not an valid image! Err: {e}")
-                        .to_string();
-                }
-            }
-        };
-
-        let image_file = tempfile::Builder::new().suffix(".png").tempfile().unwrap();
-        let img_path = image_file.path().display().to_string();
-        if let Some(logo) = logo {
-            save_png_best_compression(&logo, &img_path).unwrap();
-            info!("Generating logo AssetBundle");
-            match generate_logo(logo_ab_path.clone(), FPath::new(&img_path).to_path_buf()) {
-                Ok(_) => {}
-                Err(e) => error!("Failed to generate logo! Default will be used. Err: {e}"),
-            };
+            let msg = format!("Failed to reload injections and assetbundle info! Err: {e}");
+            error!("{msg}");
+            msg
         }
+    }
+}
 
-        // Encrypt logo AssetBundle, be it the template or newly generated AssetBundle
-        info!("Encrypting new AssetBundle {}", &logo_ab_path);
-
-        let logo_ab_path = FPath::new(&logo_ab_path);
-        match encrypt(logo_ab_path, logo_ab_path) {
-            Ok(_) => {
-                info!("Encrypted AssetBundle")
-            }
+pub async fn rebuild_mods(
+    State(WebState {
+        manager,
+        config,
+        asset_version,
+    }): State<WebState>,
+) -> impl IntoResponse {
+    info!("Mod rebuild requested by web");
+
+    let cloned_config = config.clone();
+    let cloned_asset_version = asset_version.clone();
+    let rebuilt =
+        match spawn_blocking(move || rebuild_story_mods(&cloned_config, &cloned_asset_version))
+            .await
+            .expect("rebuild_story_mods blocking task failed")
+        {
+            Ok(rebuilt) => rebuilt,
             Err(e) => {
-                error!("Could not encrypt {}: {}", logo_ab_path.display(), e)
-            }
-        };
-
-        let file_name = {
-            if payload_file_name.contains(".toml") {
-                payload_file_name
-            } else {
-                format!("{}.toml", payload_file_name)
+                let msg = format!("Failed to rebuild mods! Err: {e:#}");
+                error!("{msg}");
+                return msg;
             }
         };
 
-        let output_file_path = format!("mods/{file_name}");
-        let output_file = fPath::new(&output_file_path);
-
-        if !fPath::new("mods").exists() {
-            match create_dir("mods") {
-                Ok(_) => {}
-                Err(e) => {
-                    error!(
-                        "Could not create mods dir, will try to continue with saving but will likely fail! Err: {e}"
-                    )
-                }
-            }
-        }
-
-        if output_file.exists() {
-            return format!(
-                "{} already exists! Please rename your file.",
-                output_file.display()
-            );
-        } else {
-            match toml::to_string_pretty(&modpack) {
-                Ok(toml) => match std::fs::write(output_file, toml) {
-                    Ok(_) => {
-                        info!(
-                            "Succesfully generated and saved modpack to {}",
-                            output_file.canonicalize().unwrap().display()
-                        );
-                        info!(
-                            "Successfully generated modpack! It was placed in {}",
-                            output_file.canonicalize().unwrap().display()
-                        )
-                    }
-                    Err(e) => {
-                        return format!("Failed to write to {:?}: {e}", output_file.canonicalize());
-                    }
-                },
-                Err(e) => return format!("Failed to serialize modpack into TOML: {e}"),
-            }
-        }
-
-        info!("Creating scenario");
-        match create_assetbundle(
-            modpack,
-            Some(std::path::Path::new(&mod_ab_path).to_path_buf()),
-            true,
-        ) {
-            Ok(_) => {
-                // Modifies injections-ab with required paths
-                info!("Reloading injections!");
-                match reload_injections(&config) {
-                    Ok(_) => {
-                        info!(
-                            "Reloading assetbundle info hashes! This may trigger multiple redownloads within the game."
-                        );
-
-                        match reload_assetbundle_info(&config, &asset_version) {
-                            Ok(_) => {
-                                let msg = "Succesfully generated modpack and AssetBundle!".to_string();
-                                info!("{msg}");
-                                msg
-                            }
-                            Err(e) => {
-                                let msg = format!("Failed to reload assetbundle info! Err: {e}");
-                                error!("{msg}");
-                                msg
-                            }
-                        }
-                    }
-                    Err(e) => {
-                        let msg = format!("Failed to reload injections! Err: {e}");
-                        error!("{msg}");
-                        msg
-                    }
-                }
-            }
-            Err(e) => format!("Failed to convert modpack to AssetBundle: {e}"),
+    match hot_reload_mods(&manager, &config, &asset_version).await {
+        Ok(_) => format!("Rebuilt {}", rebuilt.join(", ")),
+        Err(e) => {
+            let msg = format!("Rebuilt mods, but failed to reload them! Err: {e}");
+            error!("{msg}");
+            msg
         }
-    })
-    .await
-    .expect("generate_screen_image blocking task failed")
-}
-
-// Needed because UnityPy seems to take a very long time with images
-fn save_png_best_compression(
-    img: &DynamicImage,
-    path: &str,
-) -> Result<(), Box<dyn std::error::Error>> {
-    let file = File::create(path)?;
-
-    // Convert image to RGBA8 buffer
-    let rgba8 = img.to_rgba8();
-    let (width, height) = rgba8.dimensions();
-
-    // Use best compression + adaptive filtering
-    let encoder = PngEncoder::new_with_quality(file, CompressionType::Best, FilterType::Adaptive);
-
-    encoder.write_image(&rgba8, width, height, image::ExtendedColorType::Rgba8)?;
-    Ok(())
-}
-
-fn png_from_base64_str(base64: &Option<String>) -> Result<Option<DynamicImage>> {
-    if let Some(base64) = base64 {
-        let image_bytes = BASE64_STANDARD.decode(base64.as_bytes())?;
-        let img = load_from_memory(&image_bytes)?;
-
-        let mut image_buffer = Cursor::new(Vec::new());
-        img.write_to(&mut image_buffer, image::ImageFormat::Png)?;
-        Ok(Some(load_from_memory(&image_buffer.into_inner())?))
-    } else {
-        Ok(None)
     }
 }
 
@@ -1,17 +1,12 @@
 use std::{
     collections::HashMap,
-    ffi::CString,
-    fs::{self, copy},
+    fs,
     path::{Path, PathBuf},
 };
 
-use anyhow::Result;
+use anyhow::{Context, Result, bail};
+use chrono::Local;
 use log::{debug, error, info, warn};
-use pyo3::{
-    PyResult, Python,
-    types::{PyAnyMethods, PyModule},
-};
-use pythonize::{depythonize, pythonize};
 // use dict_derive::FromPyObject;
 use serde::{Deserialize, Serialize};
 
@@ -19,14 +14,137 @@ use crate::{
     encrypt,
     mods::{ModData, ModType},
     notify_mml,
-    utils::{self, Character2DS, Model3Root},
+    unity::{MonoBehaviourQuery, bundle::UnityBundle, find_mono_behaviour, read_typetree},
+    unitypy::{ObjectQuery, UnityPyBundle},
+    utils::{self, ABInfoRoot, Character2DS, Model3Root},
 };
 
-/// Contains all relevant UnityPy code for loading and exporting a typetree.
-pub const PY_CODE: &str = include_str!("../python/story_to_assetbundle.py");
+/// The scenario template we are using, which by default is the whip_2024 scenario but stripped of anything but the bare min.
+/// Any scenario AssetBundle downloaded from the game can take its place, as scenarios are looked up by name and script.
+pub const SCENARIO_TEMPLATE_PATH: &str = "assets/story/scenario/scenario";
+
+/// Class name of the script every scenario MonoBehaviour runs.
+const SCENARIO_SCRIPT: &str = "ScenarioSceneData";
+
+/// Fields only scenarios have, used to find them when their script is stored in another AssetBundle.
+const SCENARIO_FIELDS: &[&str] = &["ScenarioId", "Snippets", "TalkData", "LayoutData"];
+
+/// Declares an enum for a number the game stores as an i32, (de)serialized as that number.
+/// Values MikuMikuLoader doesn't know are kept in `Other`, so scenarios from the game still read and write back unchanged.
+macro_rules! game_enum {
+    ($(#[$meta:meta])* $name:ident { $($(#[$variant_meta:meta])* $variant:ident = $value:literal,)* }) => {
+        $(#[$meta])*
+        #[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+        #[serde(from = "i32", into = "i32")]
+        pub enum $name {
+            $($(#[$variant_meta])* $variant,)*
+            Other(i32),
+        }
+
+        impl From<i32> for $name {
+            fn from(value: i32) -> Self {
+                match value {
+                    $($value => $name::$variant,)*
+                    other => $name::Other(other),
+                }
+            }
+        }
+
+        impl From<$name> for i32 {
+            fn from(value: $name) -> Self {
+                match value {
+                    $($name::$variant => $value,)*
+                    $name::Other(other) => other,
+                }
+            }
+        }
+    };
+}
+
+game_enum! {
+    /// What a ScenarioSnippet plays, and so which list its ReferenceIndex points into.
+    #[derive(Default)]
+    SnippetAction {
+        #[default]
+        None = 0,
+        /// TalkData
+        Talk = 1,
+        /// LayoutData
+        CharacterLayout = 2,
+        InputName = 3,
+        /// LayoutData, only changing the motion
+        CharacterMotion = 4,
+        Selectable = 5,
+        /// SpecialEffectData
+        SpecialEffect = 6,
+        /// SoundData
+        Sound = 7,
+    }
+}
+
+game_enum! {
+    /// Move changes the side and motion of a character already on stage, appear brings one in and clear removes one.
+    LayoutType {
+        Move = 0,
+        Appear = 2,
+        Clear = 3,
+    }
+}
+
+game_enum! {
+    /// Sides of the stage a ScenarioCharacterLayout can place characters on.
+    LayoutSide {
+        Left = 3,
+        Center = 4,
+        Right = 5,
+    }
+}
+
+game_enum! {
+    /// Default keeps the order characters appeared in.
+    LayoutDepth {
+        Default = 0,
+        Front = 1,
+    }
+}
+
+game_enum! {
+    /// How fast a ScenarioCharacterLayout moves a character between sides.
+    MoveSpeed {
+        Normal = 0,
+    }
+}
+
+game_enum! {
+    /// ScenarioSpecialEffect types, as numbered by the game.
+    EffectType {
+        BlackIn = 1,
+        BlackOut = 2,
+        WhiteIn = 3,
+        WhiteOut = 4,
+        ShakeScreen = 5,
+        ShakeWindow = 6,
+        /// Switches to the background named in StringVal
+        ChangeBackground = 7,
+        Telop = 8,
+        FlashbackIn = 9,
+        FlashbackOut = 10,
+        PlaceInfo = 18,
+        FullScreenText = 24,
+        StopShakeScreen = 25,
+        StopShakeWindow = 26,
+    }
+}
 
-/// The path id to the scenario in the template we are using, which in this case is the whip_2024 scenario but stripped of anything but the bare min.
-pub static SCENARIO_PATH_ID: i64 = 6343946530110770478;
+/// Width of the SEKAI-Stories canvas that model transforms are positioned on.
+/// The game lays its stage out on the same 1920 wide reference resolution, so offsets carry over as is.
+const SEKAI_STORIES_WIDTH: i32 = 1920;
+
+/// BGM that plays nothing, used to stop the music.
+const BGM_SILENT: &str = "bgm00000";
+
+/// SE bundle the game loads sound effects from when SeBundleName is empty.
+const DEFAULT_SE_BUNDLE: &str = "se_pack00001";
 
 /// Contains all relevant data that makes up a scenario.
 /// Directly represents the typetree from UnityPy.
@@ -117,14 +235,21 @@ impl Default for Scenario {
 }
 
 impl Scenario {
-    pub fn generate_story_assetbundle(&mut self, payload: &CustomStory) {
-        let mod_name = payload.modpack_name.clone();
+    /// Compiles `scenes` into the scenario, reading character2ds.json and the SEKAI-Stories models from `assets_dir`.
+    pub fn generate_story_assetbundle(
+        &mut self,
+        mod_name: &str,
+        scenes: &[CustomStoryScene],
+        assets_dir: &Path,
+    ) {
+        let mod_name = mod_name.to_owned();
 
         // Store all characters and their expressions while looping through models to be used later
         let mut character_expressions: Option<HashMap<String, CharacterData>> = None;
 
         // Load character2ds
-        let character2ds_file = fs::File::open("assets/character2ds.json").expect("Could not read assets/character2ds.json! Please remove the assets folder and try again to redownload assets.");
+        let character2ds_path = assets_dir.join("character2ds.json");
+        let character2ds_file = fs::File::open(&character2ds_path).unwrap_or_else(|_| panic!("Could not read {}! Please remove the assets folder and try again to redownload assets.", character2ds_path.display()));
 
         let character2ds: Vec<Character2DS> = serde_json::from_reader(character2ds_file).expect(
             "character2ds.json is not formatted properly! Check if MikuMikuLoader is out of date.",
@@ -136,84 +261,82 @@ impl Scenario {
             .collect();
 
         // Push the first background
-        let bkg_name = Path::new(&payload.data[0].data.background.clone().to_string())
-            .file_stem()
-            .and_then(|s| s.to_str())
-            .unwrap_or("")
-            .to_owned();
+        let mut bkg_name = background_name(&scenes[0].data.background);
 
         debug!("Pushing first background");
         self.firstBackground = bkg_name.clone();
+        self.need_background(&bkg_name);
 
-        self.needBundleNames
-            .push(format!("scenario/background/{bkg_name}"));
+        // Push special effects with story name and (?) MikuMikuLoader, along with their snippets
 
-        // Push special effect data with story name and (?) MikuMikuLoader
-        self.specialEffectData.push(ScenarioSpecialEffect {
-            effectType: 8,
-            stringVal: "Created with MikuMikuLoader".to_owned(),
-            stringValSub: "".to_owned(),
-            duration: 0.0,
-            intVal: 0,
-        });
+        // "MikuMikuLoader" special effect
+        self.push_snippet(
+            SnippetData::SpecialEffect(ScenarioSpecialEffect {
+                effectType: EffectType::Telop,
+                stringVal: "Created with MikuMikuLoader".to_owned(),
+                stringValSub: "".to_owned(),
+                duration: 0.0,
+                intVal: 0,
+            }),
+            0.0,
+        );
 
-        self.specialEffectData.push(ScenarioSpecialEffect {
-            effectType: 8,
-            stringVal: mod_name.to_owned(),
-            stringValSub: "".to_owned(),
-            duration: 0.0,
-            intVal: 0,
-        });
+        // Mod name special effect
+        self.push_snippet(
+            SnippetData::SpecialEffect(ScenarioSpecialEffect {
+                effectType: EffectType::Telop,
+                stringVal: mod_name.to_owned(),
+                stringValSub: "".to_owned(),
+                duration: 0.0,
+                intVal: 0,
+            }),
+            0.0,
+        );
 
-        self.specialEffectData.push(ScenarioSpecialEffect {
-            effectType: 4,
-            stringVal: "".to_owned(),
-            stringValSub: "".to_owned(),
-            duration: 1.0,
-            intVal: 0,
-        });
+        // Clears the title cards
+        self.push_snippet(
+            SnippetData::SpecialEffect(ScenarioSpecialEffect {
+                effectType: EffectType::WhiteOut,
+                stringVal: "".to_owned(),
+                stringValSub: "".to_owned(),
+                duration: 1.0,
+                intVal: 0,
+            }),
+            0.0,
+        );
 
-        // Push necessary ScenarioSnippet for above special effects
+        // Characters currently on stage, and how long the next layout waits. The first characters wait for the title cards to clear.
+        let mut stage: Vec<StagedCharacter> = Vec::new();
+        let mut layout_delay = 2.0;
 
-        // "MikuMikuLoader" special effect
-        self.snippets.push(ScenarioSnippet {
-            index: 0,
-            action: 6,
-            progressBehavior: 1,
-            referenceIndex: 0,
-            delay: 0.0,
-        });
+        // Loop through all the scenes to push the relevant data
+        for (index, scene) in scenes.iter().enumerate() {
+            let scene_background = background_name(&scene.data.background);
 
-        // Mod name special effect
-        self.snippets.push(ScenarioSnippet {
-            index: 1,
-            action: 6,
-            progressBehavior: 1,
-            referenceIndex: 1,
-            delay: 0.0,
-        });
+            // Transitions out of the previous scene play before its background is swapped, everything else after
+            let (effects_before, effects_after): (Vec<&SceneEffect>, Vec<&SceneEffect>) = scene
+                .effects
+                .iter()
+                .partition(|effect| effect.plays_before_background());
 
-        // Clear special effects
-        self.snippets.push(ScenarioSnippet {
-            index: 2,
-            action: 7,
-            progressBehavior: 1,
-            referenceIndex: 0,
-            delay: 0.0,
-        });
+            for effect in effects_before {
+                self.push_snippet(SnippetData::SpecialEffect(effect.to_special_effect()), 0.0);
+            }
 
-        // Have the character appear TODO: Multiple character support
-        self.snippets.push(ScenarioSnippet {
-            index: 3,
-            action: 2,
-            progressBehavior: 1,
-            referenceIndex: 0,
-            delay: 2.0,
-        });
+            if scene_background != bkg_name {
+                debug!("Changing background to {scene_background} in scene {index}");
+                self.change_background(&scene_background);
+                bkg_name = scene_background;
+            }
+
+            for effect in effects_after {
+                self.push_snippet(SnippetData::SpecialEffect(effect.to_special_effect()), 0.0);
+            }
+
+            self.play_scene_sound(&scene.sound, index == 0);
+
+            let mut cast: Vec<StagedCharacter> = Vec::new();
 
-        // Loop through all the scenes to push the relevant data
-        for (index, scene) in payload.data.iter().enumerate() {
-            let initial_scene = { index == 0 };
             // Populate appear_characters, and use the grabbed id to populate talk_data at the same time
             for model in &scene.data.models {
                 if model.from == "sekai" {
@@ -249,84 +372,111 @@ impl Scenario {
                     debug!("Setting character_id to {character_id}");
 
                     // Use BuildMotionData to determine the expression and pose, since SEKAI-Stories uses an index in the serialized JSON
-                    character_expressions.get_or_insert_with(HashMap::new).insert(model.character.clone(), {
-                    let char_map = utils::build_character_map();
-
-                    if let Some(full_id) = char_map.get(&model.character) {
-
-                        // Get pose and expression name from SEKAI-Stories index
-                        let character_motions_path =
-                            format!("assets/public/live2d/model/{}/{}/{}.model3.json", full_id.replace("_", ""), model.model_name, model.model_name);
-
-                        debug!("Trying to read build motion data from {character_motions_path}");
-
-                        let character_motions_file = fs::File::open(&character_motions_path).unwrap_or_else(|_| panic!("Could not read {}! Please remove the assets folder and try again to redownload assets.", &character_motions_path));
-
-                        let character_motions: Model3Root = serde_json::from_reader(
-                            character_motions_file,
-                        ).unwrap();
-
-                        // Collect just the keys
-                        let mut character_motions: Vec<&String> = character_motions.file_references.motions.keys().collect();
-
-                        // SEKAI-Stories has a bug(?) where v2_20mizuki_casual has face_sleepy_03, despite not being referanced in any model files for Mizuki, so it has to be inserted.
-                        let missing_motion = &"face_sleepy_03".to_string();
-                        if model.model_name == "v2_20mizuki_casual" {
-                            debug!("Inserting face_sleepy_03 to account for SEKAI-Stories");
-
-                            let insert_index = character_motions.iter().position(|&p| p == "face_sleepy_02").unwrap();
-
-                            character_motions.insert(insert_index + 1, missing_motion);
-                        }
-
-                        // Grabs pose name
-                        let result_pose = match character_motions.get(model.model_pose as usize) {
-                            Some(result_pose) => *result_pose,
-                            None => {
-                                warn!("Could not find any matching result_pose inside {character_motions_path}, using w-adult-blushed01!");
-                                &"w-adult-blushed01".to_string()
-                            }
-                        };
-
-                        // Get index of first key containing "face_", as this is how SEKAI-Stories does its indexing
+                    let char_data = {
+                        let char_map = utils::build_character_map();
+
+                        if let Some(full_id) = char_map.get(&model.character) {
+                            // Get pose and expression name from SEKAI-Stories index
+                            let character_motions_path =
+                                model_motions_path(assets_dir, full_id, &model.model_name);
+
+                            let character_motions =
+                                load_model_motions(&character_motions_path, &model.model_name)
+                                    .unwrap_or_else(|e| panic!("{e:#}"));
+
+                            // Grabs pose name
+                            let result_pose = match character_motions.get(model.model_pose as usize)
+                            {
+                                Some(result_pose) => result_pose,
+                                None => {
+                                    warn!(
+                                        "Could not find any matching result_pose inside {character_motions_path}, using w-adult-blushed01!"
+                                    );
+                                    &"w-adult-blushed01".to_string()
+                                }
+                            };
 
-                        let result_expression = match character_motions.iter().position(|k| k.contains("face_")) {
-                            Some(idx) => match character_motions.get(idx + (model.model_expression - 1) as usize) {
-                                Some(result_expression) => *result_expression,
+                            // Get index of first key containing "face_", as this is how SEKAI-Stories does its indexing
+
+                            let result_expression = match character_motions
+                                .iter()
+                                .position(|k| k.contains("face_"))
+                            {
+                                Some(idx) => match character_motions
+                                    .get(idx + (model.model_expression - 1) as usize)
+                                {
+                                    Some(result_expression) => result_expression,
+                                    None => {
+                                        warn!(
+                                            "Could not find any matching result_expression inside {character_motions_path}, using face_cry_01!"
+                                        );
+                                        &"face_cry_01".to_string()
+                                    }
+                                },
                                 None => {
-                                    warn!("Could not find any matching result_expression inside {character_motions_path}, using face_cry_01!");
+                                    warn!(
+                                        "{character_motions_path}, contains no face_*! This should never happen, using face_cry_01!"
+                                    );
                                     &"face_cry_01".to_string()
                                 }
-                            },
-                            None => {
-                                warn!("{character_motions_path}, contains no face_*! This should never happen, using face_cry_01!");
-                                &"face_cry_01".to_string()
-                            }
-                        };
+                            };
 
-                        let char_data = CharacterData {
-                            id: character_id,
-                            motion_name: result_pose.to_owned(),
-                            facial_name: result_expression.to_owned(),
-                            costume_type: model.model_name.clone(),
-                        };
+                            let char_data = CharacterData {
+                                id: character_id,
+                                motion_name: result_pose.to_owned(),
+                                facial_name: result_expression.to_owned(),
+                                costume_type: model.model_name.clone(),
+                            };
 
-                        debug!("Inserting the following CharacterData: {char_data:?}");
-                        char_data
-                    } else {
-                        error!(
-                            "Could not find character {}! Please create a bug report. The expression will be replaced with face_cry_01, and the pose will be replaced with w-cute-glad01",
-                            "face_cry_01"
+                            debug!("Inserting the following CharacterData: {char_data:?}");
+                            char_data
+                        } else {
+                            error!(
+                                "Could not find character {}! Please create a bug report. The expression will be replaced with face_cry_01, and the pose will be replaced with w-cute-glad01",
+                                "face_cry_01"
+                            );
+
+                            CharacterData {
+                                id: 286,
+                                motion_name: "w-cute-glad01".to_string(),
+                                facial_name: "face_cry_01".to_string(),
+                                costume_type: "v2_09kohane_casual".to_string(),
+                            }
+                        }
+                    };
+
+                    if cast
+                        .iter()
+                        .any(|character| character.character2d_id == char_data.id)
+                    {
+                        warn!(
+                            "{} is in scene {index} more than once, only the first is used",
+                            model.character
                         );
+                    } else {
+                        let (side, offset_x) = stage_position(model.model_transform.x);
 
-                        CharacterData {
-                            id: 286,
-                            motion_name: "w-cute-glad01".to_string(),
-                            facial_name: "face_cry_01".to_string(),
-                            costume_type: "v2_09kohane_casual".to_string(),
+                        if model.model_transform.y != 0 || model.model_transform.scale != 1.0 {
+                            debug!(
+                                "{} has a vertical position or scale, which the game can't show",
+                                model.character
+                            );
                         }
+
+                        cast.push(StagedCharacter {
+                            character2d_id: char_data.id,
+                            costume_type: char_data.costume_type.clone(),
+                            motion_name: char_data.motion_name.clone(),
+                            facial_name: char_data.facial_name.clone(),
+                            side,
+                            offset_x,
+                            depth: LayoutDepth::Default,
+                        });
                     }
-                });
+
+                    character_expressions
+                        .get_or_insert_with(HashMap::new)
+                        .insert(model.character.clone(), char_data);
 
                     let character_to_push = crate::scenario::ScenarioAppearCharacters {
                         character2dId: character_id,
@@ -353,11 +503,34 @@ impl Scenario {
             // Apply text and stories
             let character_name = &scene.data.text.name_tag.to_lowercase();
 
+            // SEKAI-Stories draws later models over earlier ones, so the last is brought to the front when characters share the stage
+            if cast.len() > 1
+                && let Some(last) = cast.last_mut()
+            {
+                last.depth = LayoutDepth::Front;
+            }
+
+            let speaker = character_expressions
+                .as_ref()
+                .and_then(|character_expressions| character_expressions.get(character_name))
+                .map(|character| character.id);
+            let speaker_appeared = self.change_cast(&mut stage, cast, speaker, &mut layout_delay);
+
             match character_expressions {
                 Some(ref character_expressions) => {
                     match character_expressions.get(character_name) {
                         Some(character) => {
-                            self.talkData.push(ScenarioTalkData {
+                            let voices: Vec<TalkVoice> = scene
+                                .voice_id
+                                .iter()
+                                .map(|voice_id| TalkVoice {
+                                    character2dId: character.id,
+                                    voiceId: voice_id.clone(),
+                                    volume: 1.0,
+                                })
+                                .collect();
+
+                            let talk = ScenarioTalkData {
                                 // Push character to talk_data (which will have other fields filled later)
                                 talkCharacters: vec![TalkCharacter {
                                     character2dId: character.id,
@@ -365,7 +538,8 @@ impl Scenario {
                                 windowDisplayName: capitalize(character_name),
                                 body: scene.data.text.dialogue.clone(),
                                 motions: {
-                                    if initial_scene {
+                                    // Characters that just appeared already have their motion
+                                    if speaker_appeared {
                                         Vec::new()
                                     } else {
                                         vec![TalkMotion {
@@ -376,73 +550,30 @@ impl Scenario {
                                         }]
                                     }
                                 },
-                                voices: Vec::new(),
+                                // Moves the speaker's mouth along with their voice, so only voiced lines have it
+                                lipSync: if voices.is_empty() { 0 } else { 1 },
+                                voices,
                                 whenFinishCloseWindow: {
                                     // TODO: Make configurable
-                                    if index == payload.data.len() { 1 } else { 0 }
+                                    if index == scenes.len() { 1 } else { 0 }
                                 },
                                 ..Default::default()
-                            });
-
-                            let scenario_char_layout = ScenarioCharacterLayout {
-                                r#type: if initial_scene { 2 } else { 0 },
-                                sideFrom: if initial_scene { 4 } else { 3 },
-                                sideFromOffsetX: 0.0,
-                                sideTo: if initial_scene { 4 } else { 3 },
-                                sideToOffsetX: 0.0,
-                                depthType: 0,
-                                character2dId: character.id,
-                                costumeType: if initial_scene {
-                                    character.costume_type.clone()
-                                } else {
-                                    "".to_owned()
-                                },
-                                motionName: character.motion_name.clone(),
-                                facialName: character.facial_name.clone(),
-                                moveSpeedType: 0,
                             };
 
-                            self.layoutData.push(scenario_char_layout);
-
-                            // If it's the last scene, we need to push an empty layoutdata. Not sure why.
-                            if index == payload.data.len() - 1 {
-                                debug!(
-                                    "Pushing final needed empty ScenarioSnippetCharacterLayout!"
-                                );
-
-                                self.layoutData.push(ScenarioCharacterLayout {
-                                    r#type: 3,
-                                    sideFrom: 4,
-                                    sideFromOffsetX: 0.0,
-                                    sideTo: 4,
-                                    sideToOffsetX: 0.0,
-                                    depthType: 0,
-                                    character2dId: character.id,
-                                    costumeType: "".to_owned(),
-                                    motionName: "".to_owned(),
-                                    facialName: "".to_owned(),
-                                    moveSpeedType: 0,
-                                })
-                            }
-                            // match self.layoutData.contains(&scenario_char_layout) {
-                            //     true => {}
-                            //     false => self.layoutData.push(scenario_char_layout),
-                            // };
-
-                            // Push an ScenarioSnippet for our dialogue
-                            self.snippets.push(ScenarioSnippet {
-                                index: self.snippets.len() as i32,
-                                action: 1,
-                                progressBehavior: 1,
-                                referenceIndex: index as i32,
-                                delay: 2.0,
-                            });
+                            // Push our dialogue along with its ScenarioSnippet
+                            self.push_snippet(SnippetData::Talk(talk), 2.0);
                         }
                         None => {
                             error!(
                                 "Could not find {character_name} in {character_expressions:?}! Please file a bug report. Default character will be used."
                             );
-                            self.talkData.push(ScenarioTalkData::default())
+                            self.push_snippet(
+                                SnippetData::Talk(ScenarioTalkData {
+                                    body: scene.data.text.dialogue.clone(),
+                                    ..Default::default()
+                                }),
+                                2.0,
+                            );
                         }
                     }
                 }
@@ -453,9 +584,612 @@ impl Scenario {
                 }
             }
         }
+
+        // Clear the stage once the story is over
+        self.change_cast(&mut stage, Vec::new(), None, &mut layout_delay);
+    }
+
+    /// Pushes the layouts that take the stage from `stage` to `cast`, clearing characters that left, moving or changing the motion of characters that stayed and bringing in new ones.
+    /// The speaker's motion is left to their talk data unless they moved. Returns whether the speaker just appeared, and so already has their motion.
+    fn change_cast(
+        &mut self,
+        stage: &mut Vec<StagedCharacter>,
+        cast: Vec<StagedCharacter>,
+        speaker: Option<i32>,
+        delay: &mut f32,
+    ) -> bool {
+        let mut speaker_appeared = false;
+
+        for character in stage.iter() {
+            if !cast
+                .iter()
+                .any(|new| new.character2d_id == character.character2d_id)
+            {
+                debug!("Clearing {} from the stage", character.character2d_id);
+                self.push_layout(
+                    SnippetData::Layout(character.layout(LayoutType::Clear, character, false)),
+                    delay,
+                );
+            }
+        }
+
+        for character in &cast {
+            let previous = stage
+                .iter()
+                .find(|staged| staged.character2d_id == character.character2d_id);
+
+            match previous {
+                None => {
+                    debug!(
+                        "Bringing {} in on side {:?}",
+                        character.character2d_id, character.side
+                    );
+                    speaker_appeared |= speaker == Some(character.character2d_id);
+                    self.push_layout(
+                        SnippetData::Layout(character.layout(LayoutType::Appear, character, true)),
+                        delay,
+                    );
+                }
+                Some(previous)
+                    if previous.side != character.side
+                        || previous.offset_x != character.offset_x
+                        || previous.depth != character.depth =>
+                {
+                    self.push_layout(
+                        SnippetData::Layout(character.layout(LayoutType::Move, previous, false)),
+                        delay,
+                    );
+                }
+                Some(previous)
+                    if speaker != Some(character.character2d_id)
+                        && (previous.motion_name != character.motion_name
+                            || previous.facial_name != character.facial_name) =>
+                {
+                    self.push_layout(
+                        SnippetData::Motion(character.layout(LayoutType::Move, character, false)),
+                        delay,
+                    );
+                }
+                Some(_) => {}
+            }
+        }
+
+        *stage = cast;
+        speaker_appeared
+    }
+
+    /// Pushes the special effect and snippet that switch to the background `background`, before anything else in the scene happens.
+    fn change_background(&mut self, background: &str) {
+        self.push_snippet(
+            SnippetData::SpecialEffect(ScenarioSpecialEffect {
+                effectType: EffectType::ChangeBackground,
+                stringVal: background.to_owned(),
+                stringValSub: "".to_owned(),
+                duration: 0.0,
+                intVal: 0,
+            }),
+            0.0,
+        );
+
+        self.need_background(background);
+    }
+
+    /// Pushes the sound data and snippets for the music and sound effects of a scene.
+    /// The first scene's BGM becomes the scenario's FirstBgm instead, so it is already playing when the story starts.
+    fn play_scene_sound(&mut self, sound: &SceneSound, first_scene: bool) {
+        let bgm = if sound.stop_bgm {
+            Some(BGM_SILENT)
+        } else {
+            sound.bgm.as_deref()
+        };
+
+        if let Some(bgm) = bgm {
+            self.include_sound_bundle(bgm_bundle_name(bgm));
+
+            if first_scene {
+                self.firstBgm = bgm.to_owned();
+            } else {
+                self.push_snippet(
+                    SnippetData::Sound(ScenarioSoundData {
+                        bgm: bgm.to_owned(),
+                        se: "".to_owned(),
+                        ..Default::default()
+                    }),
+                    0.0,
+                );
+            }
+        }
+
+        for effect in &sound.se {
+            self.include_sound_bundle(se_bundle_name(effect.bundle.as_deref()));
+            self.push_snippet(
+                SnippetData::Sound(ScenarioSoundData {
+                    se: effect.name.clone(),
+                    seBundleName: effect.bundle.clone().unwrap_or_default(),
+                    ..Default::default()
+                }),
+                0.0,
+            );
+        }
+    }
+
+    fn include_sound_bundle(&mut self, bundle_name: String) {
+        if !self.includeSoundDataBundleNames.contains(&bundle_name) {
+            self.includeSoundDataBundleNames.push(bundle_name);
+        }
+    }
+
+    /// Makes the game download the bundle of `background` before the scenario starts.
+    fn need_background(&mut self, background: &str) {
+        let bundle_name = format!("scenario/background/{background}");
+
+        if !self.needBundleNames.contains(&bundle_name) {
+            self.needBundleNames.push(bundle_name);
+        }
+    }
+
+    /// Pushes `layout` along with the snippet that plays it, which waits for `delay`. Only the first layout waits, the rest play along with it.
+    fn push_layout(&mut self, layout: SnippetData, delay: &mut f32) {
+        self.push_snippet(layout, *delay);
+        *delay = 0.0;
+    }
+
+    /// Appends `data` to the list it belongs in, along with a snippet that plays it after `delay`.
+    /// Every snippet should be pushed through here, so snippet indexes and the reference indexes into each list always line up.
+    fn push_snippet(&mut self, data: SnippetData, delay: f32) {
+        let (action, reference_index) = match data {
+            SnippetData::Talk(talk) => {
+                self.talkData.push(talk);
+                (SnippetAction::Talk, self.talkData.len())
+            }
+            SnippetData::Layout(layout) => {
+                self.layoutData.push(layout);
+                (SnippetAction::CharacterLayout, self.layoutData.len())
+            }
+            SnippetData::Motion(layout) => {
+                self.layoutData.push(layout);
+                (SnippetAction::CharacterMotion, self.layoutData.len())
+            }
+            SnippetData::SpecialEffect(effect) => {
+                self.specialEffectData.push(effect);
+                (SnippetAction::SpecialEffect, self.specialEffectData.len())
+            }
+            SnippetData::Sound(sound) => {
+                self.soundData.push(sound);
+                (SnippetAction::Sound, self.soundData.len())
+            }
+        };
+
+        self.snippets.push(ScenarioSnippet {
+            index: self.snippets.len() as i32,
+            action,
+            progressBehavior: 1,
+            referenceIndex: reference_index as i32 - 1,
+            delay,
+        });
+    }
+}
+
+fn bgm_bundle_name(bgm: &str) -> String {
+    format!("sound/scenario/bgm/{bgm}")
+}
+
+/// The voice bundle the game plays the voice lines of scenario `scenario_id` from.
+fn voice_bundle_name(scenario_id: &str) -> String {
+    format!("sound/scenario/voice/{scenario_id}")
+}
+
+fn se_bundle_name(bundle: Option<&str>) -> String {
+    format!("sound/scenario/se/{}", bundle.unwrap_or(DEFAULT_SE_BUNDLE))
+}
+
+/// Name of the background bundle a SEKAI-Stories background is served from, which is its file name without the extension.
+fn background_name(background: &str) -> String {
+    Path::new(background)
+        .file_stem()
+        .and_then(|s| s.to_str())
+        .unwrap_or("")
+        .to_owned()
+}
+
+/// Whatever a snippet plays, to be pushed into its own list of the scenario by `Scenario::push_snippet`.
+enum SnippetData {
+    Talk(ScenarioTalkData),
+    Layout(ScenarioCharacterLayout),
+    /// A layout that only changes the character's motion
+    Motion(ScenarioCharacterLayout),
+    SpecialEffect(ScenarioSpecialEffect),
+    Sound(ScenarioSoundData),
+}
+
+/// The side of the stage closest to where SEKAI-Stories places a model, splitting the canvas into thirds, along with how far off the middle of that side it stands.
+fn stage_position(x: i32) -> (LayoutSide, f32) {
+    let side = match x * 3 / SEKAI_STORIES_WIDTH {
+        ..1 => LayoutSide::Left,
+        1 => LayoutSide::Center,
+        _ => LayoutSide::Right,
+    };
+
+    (side, (x - side_middle(side)) as f32)
+}
+
+/// Where SEKAI-Stories would place a model standing `offset_x` off the middle of `side`.
+fn x_from_stage_position(side: LayoutSide, offset_x: f32) -> i32 {
+    side_middle(side) + offset_x.round() as i32
+}
+
+/// The middle of the third of the canvas a side covers.
+fn side_middle(side: LayoutSide) -> i32 {
+    let third = match side {
+        LayoutSide::Left => 0,
+        LayoutSide::Right => 2,
+        _ => 1,
+    };
+
+    SEKAI_STORIES_WIDTH * (2 * third + 1) / 6
+}
+
+impl Scenario {
+    /// Turns the scenario back into SEKAI-Stories scenes, one for every line of dialogue, so official stories can be remixed.
+    /// Characters keep their costume and latest motion between lines, and the background carries over until it is changed.
+    /// Models are placed where the game places them, though the game has no vertical position or scale for them to keep.
+    pub fn to_sekai_stories_scenes(&self) -> Result<Vec<CustomStoryScene>> {
+        let character2ds_file = fs::File::open("assets/character2ds.json").context("Could not read assets/character2ds.json! Please remove the assets folder and try again to redownload assets.")?;
+
+        let character2ds: HashMap<i32, Character2DS> = serde_json::from_reader::<
+            _,
+            Vec<Character2DS>,
+        >(character2ds_file)
+        .context(
+            "character2ds.json is not formatted properly! Check if MikuMikuLoader is out of date.",
+        )?
+        .into_iter()
+        .map(|character2d| (character2d.id, character2d))
+        .collect();
+
+        // Keyed by character id, which is the number in front of the full id
+        let characters: HashMap<i32, (String, &str)> = utils::build_character_map()
+            .into_iter()
+            .filter_map(|(name, full_id)| {
+                let character_id = full_id.split('_').next()?.parse().ok()?;
+                Some((character_id, (name, full_id)))
+            })
+            .collect();
+
+        let last_modified = Local::now().to_rfc3339();
+        let mut motion_cache = HashMap::new();
+        let mut background = self.firstBackground.clone();
+        let mut stage: Vec<StagedCharacter> = Vec::new();
+        let mut scenes = Vec::new();
+
+        // Effects and sound played since the last line, which go to the scene of the next line
+        let mut effects = Vec::new();
+        let mut sound = SceneSound {
+            bgm: Some(self.firstBgm.clone()).filter(|bgm| !bgm.is_empty() && bgm != BGM_SILENT),
+            ..Default::default()
+        };
+
+        for snippet in &self.snippets {
+            let reference_index = snippet.referenceIndex as usize;
+
+            match snippet.action {
+                SnippetAction::CharacterLayout | SnippetAction::CharacterMotion => {
+                    let Some(layout) = self.layoutData.get(reference_index) else {
+                        warn!(
+                            "Snippet {} references missing layout {reference_index}, skipping",
+                            snippet.index
+                        );
+                        continue;
+                    };
+
+                    if snippet.action == SnippetAction::CharacterLayout
+                        && layout.r#type == LayoutType::Clear
+                    {
+                        stage.retain(|character| character.character2d_id != layout.character2dId);
+                    } else {
+                        self.stage_character(
+                            &mut stage,
+                            layout.character2dId,
+                            Some((layout.sideTo, layout.sideToOffsetX)),
+                            &layout.costumeType,
+                            &layout.motionName,
+                            &layout.facialName,
+                        );
+                    }
+                }
+                SnippetAction::Talk => {
+                    let Some(talk) = self.talkData.get(reference_index) else {
+                        warn!(
+                            "Snippet {} references missing talk data {reference_index}, skipping",
+                            snippet.index
+                        );
+                        continue;
+                    };
+
+                    for motion in &talk.motions {
+                        self.stage_character(
+                            &mut stage,
+                            motion.character2dId,
+                            None,
+                            "",
+                            &motion.motionName,
+                            &motion.facialName,
+                        );
+                    }
+
+                    let models = stage
+                        .iter()
+                        .filter_map(|character| {
+                            character.to_sekai_stories_model(
+                                &character2ds,
+                                &characters,
+                                &mut motion_cache,
+                            )
+                        })
+                        .collect();
+
+                    scenes.push(CustomStoryScene {
+                        index: scenes.len() as i64,
+                        data: SekaiStoriesScene {
+                            last_modified: last_modified.clone(),
+                            // SEKAI-Stories serves backgrounds as jpgs named after their bundle
+                            background: format!("/background_compressed/{background}.jpg"),
+                            text: SekaiStoriesSceneText {
+                                name_tag: talk.windowDisplayName.clone(),
+                                dialogue: talk.body.clone(),
+                            },
+                            models,
+                        },
+                        sound: std::mem::take(&mut sound),
+                        effects: std::mem::take(&mut effects),
+                        voice_id: talk.voices.first().map(|voice| voice.voiceId.clone()),
+                    });
+                }
+                SnippetAction::Sound => {
+                    let Some(sound_data) = self.soundData.get(reference_index) else {
+                        continue;
+                    };
+
+                    if sound_data.bgm == BGM_SILENT {
+                        sound.bgm = None;
+                        sound.stop_bgm = true;
+                    } else if !sound_data.bgm.is_empty() {
+                        sound.bgm = Some(sound_data.bgm.clone());
+                        sound.stop_bgm = false;
+                    }
+
+                    if !sound_data.se.is_empty() {
+                        sound.se.push(SceneSoundEffect {
+                            name: sound_data.se.clone(),
+                            bundle: Some(sound_data.seBundleName.clone())
+                                .filter(|bundle| !bundle.is_empty()),
+                        });
+                    }
+                }
+                SnippetAction::SpecialEffect => {
+                    let Some(effect) = self.specialEffectData.get(reference_index) else {
+                        continue;
+                    };
+
+                    if effect.effectType == EffectType::ChangeBackground {
+                        background = effect.stringVal.clone();
+                    } else if let Some(effect) = SceneEffect::from_special_effect(effect) {
+                        effects.push(effect);
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        Ok(scenes)
+    }
+
+    /// Puts a character on stage, or updates the one already there. Empty names and no position leave the current value alone.
+    fn stage_character(
+        &self,
+        stage: &mut Vec<StagedCharacter>,
+        character2d_id: i32,
+        position: Option<(LayoutSide, f32)>,
+        costume_type: &str,
+        motion_name: &str,
+        facial_name: &str,
+    ) {
+        let index = match stage
+            .iter()
+            .position(|character| character.character2d_id == character2d_id)
+        {
+            Some(index) => index,
+            None => {
+                // Costumes are only set when a character first appears, otherwise they come from AppearCharacters
+                let costume_type = self
+                    .appearCharacters
+                    .iter()
+                    .find(|character| character.character2dId == character2d_id)
+                    .map(|character| character.costumeType.clone())
+                    .unwrap_or_default();
+
+                stage.push(StagedCharacter {
+                    character2d_id,
+                    costume_type,
+                    motion_name: String::new(),
+                    facial_name: String::new(),
+                    side: LayoutSide::Center,
+                    offset_x: 0.0,
+                    depth: LayoutDepth::Default,
+                });
+                stage.len() - 1
+            }
+        };
+
+        let character = &mut stage[index];
+        if let Some((side, offset_x)) = position {
+            character.side = side;
+            character.offset_x = offset_x;
+        }
+
+        for (value, new_value) in [
+            (&mut character.costume_type, costume_type),
+            (&mut character.motion_name, motion_name),
+            (&mut character.facial_name, facial_name),
+        ] {
+            if !new_value.is_empty() {
+                *value = new_value.to_owned();
+            }
+        }
+    }
+}
+
+/// A character on stage while generating or walking through a scenario.
+struct StagedCharacter {
+    character2d_id: i32,
+    costume_type: String,
+    motion_name: String,
+    facial_name: String,
+    side: LayoutSide,
+    /// How far the character stands off the middle of its side
+    offset_x: f32,
+    depth: LayoutDepth,
+}
+
+impl StagedCharacter {
+    /// A layout of `layout_type` moving the character from where `from` stood to where it stands now.
+    /// The costume is only set when `with_costume`, as the game takes it from AppearCharacters otherwise. Clearing layouts leave out the motion too.
+    fn layout(
+        &self,
+        layout_type: LayoutType,
+        from: &StagedCharacter,
+        with_costume: bool,
+    ) -> ScenarioCharacterLayout {
+        let clear = layout_type == LayoutType::Clear;
+
+        ScenarioCharacterLayout {
+            r#type: layout_type,
+            sideFrom: from.side,
+            sideFromOffsetX: from.offset_x,
+            sideTo: self.side,
+            sideToOffsetX: self.offset_x,
+            depthType: self.depth,
+            character2dId: self.character2d_id,
+            costumeType: if with_costume {
+                self.costume_type.clone()
+            } else {
+                String::new()
+            },
+            motionName: if clear {
+                String::new()
+            } else {
+                self.motion_name.clone()
+            },
+            facialName: if clear {
+                String::new()
+            } else {
+                self.facial_name.clone()
+            },
+            moveSpeedType: MoveSpeed::Normal,
+        }
+    }
+
+    /// Converts the character into a SEKAI-Stories model, turning motion names back into the indexes SEKAI-Stories uses.
+    /// Returns None for characters SEKAI-Stories has no models for.
+    fn to_sekai_stories_model(
+        &self,
+        character2ds: &HashMap<i32, Character2DS>,
+        characters: &HashMap<i32, (String, &str)>,
+        motion_cache: &mut HashMap<String, Option<Vec<String>>>,
+    ) -> Option<SekaiStoriesSceneModels> {
+        let Some((name, full_id)) = character2ds
+            .get(&self.character2d_id)
+            .and_then(|character2d| characters.get(&character2d.character_id))
+        else {
+            warn!(
+                "Character2d {} is not a Project Sekai character, leaving it out",
+                self.character2d_id
+            );
+            return None;
+        };
+
+        let motions = motion_cache
+            .entry(self.costume_type.clone())
+            .or_insert_with(|| {
+                load_model_motions(
+                    &model_motions_path(Path::new("assets"), full_id, &self.costume_type),
+                    &self.costume_type,
+                )
+                .inspect_err(|e| warn!("{e:#}, {name} will use the default pose and expression"))
+                .ok()
+            })
+            .as_deref()
+            .unwrap_or_default();
+
+        let model_pose = motions
+            .iter()
+            .position(|motion| *motion == self.motion_name)
+            .unwrap_or(0);
+
+        // Expressions are indexed from 1, starting at the first face_ motion
+        let model_expression = motions
+            .iter()
+            .position(|motion| motion.contains("face_"))
+            .zip(
+                motions
+                    .iter()
+                    .position(|motion| *motion == self.facial_name),
+            )
+            .and_then(|(first_face, facial)| facial.checked_sub(first_face))
+            .map_or(1, |offset| offset + 1);
+
+        Some(SekaiStoriesSceneModels {
+            from: "sekai".to_string(),
+            character: name.clone(),
+            model_name: self.costume_type.clone(),
+            model_transform: SekaiStoriesSceneTransform {
+                x: x_from_stage_position(self.side, self.offset_x),
+                y: 0,
+                scale: 1.0,
+            },
+            model_expression: model_expression as i32,
+            model_pose: model_pose as i32,
+        })
     }
 }
 
+/// Path to the model3.json of a SEKAI-Stories model in `assets_dir`, where `full_id` is the id from `utils::build_character_map`.
+fn model_motions_path(assets_dir: &Path, full_id: &str, model_name: &str) -> String {
+    format!(
+        "{}/public/live2d/model/{}/{model_name}/{model_name}.model3.json",
+        assets_dir.display(),
+        full_id.replace("_", "")
+    )
+}
+
+/// Reads the motions of a model in the order SEKAI-Stories indexes them, where poses are indexed directly and expressions from the first face_ motion.
+fn load_model_motions(character_motions_path: &str, model_name: &str) -> Result<Vec<String>> {
+    debug!("Trying to read build motion data from {character_motions_path}");
+
+    let character_motions_file = fs::File::open(character_motions_path).with_context(|| format!("Could not read {character_motions_path}! Please remove the assets folder and try again to redownload assets."))?;
+
+    let character_motions: Model3Root = serde_json::from_reader(character_motions_file)
+        .with_context(|| format!("{character_motions_path} is not a valid model3.json"))?;
+
+    // Collect just the keys
+    let mut character_motions: Vec<String> = character_motions
+        .file_references
+        .motions
+        .into_keys()
+        .collect();
+
+    // SEKAI-Stories has a bug(?) where v2_20mizuki_casual has face_sleepy_03, despite not being referanced in any model files for Mizuki, so it has to be inserted.
+    if model_name == "v2_20mizuki_casual"
+        && let Some(insert_index) = character_motions.iter().position(|p| p == "face_sleepy_02")
+    {
+        debug!("Inserting face_sleepy_03 to account for SEKAI-Stories");
+
+        character_motions.insert(insert_index + 1, "face_sleepy_03".to_string());
+    }
+
+    Ok(character_motions)
+}
+
 fn capitalize(s: &str) -> String {
     let mut c = s.chars();
     match c.next() {
@@ -489,7 +1223,7 @@ pub struct ScenarioSnippet {
     pub index: i32,
 
     #[serde(rename = "Action")]
-    pub action: i32,
+    pub action: SnippetAction,
 
     #[serde(rename = "ProgressBehavior")]
     pub progressBehavior: i32,
@@ -560,10 +1294,10 @@ impl Default for ScenarioTalkData {
             windowDisplayName: "Kohane".to_string(),
             body: "Default body".to_string(),
             talkTention: 0,
-            lipSync: 1,
+            lipSync: 0,
             motionChangeFrom: 1,
             motions: vec![TalkMotion::default()],
-            voices: vec![TalkVoice::default()],
+            voices: Vec::new(),
             speed: 0.0,
             fontSize: 0,
             whenFinishCloseWindow: 1,
@@ -637,22 +1371,22 @@ impl Default for TalkVoice {
 #[allow(non_snake_case)]
 pub struct ScenarioCharacterLayout {
     #[serde(rename = "Type")]
-    pub r#type: i32,
+    pub r#type: LayoutType,
 
     #[serde(rename = "SideFrom")]
-    pub sideFrom: i32,
+    pub sideFrom: LayoutSide,
 
     #[serde(rename = "SideFromOffsetX")]
     pub sideFromOffsetX: f32,
 
     #[serde(rename = "SideTo")]
-    pub sideTo: i32,
+    pub sideTo: LayoutSide,
 
     #[serde(rename = "SideToOffsetX")]
     pub sideToOffsetX: f32,
 
     #[serde(rename = "DepthType")]
-    pub depthType: i32,
+    pub depthType: LayoutDepth,
 
     #[serde(rename = "Character2dId")]
     pub character2dId: i32,
@@ -667,23 +1401,23 @@ pub struct ScenarioCharacterLayout {
     pub facialName: String,
 
     #[serde(rename = "MoveSpeedType")]
-    pub moveSpeedType: i32,
+    pub moveSpeedType: MoveSpeed,
 }
 
 impl Default for ScenarioCharacterLayout {
     fn default() -> Self {
         ScenarioCharacterLayout {
-            r#type: 2,
-            sideFrom: 4,
+            r#type: LayoutType::Appear,
+            sideFrom: LayoutSide::Center,
             sideFromOffsetX: 0.0,
-            sideTo: 4,
+            sideTo: LayoutSide::Center,
             sideToOffsetX: 0.0,
-            depthType: 0,
+            depthType: LayoutDepth::Default,
             character2dId: 286,
             costumeType: "v2_09kohane_casual".to_string(),
             motionName: "w-normal15-tilthead".to_string(),
             facialName: "face_smallmouth_01".to_string(),
-            moveSpeedType: 0,
+            moveSpeedType: MoveSpeed::Normal,
         }
     }
 }
@@ -692,7 +1426,7 @@ impl Default for ScenarioCharacterLayout {
 #[allow(non_snake_case)]
 pub struct ScenarioSpecialEffect {
     #[serde(rename = "EffectType")]
-    pub effectType: i32,
+    pub effectType: EffectType,
 
     #[serde(rename = "StringVal")]
     pub stringVal: String,
@@ -710,7 +1444,7 @@ pub struct ScenarioSpecialEffect {
 impl Default for ScenarioSpecialEffect {
     fn default() -> Self {
         ScenarioSpecialEffect {
-            effectType: 8,
+            effectType: EffectType::Telop,
             stringVal: "Morning".to_string(),
             stringValSub: "".to_string(),
             duration: 0.0,
@@ -781,13 +1515,353 @@ pub struct CustomStory {
     pub story_background: Option<String>,
     pub title_background: Option<String>,
     pub logo: Option<String>,
+    /// Scenes of a story with a single episode. Only used when `episodes` is empty.
+    #[serde(default)]
     pub data: Vec<CustomStoryScene>,
+
+    /// Every episode of the story in order, for stories with more than one.
+    #[serde(default)]
+    pub episodes: Vec<CustomStoryEpisode>,
+
+    /// Which event's story the mod replaces, defaulting to event_whip_2024 for stories made before it could be set.
+    #[serde(default)]
+    pub target: StoryTarget,
+}
+
+impl CustomStory {
+    /// Checks that the target event and every sound and voice bundle the story plays exist in the assetbundle info.
+    pub fn verify(&self, abinfo: &ABInfoRoot) -> Result<()> {
+        let episode_scenes = self.episode_scenes();
+
+        let episode_images = episode_scenes
+            .iter()
+            .any(|(_, title_image)| title_image.is_some());
+        self.target.verify(abinfo, episode_images)?;
+
+        let mut bundle_names: Vec<String> = episode_scenes
+            .iter()
+            .flat_map(|(scenes, _)| scenes.iter())
+            .flat_map(|scene| scene.sound.bundle_names())
+            .collect();
+
+        for (episode, (scenes, _)) in episode_scenes.iter().enumerate() {
+            if scenes.is_empty() {
+                bail!("Episode {} has no scenes", episode + 1);
+            }
+
+            if scenes.iter().any(|scene| scene.voice_id.is_some()) {
+                let Some(first_id) = &self.target.scenario_id else {
+                    bail!(
+                        "Voice lines are played from the voice bundle of the episode's scenario id, so the target scenario id has to be set"
+                    );
+                };
+
+                bundle_names.push(voice_bundle_name(&episode_scenario_id(first_id, episode)));
+            }
+        }
+
+        let mut missing: Vec<String> = bundle_names
+            .into_iter()
+            .filter(|bundle_name| !abinfo.bundles.contains_key(bundle_name))
+            .collect();
+        missing.sort();
+        missing.dedup();
+
+        if !missing.is_empty() {
+            bail!(
+                "{} could not be found in the assetbundle info, check the BGM, sound effect and voice names",
+                missing.join(", ")
+            );
+        }
+
+        Ok(())
+    }
+
+    /// Scenes and title image of every episode, in order.
+    pub fn episode_scenes(&self) -> Vec<(&[CustomStoryScene], Option<&String>)> {
+        if self.episodes.is_empty() {
+            return vec![(&self.data, None)];
+        }
+
+        self.episodes
+            .iter()
+            .map(|episode| (episode.data.as_slice(), episode.title_image.as_ref()))
+            .collect()
+    }
+}
+
+/// One episode of a story, compiled into its own scenario.
+#[derive(Debug, Deserialize, Serialize)]
+pub struct CustomStoryEpisode {
+    /// Image shown for the episode in the episode list, base64 encoded like the other images. The event's own image is kept if not set.
+    #[serde(default)]
+    pub title_image: Option<String>,
+    pub data: Vec<CustomStoryScene>,
+}
+
+/// The event slot a story mod is injected into.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct StoryTarget {
+    /// Asset bundle name of the event, such as event_whip_2024
+    pub event: String,
+
+    /// Scenario id of the first episode, such as event_129_01. Later episodes count up from it. The template's scenario id is kept if not set.
+    #[serde(default)]
+    pub scenario_id: Option<String>,
+}
+
+impl Default for StoryTarget {
+    fn default() -> Self {
+        StoryTarget {
+            event: "event_whip_2024".to_string(),
+            scenario_id: None,
+        }
+    }
+}
+
+impl StoryTarget {
+    pub fn scenario_resource_path(&self) -> String {
+        format!("event_story/{}/scenario", self.event)
+    }
+
+    pub fn screen_image_resource_path(&self) -> String {
+        format!("event_story/{}/screen_image", self.event)
+    }
+
+    pub fn logo_resource_path(&self) -> String {
+        format!("event/{}/logo", self.event)
+    }
+
+    pub fn episode_image_resource_path(&self) -> String {
+        format!("event_story/{}/episode_image", self.event)
+    }
+
+    /// Name of the texture holding the title image of episode `episode` (counting from 0) in the episode_image AssetBundle.
+    pub fn episode_image_name(&self, episode: usize) -> String {
+        format!("{}_{:02}", self.event, episode + 1)
+    }
+
+    /// Checks that every AssetBundle the story replaces exists in the assetbundle info, so typos don't silently produce a mod that never loads.
+    pub fn verify(&self, abinfo: &ABInfoRoot, episode_images: bool) -> Result<()> {
+        let mut resource_paths = vec![
+            self.scenario_resource_path(),
+            self.screen_image_resource_path(),
+            self.logo_resource_path(),
+        ];
+
+        if episode_images {
+            resource_paths.push(self.episode_image_resource_path());
+        }
+
+        let missing: Vec<String> = resource_paths
+            .into_iter()
+            .filter(|resource_path| !abinfo.bundles.contains_key(resource_path))
+            .collect();
+
+        if !missing.is_empty() {
+            bail!(
+                "{} is not an event with a story, as {} could not be found in the assetbundle info",
+                self.event,
+                missing.join(", ")
+            );
+        }
+
+        Ok(())
+    }
+}
+
+/// Scenario id of episode `episode` (counting from 0) of a story whose first episode is `first`.
+/// Counts up the number `first` ends with, keeping its width, so event_129_01 is followed by event_129_02.
+pub fn episode_scenario_id(first: &str, episode: usize) -> String {
+    if episode == 0 {
+        return first.to_string();
+    }
+
+    let digits = first.len() - first.trim_end_matches(|c: char| c.is_ascii_digit()).len();
+    let (stem, number) = first.split_at(first.len() - digits);
+
+    match number.parse::<usize>() {
+        Ok(number) => format!("{stem}{:0digits$}", number + episode),
+        Err(_) => format!("{first}_{:02}", episode + 1),
+    }
+}
+
+/// A story as saved and loaded by the custom-story page, without any of the mod settings.
+#[derive(Debug, Deserialize, Serialize)]
+pub struct CustomStoryFile {
+    #[serde(rename = "scenesData")]
+    pub scenes_data: Vec<CustomStoryScene>,
 }
 
 #[derive(Debug, Deserialize, Serialize)]
 pub struct CustomStoryScene {
     pub index: i64,
     pub data: SekaiStoriesScene,
+
+    /// Music and sound effects that start with the scene. Not part of SEKAI-Stories, which has no sound.
+    #[serde(default, skip_serializing_if = "SceneSound::is_empty")]
+    pub sound: SceneSound,
+
+    /// Transitions and special effects that play as the scene starts, in order.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub effects: Vec<SceneEffect>,
+
+    /// Game voice line the dialogue is spoken with, such as voice_ev_street_17_01_01_09.
+    /// The game plays it from the voice bundle of the episode's scenario id, so it must be one of that episode's lines.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub voice_id: Option<String>,
+}
+
+/// A screen transition or special effect, compiled into the scenario's special effect data.
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum SceneEffect {
+    /// Fades in from black
+    BlackIn {
+        #[serde(default = "default_effect_duration")]
+        duration: f32,
+    },
+    /// Fades out to black
+    BlackOut {
+        #[serde(default = "default_effect_duration")]
+        duration: f32,
+    },
+    /// Fades in from white, also used as a flash along with `WhiteOut`
+    WhiteIn {
+        #[serde(default = "default_effect_duration")]
+        duration: f32,
+    },
+    /// Fades out to white
+    WhiteOut {
+        #[serde(default = "default_effect_duration")]
+        duration: f32,
+    },
+    /// Starts the sepia filter used for flashbacks
+    FlashbackIn,
+    FlashbackOut,
+    ShakeScreen {
+        #[serde(default = "default_effect_duration")]
+        duration: f32,
+    },
+    ShakeWindow {
+        #[serde(default = "default_effect_duration")]
+        duration: f32,
+    },
+    StopShakeScreen,
+    StopShakeWindow,
+    /// Text shown across the middle of the screen, such as a time of day
+    Telop {
+        text: String,
+    },
+    /// Name of the place the scene is set in, shown in the corner
+    PlaceInfo {
+        text: String,
+    },
+    /// Text over a black screen, as used for narration
+    FullScreenText {
+        text: String,
+    },
+}
+
+fn default_effect_duration() -> f32 {
+    1.0
+}
+
+impl SceneEffect {
+    /// Transitions out of a scene, which play before its background changes.
+    fn plays_before_background(&self) -> bool {
+        matches!(
+            self,
+            SceneEffect::BlackOut { .. } | SceneEffect::WhiteOut { .. } | SceneEffect::FlashbackOut
+        )
+    }
+}
+
+/// Declares the conversions between SceneEffect and ScenarioSpecialEffect, where every SceneEffect plays the EffectType of the same name.
+/// Effects either last a duration, show a text from StringVal or take nothing.
+macro_rules! scene_effect_types {
+    (
+        duration: [$($duration:ident),* $(,)?],
+        text: [$($text:ident),* $(,)?],
+        plain: [$($plain:ident),* $(,)?] $(,)?
+    ) => {
+        impl SceneEffect {
+            fn to_special_effect(&self) -> ScenarioSpecialEffect {
+                let (effect_type, string_val, duration) = match self {
+                    $(SceneEffect::$duration { duration } => (EffectType::$duration, "", *duration),)*
+                    $(SceneEffect::$text { text } => (EffectType::$text, text.as_str(), 0.0),)*
+                    $(SceneEffect::$plain => (EffectType::$plain, "", 0.0),)*
+                };
+
+                ScenarioSpecialEffect {
+                    effectType: effect_type,
+                    stringVal: string_val.to_owned(),
+                    stringValSub: "".to_owned(),
+                    duration,
+                    intVal: 0,
+                }
+            }
+
+            /// The effect a scenario's special effect plays, if it is one that can be authored.
+            fn from_special_effect(effect: &ScenarioSpecialEffect) -> Option<SceneEffect> {
+                Some(match effect.effectType {
+                    $(EffectType::$duration => SceneEffect::$duration { duration: effect.duration },)*
+                    $(EffectType::$text => SceneEffect::$text { text: effect.stringVal.clone() },)*
+                    $(EffectType::$plain => SceneEffect::$plain,)*
+                    _ => return None,
+                })
+            }
+        }
+    };
+}
+
+scene_effect_types! {
+    duration: [BlackIn, BlackOut, WhiteIn, WhiteOut, ShakeScreen, ShakeWindow],
+    text: [Telop, PlaceInfo, FullScreenText],
+    plain: [FlashbackIn, FlashbackOut, StopShakeScreen, StopShakeWindow],
+}
+
+#[derive(Debug, Default, Deserialize, Serialize)]
+pub struct SceneSound {
+    /// BGM to switch to, such as bgm00012
+    #[serde(default)]
+    pub bgm: Option<String>,
+
+    /// Stops the music instead
+    #[serde(default)]
+    pub stop_bgm: bool,
+
+    #[serde(default)]
+    pub se: Vec<SceneSoundEffect>,
+}
+
+impl SceneSound {
+    pub fn is_empty(&self) -> bool {
+        self.bgm.is_none() && !self.stop_bgm && self.se.is_empty()
+    }
+
+    /// Every sound bundle the scene plays from.
+    pub fn bundle_names(&self) -> Vec<String> {
+        self.bgm
+            .iter()
+            .map(|bgm| bgm_bundle_name(bgm))
+            .chain(
+                self.se
+                    .iter()
+                    .map(|effect| se_bundle_name(effect.bundle.as_deref())),
+            )
+            .collect()
+    }
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct SceneSoundEffect {
+    /// Name of the sound effect, such as se_walk_women_001_1
+    pub name: String,
+
+    /// SE bundle it is in, such as se_pack00001. The game's default SE bundle is used if not set.
+    #[serde(default)]
+    pub bundle: Option<String>,
 }
 
 #[derive(Debug, Deserialize, Serialize)]
@@ -836,38 +1910,37 @@ pub struct CharacterData {
 /// Saves the AssetBundle typetree inside a modpack into output_path if set, otherwise mods/{mod_name}.ab
 /// Optionally encrypts before saving. (Encryption is required for the game to read it properly.)
 pub fn create_assetbundle(
-    modpack: ModData,
+    modpack: &ModData,
     output_path: Option<PathBuf>,
     encrypt_ab: bool,
-) -> PyResult<()> {
-    let mod_name = modpack.mod_name;
+) -> Result<()> {
+    let mod_name = &modpack.mod_name;
 
     let mod_ab_path = &format!("mods/{mod_name}.ab");
     let mod_ab_path = output_path.unwrap_or(Path::new(mod_ab_path).to_path_buf());
 
-    copy("assets/story/scenario/scenario", mod_ab_path.clone())?; // TODO: Don't hardcode
-
-    match modpack.mod_type {
-        ModType::Story(scenario_self) => Python::attach(|py| {
-            let filename = CString::new("story_to_assetbundle.py").unwrap();
-            let modname = CString::new("story_to_assetbundle").unwrap();
-
-            let module =
-                PyModule::from_code(py, &CString::new(PY_CODE).unwrap(), &filename, &modname)?;
+    match &modpack.mod_type {
+        ModType::Story(episodes) => {
+            let template_path = Path::new(SCENARIO_TEMPLATE_PATH);
 
-            module
-                .getattr("set_asset_path")?
-                .call1((&mod_ab_path.to_str(),))?;
-
-            module
-                .getattr("set_target_path_id")?
-                .call1((SCENARIO_PATH_ID,))?;
+            match write_episodes(template_path, episodes) {
+                Ok(assetbundle) => fs::write(&mod_ab_path, assetbundle)?,
+                Err(e) => {
+                    let [scenario_self] = episodes.as_slice() else {
+                        bail!("Could not write the episodes of {mod_name}: {e:#}");
+                    };
 
-            module.getattr("return_typetree")?.call0()?; // Will set target_object to the correct object as a global in Python so that we can then use save_typetree
+                    warn!("Could not write the scenario natively, falling back to UnityPy: {e:#}");
 
-            module
-                .getattr("save_typetree")?
-                .call1((pythonize(py, &scenario_self)?,))?;
+                    // Replaces the scenario the story was loaded from, which is the first one as the scenario id may have been changed
+                    let bundle = UnityPyBundle::open(template_path)?;
+                    bundle.write_object(
+                        &ObjectQuery::from(&scenario_query(None)),
+                        &serde_json::to_value(scenario_self)?,
+                    )?;
+                    bundle.save(&mod_ab_path)?;
+                }
+            }
 
             if encrypt_ab {
                 info!("Encrypting new AssetBundle {}", mod_ab_path.display());
@@ -883,27 +1956,344 @@ pub fn create_assetbundle(
 
             info!("Saved new AssetBundle to: {}", mod_ab_path.display());
             Ok(())
-        }),
+        }
     }
 }
 
-/// Loads the AssetBundle typetree from assets/story/scenario/scenario template
-pub fn load_scenario_typetree(path_id: i64) -> Result<Scenario> {
-    Python::attach(|py| {
-        let filename = CString::new("story_to_assetbundle.py").unwrap();
-        let modname = CString::new("story_to_assetbundle").unwrap();
+/// Writes every episode into the scenario template at `template_path`, returning the repacked, unencrypted bundle.
+/// Like the game's own scenario bundles, each episode is a scenario of its own, listed in the container under its scenario id.
+/// Episodes replace the template scenario with the same name. The first one otherwise replaces the scenario the story was loaded from, and the rest are added as copies of it.
+fn write_episodes(template_path: &Path, episodes: &[Scenario]) -> Result<Vec<u8>> {
+    let mut bundle = UnityBundle::open(template_path)?;
+    let mut scenarios = bundle.mono_behaviours(&scenario_query(None))?;
+
+    let (first, _) = scenarios
+        .first()
+        .cloned()
+        .with_context(|| format!("No {SCENARIO_SCRIPT} in {}", template_path.display()))?;
+
+    for (episode, scenario) in episodes.iter().enumerate() {
+        let value = serde_json::to_value(scenario)?;
+
+        let existing = scenarios
+            .iter()
+            .position(|(_, name)| name.as_deref() == Some(scenario.m_Name.as_str()))
+            .or((episode == 0).then_some(0));
+
+        let path_id = match existing {
+            Some(index) => {
+                let (path_id, name) = &mut scenarios[index];
+                *name = Some(scenario.m_Name.clone());
+                bundle.write_object(*path_id, &value)?;
+                *path_id
+            }
+            None => bundle.add_object(first, &value)?,
+        };
+
+        bundle
+            .set_container_name(path_id, first, &scenario.m_Name)
+            .with_context(|| format!("Could not list {} in the container", scenario.m_Name))?;
 
-        let module = PyModule::from_code(py, &CString::new(PY_CODE).unwrap(), &filename, &modname)?;
+        debug!("Wrote {} as object {path_id}", scenario.m_Name);
+    }
 
-        module
-            .getattr("set_asset_path")?
-            .call1((&"assets/story/scenario/scenario",))?;
+    bundle.to_bytes()
+}
 
-        module.getattr("set_target_path_id")?.call1((path_id,))?;
+/// Loads the scenario named `name` from the decrypted scenario AssetBundle at `bundle_path`, or its first scenario if `name` is not set.
+/// Fails if there is no scenario named `name`.
+pub fn load_scenario_typetree(bundle_path: &Path, name: Option<&str>) -> Result<Scenario> {
+    let query = scenario_query(name);
 
-        let typetree: Scenario =
-            depythonize(&module.getattr("return_typetree")?.call0()?.extract()?)?;
+    find_mono_behaviour(bundle_path, &query)
+        .and_then(|path_id| read_typetree(bundle_path, path_id))
+        .or_else(|e| {
+            warn!("Could not read the scenario natively, falling back to UnityPy: {e:#}");
 
-        Ok(typetree)
-    })
+            let typetree =
+                UnityPyBundle::open(bundle_path)?.read_object(&ObjectQuery::from(&query))?;
+            Ok(serde_json::from_value(typetree)?)
+        })
+}
+
+fn scenario_query(name: Option<&str>) -> MonoBehaviourQuery<'_> {
+    MonoBehaviourQuery {
+        script: SCENARIO_SCRIPT,
+        name,
+        fields: SCENARIO_FIELDS,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Writes the character2ds.json and model3.json files `generate_story_assetbundle` reads for Ichika and Saki into `assets`.
+    fn write_test_assets(assets: &Path) {
+        fs::write(
+            assets.join("character2ds.json"),
+            r#"[
+                {"id": 1, "characterType": "game_character", "isNextGrade": false, "characterId": 1, "unit": "light_sound", "isEnabledFlipDisplay": false, "assetName": "v2_01ichika"},
+                {"id": 2, "characterType": "game_character", "isNextGrade": false, "characterId": 2, "unit": "light_sound", "isEnabledFlipDisplay": false, "assetName": "v2_02saki"}
+            ]"#,
+        )
+        .unwrap();
+
+        for (full_id, model_name) in [
+            ("01ichika", "v2_01ichika_casual"),
+            ("02saki", "v2_02saki_casual"),
+        ] {
+            let model_dir = assets
+                .join("public/live2d/model")
+                .join(full_id)
+                .join(model_name);
+            fs::create_dir_all(&model_dir).unwrap();
+
+            fs::write(
+                model_dir.join(format!("{model_name}.model3.json")),
+                r#"{"FileReferences": {"Moc": "model.moc3", "Motions": {
+                    "w-normal01": [{"FadeInTime": 0.5, "FadeOutTime": 0.5, "File": "w-normal01.motion3.json"}],
+                    "face_smile_01": [{"FadeInTime": 0.5, "FadeOutTime": 0.5, "File": "face_smile_01.motion3.json"}]
+                }}}"#,
+            )
+            .unwrap();
+        }
+    }
+
+    /// Ichika speaks alone in the middle with a voice line, then a fade to black moves to another background where Saki joins on the right.
+    const TEST_STORY: &str = r#"{
+        "file_name": "test.toml",
+        "modpack_name": "Test Story",
+        "banner_image": null,
+        "story_background": null,
+        "title_background": null,
+        "logo": null,
+        "data": [
+            {
+                "index": 0,
+                "data": {
+                    "lastModified": "",
+                    "background": "/background_compressed/bg_a000101.jpg",
+                    "text": {"nameTag": "Ichika", "dialogue": "Hello"},
+                    "models": [
+                        {"from": "sekai", "character": "ichika", "modelName": "v2_01ichika_casual", "modelTransform": {"x": 960, "y": 0, "scale": 1.0}, "modelExpression": 1, "modelPose": 0}
+                    ]
+                },
+                "sound": {"bgm": "bgm00012"},
+                "voice_id": "voice_test_01"
+            },
+            {
+                "index": 1,
+                "data": {
+                    "lastModified": "",
+                    "background": "/background_compressed/bg_a000102.jpg",
+                    "text": {"nameTag": "Saki", "dialogue": "Hi"},
+                    "models": [
+                        {"from": "sekai", "character": "ichika", "modelName": "v2_01ichika_casual", "modelTransform": {"x": 320, "y": 0, "scale": 1.0}, "modelExpression": 1, "modelPose": 0},
+                        {"from": "sekai", "character": "saki", "modelName": "v2_02saki_casual", "modelTransform": {"x": 1600, "y": 0, "scale": 1.0}, "modelExpression": 1, "modelPose": 0}
+                    ]
+                },
+                "sound": {"se": [{"name": "se_test"}]},
+                "effects": [{"type": "black_out"}]
+            }
+        ]
+    }"#;
+
+    #[test]
+    fn scene_effects_round_trip_through_special_effects() {
+        let effects = [
+            SceneEffect::BlackOut { duration: 0.5 },
+            SceneEffect::ShakeWindow { duration: 2.0 },
+            SceneEffect::Telop {
+                text: "Morning".to_string(),
+            },
+            SceneEffect::FullScreenText {
+                text: "Later".to_string(),
+            },
+            SceneEffect::FlashbackIn,
+            SceneEffect::StopShakeScreen,
+        ];
+
+        for effect in effects {
+            let special_effect = effect.to_special_effect();
+            assert_eq!(
+                SceneEffect::from_special_effect(&special_effect),
+                Some(effect)
+            );
+        }
+
+        assert_eq!(
+            i32::from(
+                SceneEffect::FullScreenText {
+                    text: String::new()
+                }
+                .to_special_effect()
+                .effectType
+            ),
+            24
+        );
+        assert_eq!(
+            SceneEffect::from_special_effect(&ScenarioSpecialEffect {
+                effectType: EffectType::ChangeBackground,
+                ..Default::default()
+            }),
+            None
+        );
+    }
+
+    /// A template with nothing in it, so the generated data starts at index 0.
+    fn empty_scenario() -> Scenario {
+        Scenario {
+            appearCharacters: Vec::new(),
+            snippets: Vec::new(),
+            talkData: Vec::new(),
+            layoutData: Vec::new(),
+            specialEffectData: Vec::new(),
+            soundData: Vec::new(),
+            needBundleNames: Vec::new(),
+            scenarioSnippetCharacterLayoutModes: Vec::new(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn story_compiles_to_scenario() {
+        let dir = tempfile::tempdir().unwrap();
+        write_test_assets(dir.path());
+
+        let story: CustomStory = serde_json::from_str(TEST_STORY).unwrap();
+        let mut scenario = empty_scenario();
+        scenario.generate_story_assetbundle(&story.modpack_name, &story.data, dir.path());
+
+        let snippets: Vec<(SnippetAction, i32)> = scenario
+            .snippets
+            .iter()
+            .map(|snippet| (snippet.action, snippet.referenceIndex))
+            .collect();
+        assert_eq!(
+            snippets,
+            [
+                // Title cards, then the white-out clearing them
+                (SnippetAction::SpecialEffect, 0),
+                (SnippetAction::SpecialEffect, 1),
+                (SnippetAction::SpecialEffect, 2),
+                // Ichika appears and speaks
+                (SnippetAction::CharacterLayout, 0),
+                (SnippetAction::Talk, 0),
+                // Fade to black, then the background changes and the sound effect plays
+                (SnippetAction::SpecialEffect, 3),
+                (SnippetAction::SpecialEffect, 4),
+                (SnippetAction::Sound, 0),
+                // Ichika moves to the left, Saki appears on the right and speaks
+                (SnippetAction::CharacterLayout, 1),
+                (SnippetAction::CharacterLayout, 2),
+                (SnippetAction::Talk, 1),
+                // Both leave once the story is over
+                (SnippetAction::CharacterLayout, 3),
+                (SnippetAction::CharacterLayout, 4),
+            ]
+        );
+        assert!(
+            scenario
+                .snippets
+                .iter()
+                .enumerate()
+                .all(|(index, snippet)| snippet.index == index as i32)
+        );
+
+        let effects: Vec<(EffectType, &str)> = scenario
+            .specialEffectData
+            .iter()
+            .map(|effect| (effect.effectType, effect.stringVal.as_str()))
+            .collect();
+        assert_eq!(
+            effects,
+            [
+                (EffectType::Telop, "Created with MikuMikuLoader"),
+                (EffectType::Telop, "Test Story"),
+                (EffectType::WhiteOut, ""),
+                (EffectType::BlackOut, ""),
+                (EffectType::ChangeBackground, "bg_a000102"),
+            ]
+        );
+
+        let layouts: Vec<(LayoutType, i32, LayoutSide, LayoutSide, LayoutDepth)> = scenario
+            .layoutData
+            .iter()
+            .map(|layout| {
+                (
+                    layout.r#type,
+                    layout.character2dId,
+                    layout.sideFrom,
+                    layout.sideTo,
+                    layout.depthType,
+                )
+            })
+            .collect();
+        assert_eq!(
+            layouts,
+            [
+                (
+                    LayoutType::Appear,
+                    1,
+                    LayoutSide::Center,
+                    LayoutSide::Center,
+                    LayoutDepth::Default
+                ),
+                (
+                    LayoutType::Move,
+                    1,
+                    LayoutSide::Center,
+                    LayoutSide::Left,
+                    LayoutDepth::Default
+                ),
+                (
+                    LayoutType::Appear,
+                    2,
+                    LayoutSide::Right,
+                    LayoutSide::Right,
+                    LayoutDepth::Front
+                ),
+                (
+                    LayoutType::Clear,
+                    1,
+                    LayoutSide::Left,
+                    LayoutSide::Left,
+                    LayoutDepth::Default
+                ),
+                (
+                    LayoutType::Clear,
+                    2,
+                    LayoutSide::Right,
+                    LayoutSide::Right,
+                    LayoutDepth::Front
+                ),
+            ]
+        );
+
+        // Only the voiced line moves the speaker's mouth
+        let talk: Vec<(&str, usize, i32)> = scenario
+            .talkData
+            .iter()
+            .map(|talk| (talk.body.as_str(), talk.voices.len(), talk.lipSync))
+            .collect();
+        assert_eq!(talk, [("Hello", 1, 1), ("Hi", 0, 0)]);
+
+        assert_eq!(scenario.firstBackground, "bg_a000101");
+        assert_eq!(scenario.firstBgm, "bgm00012");
+        assert_eq!(
+            scenario.needBundleNames,
+            [
+                "scenario/background/bg_a000101",
+                "scenario/background/bg_a000102"
+            ]
+        );
+        assert_eq!(
+            scenario.includeSoundDataBundleNames,
+            [
+                "sound/scenario/bgm/bgm00012",
+                "sound/scenario/se/se_pack00001"
+            ]
+        );
+    }
 }
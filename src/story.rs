@@ -0,0 +1,411 @@
+use std::{
+    collections::HashMap,
+    fs::{self, File, create_dir_all},
+    io::Cursor,
+    path::{Path, PathBuf},
+};
+
+use anyhow::{Context, Result, anyhow, bail};
+use base64::prelude::*;
+use image::{DynamicImage, load_from_memory};
+use log::{error, info, warn};
+
+use crate::{
+    assetbundle::{
+        generate_episode_images, generate_logo, generate_screen_image, load_assetbundle_info,
+    },
+    encrypt,
+    mods::{
+        CacheInvalidDuration, InvalidateCacheEntry, MOD_SCHEMA_VERSION, ModData, ModMetadata,
+        ModType, load_mods,
+    },
+    scenario::{
+        CustomStory, CustomStoryFile, SCENARIO_TEMPLATE_PATH, create_assetbundle,
+        episode_scenario_id, load_scenario_typetree,
+    },
+    utils::{ABInfoRoot, Config},
+};
+
+/// Not downloaded with the other templates, as only stories with episode title images need it.
+/// Any episode_image AssetBundle from the game works, but only holds the title textures of its own event.
+const EPISODE_IMAGE_TEMPLATE_PATH: &str = "assets/story/episode_image/episode_image";
+
+/// Where the source story of the mod saved as `mods/<mod_stem>.toml` is kept.
+/// Matches where .mmlpack archives extract source files to.
+pub fn source_story_path(mod_stem: &str) -> PathBuf {
+    Path::new("mods")
+        .join(mod_stem)
+        .join("source")
+        .join(format!("{mod_stem}.json"))
+}
+
+/// Saves a SEKAI-Stories story as the editable source of a mod, returning where it was saved.
+pub fn save_source_story(story: &CustomStory, mod_stem: &str) -> Result<String> {
+    let source_path = source_story_path(mod_stem);
+
+    if let Some(parent) = source_path.parent() {
+        create_dir_all(parent)?;
+    }
+
+    fs::write(
+        &source_path,
+        serde_json::to_string(story).context("Failed to serialize source story into JSON")?,
+    )
+    .with_context(|| format!("Could not write {}", source_path.display()))?;
+
+    Ok(source_path.display().to_string())
+}
+
+/// Compiles a SEKAI-Stories story into the scenario, screen_image, logo and (when it has episode title images) episode_image AssetBundles of a mod, returning the mod without saving it.
+/// Starts from the current templates in assets/, so rebuilding picks up template and asset updates.
+/// Every episode is its own scenario, but they all go into the one scenario AssetBundle of the event, as the game keeps every episode of an event in event_story/<event>/scenario.
+/// Blocking, as encoding the images and AssetBundles takes a while.
+pub fn build_story_mod(story: &CustomStory) -> Result<ModData> {
+    let mod_name = story.modpack_name.clone();
+    let mod_ab_path = format!("mods/{mod_name}.ab");
+
+    let target = &story.target;
+
+    let mut injected_assets = HashMap::new();
+    injected_assets.insert(target.scenario_resource_path(), mod_ab_path.clone());
+
+    let episode_scenes = story.episode_scenes();
+    let mut episodes = Vec::new();
+
+    for (episode, (scenes, _)) in episode_scenes.iter().enumerate() {
+        // Loads the template typetree which we will then modify
+        let mut scenario_typetree = load_scenario_typetree(Path::new(SCENARIO_TEMPLATE_PATH), None)
+            .map_err(|e| anyhow!("Failed to load typetree. Err: {e}"))?;
+
+        // Every episode needs its own scenario id, so later episodes count up from the template's if none was set
+        let first_id = target
+            .scenario_id
+            .clone()
+            .unwrap_or_else(|| scenario_typetree.m_Name.clone());
+
+        if target.scenario_id.is_some() || episode > 0 {
+            let scenario_id = episode_scenario_id(&first_id, episode);
+            scenario_typetree.m_Name = scenario_id.clone();
+            scenario_typetree.scenarioId = scenario_id;
+        }
+
+        info!(
+            "Generating episode {} as {}",
+            episode + 1,
+            scenario_typetree.m_Name
+        );
+        scenario_typetree.generate_story_assetbundle(&mod_name, scenes, Path::new("assets"));
+
+        episodes.push(scenario_typetree);
+    }
+
+    let mut modpack = ModData {
+        schema_version: MOD_SCHEMA_VERSION,
+        mod_name: mod_name.clone(),
+        enabled: true,
+        mod_type: ModType::Story(episodes),
+        invalidated_assets: Vec::new(),
+        injected_assets,
+        priority: 0,
+        metadata: Default::default(),
+        dependencies: Vec::new(),
+        source: None,
+    };
+
+    info!("Creating associated AssetBundles...");
+
+    let banner_image = decode_image(&story.banner_image, "Banner image");
+    let story_background = decode_image(&story.story_background, "Story background");
+    let title_background = decode_image(&story.title_background, "Title background");
+
+    // Copy template and generate new screen_image assetbundle in place of the copied original assetbundle
+    let screen_image_path = format!("mods/{mod_name}-screenImage.ab");
+    fs::copy("assets/story/screen_image/screen_image", &screen_image_path)
+        .context("Could not copy screen_image template")?;
+
+    modpack.invalidated_assets.push(InvalidateCacheEntry {
+        resource_path: target.screen_image_resource_path(),
+        duration: CacheInvalidDuration::PermanentlyInvalid,
+    });
+
+    modpack.injected_assets.insert(
+        target.screen_image_resource_path(),
+        screen_image_path.clone(),
+    );
+
+    info!("Generating screen image");
+    match generate_screen_image(
+        Path::new(&screen_image_path),
+        banner_image,
+        story_background,
+        title_background,
+    ) {
+        Ok(_) => encrypt_in_place(&screen_image_path),
+        Err(e) => error!("Failed to generate screen_image! Default will be used. Err: {e}"),
+    };
+
+    // Copy template and generate new logo assetbundle in place of the copied original assetbundle
+    let logo_ab_path = format!("mods/{mod_name}-logo.ab");
+    fs::copy("assets/event/logo/logo", &logo_ab_path).context("Could not copy logo template")?;
+
+    modpack.invalidated_assets.push(InvalidateCacheEntry {
+        resource_path: target.logo_resource_path(),
+        duration: CacheInvalidDuration::PermanentlyInvalid,
+    });
+
+    modpack
+        .injected_assets
+        .insert(target.logo_resource_path(), logo_ab_path.clone());
+
+    let logo = png_from_base64_str(&story.logo)
+        .map_err(|e| anyhow!("Logo image provided is not an valid image! Err: {e}"))?;
+
+    if let Some(logo) = logo {
+        info!("Generating logo AssetBundle");
+        if let Err(e) = generate_logo(Path::new(&logo_ab_path), logo) {
+            error!("Failed to generate logo! Default will be used. Err: {e}");
+        }
+    }
+
+    // Encrypt logo AssetBundle, be it the template or newly generated AssetBundle
+    encrypt_in_place(&logo_ab_path);
+
+    let episode_image_names: Vec<String> = (0..episode_scenes.len())
+        .map(|episode| target.episode_image_name(episode))
+        .collect();
+    let episode_images: Vec<(&str, DynamicImage)> = episode_scenes
+        .iter()
+        .zip(&episode_image_names)
+        .filter_map(|((_, title_image), name)| {
+            let image = decode_image(&title_image.cloned(), "Episode title image")?;
+            Some((name.as_str(), image))
+        })
+        .collect();
+
+    if !episode_images.is_empty() {
+        if Path::new(EPISODE_IMAGE_TEMPLATE_PATH).exists() {
+            // Copy template and generate new episode_image assetbundle in place of the copied original assetbundle
+            let episode_image_path = format!("mods/{mod_name}-episodeImage.ab");
+            fs::copy(EPISODE_IMAGE_TEMPLATE_PATH, &episode_image_path)
+                .context("Could not copy episode_image template")?;
+
+            modpack.invalidated_assets.push(InvalidateCacheEntry {
+                resource_path: target.episode_image_resource_path(),
+                duration: CacheInvalidDuration::PermanentlyInvalid,
+            });
+
+            modpack.injected_assets.insert(
+                target.episode_image_resource_path(),
+                episode_image_path.clone(),
+            );
+
+            info!("Generating episode images");
+            match generate_episode_images(Path::new(&episode_image_path), episode_images) {
+                Ok(_) => encrypt_in_place(&episode_image_path),
+                Err(e) => {
+                    error!("Failed to generate episode images! Defaults will be used. Err: {e}")
+                }
+            };
+        } else {
+            warn!(
+                "Episode title images need an episode_image AssetBundle of the target event at {EPISODE_IMAGE_TEMPLATE_PATH}, so the event's own images will be used"
+            );
+        }
+    }
+
+    info!("Creating scenario");
+    create_assetbundle(&modpack, Some(PathBuf::from(&mod_ab_path)), true)
+        .map_err(|e| anyhow!("Failed to convert modpack to AssetBundle: {e}"))?;
+
+    Ok(modpack)
+}
+
+/// Compiles a story into a new mod saved as mods/<file_name>.toml along with its source story, returning where the mod was saved.
+/// Fails before building anything if the target event or a sound the story plays doesn't exist in the assetbundle info.
+/// Injections and the assetbundle info still have to be reloaded for the mod to take effect.
+/// Blocking, as encoding the images and AssetBundles takes a while.
+pub fn export_story_mod(
+    story: &CustomStory,
+    config: &Config,
+    asset_version: &str,
+) -> Result<PathBuf> {
+    let abinfo = load_assetbundle_info(config, asset_version)
+        .context("Could not load the assetbundle info to check the target event against")?;
+    story.verify(&abinfo)?;
+
+    let mod_stem = story.file_name.trim_end_matches(".toml");
+    let mod_path = Path::new("mods").join(format!("{mod_stem}.toml"));
+
+    if mod_path.exists() {
+        bail!(
+            "{} already exists! Please rename your file.",
+            mod_path.display()
+        );
+    }
+
+    create_dir_all("mods").context("Could not create mods dir")?;
+
+    let mut modpack = build_story_mod(story)?;
+
+    // Kept so the story can be edited and rebuilt later, once it is known to build
+    let source = save_source_story(story, mod_stem).context("Failed to save source story")?;
+
+    // Generated AssetBundles target the resource paths of the server and platform they were built against
+    modpack.metadata = ModMetadata {
+        regions: vec![config.region.clone()],
+        platforms: vec![config.platform.clone()],
+        ..Default::default()
+    };
+    modpack.source = Some(source);
+
+    fs::write(
+        &mod_path,
+        toml::to_string_pretty(&modpack).context("Failed to serialize modpack into TOML")?,
+    )
+    .with_context(|| format!("Could not write to {}", mod_path.display()))?;
+
+    info!(
+        "Successfully generated modpack! It was placed in {}",
+        mod_path.display()
+    );
+
+    Ok(mod_path)
+}
+
+/// Recompiles every story mod that kept its source story, keeping everything the user set on the mod.
+/// Returns the names of the rebuilt mods, and fails if any mod could not be rebuilt.
+/// Sources are checked against the assetbundle info like on export, so a mod whose event or sounds are gone is left as it was.
+/// Blocking, as encoding the images and AssetBundles takes a while.
+pub fn rebuild_story_mods(config: &Config, asset_version: &str) -> Result<Vec<String>> {
+    let abinfo = load_assetbundle_info(config, asset_version)
+        .context("Could not load the assetbundle info to check the target events against")?;
+
+    let mut rebuilt = Vec::new();
+    let mut failed = Vec::new();
+
+    for (path, mod_data) in load_mods() {
+        let Some(source) = &mod_data.source else {
+            warn!(
+                "{} has no source story, so it can't be rebuilt",
+                mod_data.mod_name
+            );
+            continue;
+        };
+
+        info!("Rebuilding {} from {source}", mod_data.mod_name);
+
+        match rebuild_story_mod(&path, mod_data, &abinfo) {
+            Ok(mod_name) => rebuilt.push(mod_name),
+            Err(e) => {
+                error!("Failed to rebuild {}: {e:#}", path.display());
+                failed.push(path.display().to_string());
+            }
+        }
+    }
+
+    if !failed.is_empty() {
+        bail!("Failed to rebuild {}", failed.join(", "));
+    }
+
+    Ok(rebuilt)
+}
+
+fn rebuild_story_mod(path: &Path, old: ModData, abinfo: &ABInfoRoot) -> Result<String> {
+    let source = old.source.clone().unwrap_or_default();
+
+    let story: CustomStory = serde_json::from_reader(
+        File::open(&source).with_context(|| format!("Could not read source story {source}"))?,
+    )
+    .with_context(|| format!("{source} is not a valid SEKAI-Stories story"))?;
+    story.verify(abinfo)?;
+
+    let mut rebuilt = build_story_mod(&story)?;
+
+    // Anything the user added by hand that the build doesn't generate is kept
+    for entry in old.invalidated_assets {
+        if !rebuilt
+            .invalidated_assets
+            .iter()
+            .any(|rebuilt_entry| rebuilt_entry.resource_path == entry.resource_path)
+        {
+            rebuilt.invalidated_assets.push(entry);
+        }
+    }
+
+    for (resource_path, local_path) in old.injected_assets {
+        rebuilt
+            .injected_assets
+            .entry(resource_path)
+            .or_insert(local_path);
+    }
+
+    rebuilt.enabled = old.enabled;
+    rebuilt.priority = old.priority;
+    rebuilt.metadata = old.metadata;
+    rebuilt.dependencies = old.dependencies;
+    rebuilt.source = old.source;
+
+    fs::write(
+        path,
+        toml::to_string_pretty(&rebuilt).context("Failed to serialize modpack into TOML")?,
+    )
+    .with_context(|| format!("Could not write to {}", path.display()))?;
+
+    Ok(rebuilt.mod_name)
+}
+
+/// Converts a scenario from a decrypted scenario AssetBundle into a story file the custom-story page can load, returning how many scenes it has.
+/// Scenario bundles from the game hold one scenario per episode, `name` picks which one.
+pub fn import_scenario(scenario_path: &Path, name: Option<&str>, output: &Path) -> Result<usize> {
+    let scenario = load_scenario_typetree(scenario_path, name)
+        .with_context(|| format!("Could not load a scenario from {}", scenario_path.display()))?;
+
+    info!("Importing {}", scenario.m_Name);
+
+    let story = CustomStoryFile {
+        scenes_data: scenario.to_sekai_stories_scenes()?,
+    };
+
+    fs::write(
+        output,
+        serde_json::to_string(&story).context("Failed to serialize story into JSON")?,
+    )
+    .with_context(|| format!("Could not write {}", output.display()))?;
+
+    Ok(story.scenes_data.len())
+}
+
+fn encrypt_in_place(assetbundle_path: &str) {
+    info!("Encrypting new AssetBundle {assetbundle_path}");
+
+    let assetbundle_path = Path::new(assetbundle_path);
+    match encrypt(assetbundle_path, assetbundle_path) {
+        Ok(_) => {
+            info!("Encrypted AssetBundle")
+        }
+        Err(e) => {
+            error!("Could not encrypt {}: {}", assetbundle_path.display(), e)
+        }
+    };
+}
+
+/// Decodes an optional base64 image, logging and ignoring invalid images.
+fn decode_image(base64: &Option<String>, description: &str) -> Option<DynamicImage> {
+    png_from_base64_str(base64).unwrap_or_else(|e| {
+        error!("{description} provided is not an valid image! Err: {e}");
+        None
+    })
+}
+
+fn png_from_base64_str(base64: &Option<String>) -> Result<Option<DynamicImage>> {
+    if let Some(base64) = base64 {
+        let image_bytes = BASE64_STANDARD.decode(base64.as_bytes())?;
+        let img = load_from_memory(&image_bytes)?;
+
+        let mut image_buffer = Cursor::new(Vec::new());
+        img.write_to(&mut image_buffer, image::ImageFormat::Png)?;
+        Ok(Some(load_from_memory(&image_buffer.into_inner())?))
+    } else {
+        Ok(None)
+    }
+}
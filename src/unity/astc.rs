@@ -0,0 +1,290 @@
+use image::RgbaImage;
+
+use super::texture::for_each_block;
+
+/// Block mode of a 4x4 grid of 2 bit weights, on a single plane.
+/// With one partition this leaves enough room for every endpoint to be stored as a full 8 bit value.
+const BLOCK_MODE: u128 = 0x042;
+/// Color endpoint mode of LDR RGBA endpoints stored directly.
+const CEM_LDR_RGBA_DIRECT: u128 = 12;
+
+const GRID_SIZE: usize = 4;
+const WEIGHT_MAX: f32 = 3.0;
+
+/// Marks a block as a single color, with the texture coordinates it covers left unset.
+const VOID_EXTENT_LDR: u128 = 0x1FC | 0b11 << 10 | ((1 << 52) - 1) << 12;
+
+/// Encodes an image as LDR ASTC with the given block size.
+/// Each block is a single line between two colors, which is a lot simpler than what Unity's encoder does, but looks fine for story images.
+pub fn encode_astc(image: &RgbaImage, block_width: usize, block_height: usize) -> Vec<u8> {
+    let infill = infill_factors(block_width, block_height);
+    let mut output = Vec::new();
+
+    for_each_block(image, block_width, block_height, |texels| {
+        output.extend_from_slice(&encode_block(texels, &infill).to_le_bytes());
+    });
+
+    output
+}
+
+fn encode_block(texels: &[[u8; 4]], infill: &[[(usize, f32); 4]]) -> u128 {
+    if texels.iter().all(|texel| *texel == texels[0]) {
+        let mut bits = VOID_EXTENT_LDR;
+        for (channel, value) in texels[0].iter().enumerate() {
+            bits |= (*value as u128 * 257) << (64 + channel * 16);
+        }
+        return bits;
+    }
+
+    let (mut low, mut high) = bounding_box(texels);
+
+    // The decoder applies blue contraction when the second endpoint is darker, so always make it the brighter one
+    if high[..3].iter().map(|c| *c as u32).sum::<u32>()
+        < low[..3].iter().map(|c| *c as u32).sum::<u32>()
+    {
+        (low, high) = (high, low);
+    }
+
+    let axis: [f32; 4] = std::array::from_fn(|c| high[c] as f32 - low[c] as f32);
+    let length = axis.iter().map(|value| value * value).sum::<f32>();
+
+    let ideal: Vec<f32> = texels
+        .iter()
+        .map(|texel| {
+            let projection: f32 = (0..4)
+                .map(|c| (texel[c] as f32 - low[c] as f32) * axis[c])
+                .sum();
+            (projection / length).clamp(0.0, 1.0)
+        })
+        .collect();
+
+    // Each grid weight is the average of the texels it is spread over
+    let mut totals = [0.0f32; GRID_SIZE * GRID_SIZE];
+    let mut factors = [0.0f32; GRID_SIZE * GRID_SIZE];
+    for (texel, contributions) in infill.iter().enumerate() {
+        for (grid_index, factor) in contributions {
+            totals[*grid_index] += ideal[texel] * factor;
+            factors[*grid_index] += factor;
+        }
+    }
+
+    let mut bits = BLOCK_MODE | CEM_LDR_RGBA_DIRECT << 13;
+
+    for (channel, (low, high)) in low.iter().zip(high).enumerate() {
+        bits |= (*low as u128) << (17 + channel * 16);
+        bits |= (high as u128) << (25 + channel * 16);
+    }
+
+    for (grid_index, (total, factor)) in totals.iter().zip(factors).enumerate() {
+        let weight = if factor > 0.0 {
+            (total / factor * WEIGHT_MAX).round() as u128
+        } else {
+            0
+        };
+
+        // Weights are stored backwards from the top of the block
+        for bit in 0..2 {
+            if weight >> bit & 1 != 0 {
+                bits |= 1 << (127 - (grid_index * 2 + bit));
+            }
+        }
+    }
+
+    bits
+}
+
+/// Per channel minimum and maximum, with channels that fall as red rises swapped so the line follows the colors.
+fn bounding_box(texels: &[[u8; 4]]) -> ([u8; 4], [u8; 4]) {
+    let mut low = [255u8; 4];
+    let mut high = [0u8; 4];
+    for texel in texels {
+        for c in 0..4 {
+            low[c] = low[c].min(texel[c]);
+            high[c] = high[c].max(texel[c]);
+        }
+    }
+
+    let reference = (0..4).max_by_key(|c| high[*c] - low[*c]).unwrap();
+    let count = texels.len() as f32;
+    let mean: [f32; 4] =
+        std::array::from_fn(|c| texels.iter().map(|texel| texel[c] as f32).sum::<f32>() / count);
+
+    for c in (0..4).filter(|c| *c != reference) {
+        let covariance: f32 = texels
+            .iter()
+            .map(|texel| (texel[c] as f32 - mean[c]) * (texel[reference] as f32 - mean[reference]))
+            .sum();
+
+        if covariance < 0.0 {
+            std::mem::swap(&mut low[c], &mut high[c]);
+        }
+    }
+
+    (low, high)
+}
+
+/// For every texel of a block, the grid weights the decoder blends into its weight and how much of each it uses.
+/// Follows the weight infill procedure of the ASTC specification.
+fn infill_factors(block_width: usize, block_height: usize) -> Vec<[(usize, f32); 4]> {
+    let scale = |size: usize| (1024 + size / 2) / (size - 1);
+    let (scale_s, scale_t) = (scale(block_width), scale(block_height));
+
+    let mut factors = Vec::new();
+
+    for t in 0..block_height {
+        for s in 0..block_width {
+            let gs = (scale_s * s * (GRID_SIZE - 1) + 32) >> 6;
+            let gt = (scale_t * t * (GRID_SIZE - 1) + 32) >> 6;
+            let (js, fs) = (gs >> 4, gs & 0xF);
+            let (jt, ft) = (gt >> 4, gt & 0xF);
+
+            let w11 = (fs * ft + 8) >> 4;
+            let w10 = ft - w11;
+            let w01 = fs - w11;
+            let w00 = 16 + w11 - fs - ft;
+
+            let v0 = js + jt * GRID_SIZE;
+            // Grid weights past the edge are never used, as their factor is always 0
+            let clamp = |index: usize| index.min(GRID_SIZE * GRID_SIZE - 1);
+
+            factors.push([
+                (v0, w00 as f32 / 16.0),
+                (clamp(v0 + 1), w01 as f32 / 16.0),
+                (clamp(v0 + GRID_SIZE), w10 as f32 / 16.0),
+                (clamp(v0 + GRID_SIZE + 1), w11 as f32 / 16.0),
+            ]);
+        }
+    }
+
+    factors
+}
+
+#[cfg(test)]
+mod tests {
+    use image::Rgba;
+
+    use super::*;
+
+    /// Decodes an LDR block that is either a void extent block or uses a single partition with direct RGBA endpoints,
+    /// following the ASTC specification. Fails on anything else.
+    fn decode_block(bits: u128, block_width: usize, block_height: usize) -> Vec<[u8; 4]> {
+        let field = |shift: u32, width: u32| (bits >> shift & ((1 << width) - 1)) as usize;
+
+        if field(0, 9) == 0x1FC {
+            assert_eq!(field(9, 3), 0b110, "not an LDR void extent block");
+            let color: [u8; 4] =
+                std::array::from_fn(|c| (field(64 + c as u32 * 16, 16) >> 8) as u8);
+            return vec![color; block_width * block_height];
+        }
+
+        // Block mode with a low bit set: D H B B A A R0 0 0 R2 R1, for a (B + 4) x (A + 2) weight grid
+        assert_ne!(field(0, 2), 0, "unexpected block mode layout");
+        assert_eq!(field(2, 2), 0, "unexpected block mode layout");
+        assert_eq!(field(9, 2), 0, "dual plane or high precision weights");
+        let range = field(4, 1) | field(0, 2) << 1;
+        assert_eq!(range, 4, "weights aren't in the range 0..=3");
+        let (grid_width, grid_height) = (field(7, 2) + 4, field(5, 2) + 2);
+
+        assert_eq!(field(11, 2), 0, "more than one partition");
+        assert_eq!(field(13, 4), 12, "endpoints aren't LDR RGBA direct");
+
+        // 79 bits are left for the 8 endpoint values, so they get the full 8 bits each
+        let values: [usize; 8] = std::array::from_fn(|i| field(17 + i as u32 * 8, 8));
+        let (low, high): ([usize; 4], [usize; 4]) = (
+            std::array::from_fn(|c| values[c * 2]),
+            std::array::from_fn(|c| values[c * 2 + 1]),
+        );
+        assert!(
+            high[..3].iter().sum::<usize>() >= low[..3].iter().sum::<usize>(),
+            "endpoints would be blue contracted"
+        );
+
+        let reversed = bits.reverse_bits();
+        let grid: Vec<usize> = (0..grid_width * grid_height)
+            .map(|i| {
+                let weight = (reversed >> (i * 2) & 3) as usize;
+                let weight = weight << 4 | weight << 2 | weight;
+                weight + (weight > 32) as usize
+            })
+            .collect();
+
+        let scale = |size: usize| (1024 + size / 2) / (size - 1);
+        let mut texels = Vec::new();
+
+        for t in 0..block_height {
+            for s in 0..block_width {
+                let gs = (scale(block_width) * s * (grid_width - 1) + 32) >> 6;
+                let gt = (scale(block_height) * t * (grid_height - 1) + 32) >> 6;
+                let (js, fs, jt, ft) = (gs >> 4, gs & 0xF, gt >> 4, gt & 0xF);
+                let w11 = (fs * ft + 8) >> 4;
+                let grid_weight =
+                    |x: usize, y: usize| grid.get(y * grid_width + x).copied().unwrap_or_default();
+
+                let weight = (grid_weight(js, jt) * (16 + w11 - fs - ft)
+                    + grid_weight(js + 1, jt) * (fs - w11)
+                    + grid_weight(js, jt + 1) * (ft - w11)
+                    + grid_weight(js + 1, jt + 1) * w11
+                    + 8)
+                    >> 4;
+
+                texels.push(std::array::from_fn(|c| {
+                    let color = (low[c] * 257 * (64 - weight) + high[c] * 257 * weight + 32) / 64;
+                    (color >> 8) as u8
+                }));
+            }
+        }
+
+        texels
+    }
+
+    /// Encodes `image`, a single block, and decodes it again.
+    fn round_trip(image: &RgbaImage, block_size: usize) -> Vec<[u8; 4]> {
+        let data = encode_astc(image, block_size, block_size);
+        assert_eq!(data.len(), 16);
+
+        decode_block(
+            u128::from_le_bytes(data.try_into().unwrap()),
+            block_size,
+            block_size,
+        )
+    }
+
+    fn max_error(decoded: &[[u8; 4]], image: &RgbaImage) -> u8 {
+        decoded
+            .iter()
+            .zip(image.pixels())
+            .flat_map(|(a, b)| a.iter().zip(b.0).map(|(a, b)| a.abs_diff(b)))
+            .max()
+            .unwrap()
+    }
+
+    #[test]
+    fn solid_color_is_a_void_extent_block() {
+        let image = RgbaImage::from_pixel(6, 6, Rgba([12, 34, 56, 78]));
+        let data = encode_astc(&image, 6, 6);
+
+        assert_eq!(data[..8], [0xFC, 0xFD, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF]);
+        assert_eq!(data[8..], [12, 12, 34, 34, 56, 56, 78, 78]);
+        assert_eq!(round_trip(&image, 6), vec![[12, 34, 56, 78]; 36]);
+    }
+
+    #[test]
+    fn gradient_stays_close() {
+        for block_size in [4, 6, 8] {
+            let step = (255 / (block_size - 1)) as u8;
+            let image = RgbaImage::from_fn(block_size as u32, block_size as u32, |x, _| {
+                Rgba([x as u8 * step, 255 - x as u8 * step, 128, 255])
+            });
+
+            let error = max_error(&round_trip(&image, block_size), &image);
+            assert!(error <= 4, "{block_size}x{block_size} is off by {error}");
+        }
+    }
+
+    #[test]
+    fn alpha_gradient_stays_close() {
+        let image = RgbaImage::from_fn(4, 4, |_, y| Rgba([255, 255, 255, y as u8 * 85]));
+
+        assert!(max_error(&round_trip(&image, 4), &image) <= 2);
+    }
+}
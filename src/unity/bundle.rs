@@ -0,0 +1,440 @@
+use std::{fs, io::Cursor, path::Path};
+
+use anyhow::{Context, Result, anyhow, bail};
+use log::debug;
+
+use super::{reader::EndianReader, writer::EndianWriter};
+
+/// Header magic of AssetBundles encrypted by the game.
+const SEKAI_MAGIC: [u8; 4] = [0x10, 0x00, 0x00, 0x00];
+
+/// Lower bits of the archive and block flags, holding the compression used.
+const COMPRESSION_MASK: u32 = 0x3F;
+/// Blocks info is stored at the end of the file instead of after the header.
+const BLOCKS_INFO_AT_END: u32 = 0x80;
+/// Block data starts on a 16 byte boundary after the blocks info.
+const BLOCK_INFO_NEEDS_PADDING: u32 = 0x200;
+
+const COMPRESSION_NONE: u32 = 0;
+const COMPRESSION_LZMA: u32 = 1;
+const COMPRESSION_LZ4: u32 = 2;
+const COMPRESSION_LZ4HC: u32 = 3;
+
+/// Size Unity splits the data of chunk compressed bundles into.
+const DEFAULT_BLOCK_SIZE: usize = 0x20000;
+
+/// LZ4 can't shrink data to less than about 1/255th of its size.
+const LZ4_MAX_RATIO: usize = 256;
+
+/// A file stored inside an AssetBundle, such as a SerializedFile (CAB-...) or its texture data (CAB-....resS).
+pub struct BundleNode {
+    pub path: String,
+    pub data: Vec<u8>,
+    flags: u32,
+    /// Where the node was in the block data it was read from, and how large it was
+    original_offset: usize,
+    original_size: usize,
+}
+
+/// A block as it was read, so blocks whose data didn't change can be written back untouched.
+struct Block {
+    uncompressed_size: usize,
+    flags: u16,
+    compressed: Vec<u8>,
+}
+
+/// A parsed UnityFS AssetBundle, with every block already decompressed.
+pub struct UnityBundle {
+    format_version: u32,
+    unity_version: String,
+    unity_revision: String,
+    flags: u32,
+    data_hash: Vec<u8>,
+    blocks_info: Vec<u8>,
+    compressed_blocks_info: Vec<u8>,
+    blocks: Vec<Block>,
+    /// Every block decompressed and joined, as it was read
+    block_data: Vec<u8>,
+    pub nodes: Vec<BundleNode>,
+}
+
+impl UnityBundle {
+    /// Reads an AssetBundle from disk, decrypting it first if it is encrypted.
+    pub fn open(path: &Path) -> Result<UnityBundle> {
+        let data = fs::read(path).with_context(|| format!("Could not read {}", path.display()))?;
+
+        UnityBundle::from_bytes(decrypt_bundle(data))
+            .with_context(|| format!("{} is not a valid AssetBundle", path.display()))
+    }
+
+    /// Parses an unencrypted UnityFS AssetBundle.
+    pub fn from_bytes(data: Vec<u8>) -> Result<UnityBundle> {
+        let mut reader = EndianReader::new(&data, true);
+
+        let signature = reader.read_cstring()?;
+        if signature != "UnityFS" {
+            bail!("Unsupported AssetBundle signature {signature}, only UnityFS is supported");
+        }
+
+        let format_version = reader.read_u32()?;
+        let unity_version = reader.read_cstring()?;
+        let unity_revision = reader.read_cstring()?;
+        let _size = reader.read_i64()?;
+        let compressed_blocks_info_size = reader.read_u32()? as usize;
+        let uncompressed_blocks_info_size = reader.read_u32()? as usize;
+        let flags = reader.read_u32()?;
+
+        debug!("UnityFS version {format_version}, Unity {unity_revision}, flags {flags:#x}");
+
+        if format_version >= 7 {
+            reader.align(16);
+        }
+
+        let compressed_blocks_info = if flags & BLOCKS_INFO_AT_END != 0 {
+            let start = data
+                .len()
+                .checked_sub(compressed_blocks_info_size)
+                .context("Blocks info is larger than the AssetBundle")?;
+            &data[start..]
+        } else {
+            reader.read_bytes(compressed_blocks_info_size)?
+        };
+
+        let blocks_info = decompress(
+            compressed_blocks_info,
+            uncompressed_blocks_info_size,
+            flags & COMPRESSION_MASK,
+        )
+        .context("Could not decompress blocks info")?;
+
+        if flags & BLOCK_INFO_NEEDS_PADDING != 0 {
+            reader.align(16);
+        }
+
+        let mut info_reader = EndianReader::new(&blocks_info, true);
+        // Hash of the uncompressed data, which Unity doesn't check
+        let data_hash = info_reader.read_bytes(16)?.to_vec();
+
+        let block_count = info_reader.read_i32()?;
+        let mut blocks = Vec::new();
+        let mut block_data = Vec::new();
+
+        for _ in 0..block_count {
+            let uncompressed_size = info_reader.read_u32()? as usize;
+            let compressed_size = info_reader.read_u32()? as usize;
+            let block_flags = info_reader.read_u16()?;
+
+            let compressed = reader.read_bytes(compressed_size)?;
+            block_data.extend(decompress(
+                compressed,
+                uncompressed_size,
+                block_flags as u32 & COMPRESSION_MASK,
+            )?);
+
+            blocks.push(Block {
+                uncompressed_size,
+                flags: block_flags,
+                compressed: compressed.to_vec(),
+            });
+        }
+
+        let node_count = info_reader.read_i32()?;
+        let mut nodes = Vec::new();
+
+        for _ in 0..node_count {
+            let offset = info_reader.read_i64()?;
+            let size = info_reader.read_i64()?;
+            let node_flags = info_reader.read_u32()?;
+            let path = info_reader.read_cstring()?;
+
+            let (offset, size, node_data) = usize::try_from(offset)
+                .ok()
+                .zip(usize::try_from(size).ok())
+                .and_then(|(offset, size)| {
+                    let data = block_data.get(offset..offset.checked_add(size)?)?;
+                    Some((offset, size, data.to_vec()))
+                })
+                .with_context(|| format!("{path} lies outside of the AssetBundle data"))?;
+
+            debug!("Found {path} ({size} bytes)");
+            nodes.push(BundleNode {
+                path,
+                data: node_data,
+                flags: node_flags,
+                original_offset: offset,
+                original_size: size,
+            });
+        }
+
+        Ok(UnityBundle {
+            format_version,
+            unity_version,
+            unity_revision,
+            flags,
+            data_hash,
+            compressed_blocks_info: compressed_blocks_info.to_vec(),
+            blocks_info,
+            blocks,
+            block_data,
+            nodes,
+        })
+    }
+
+    /// Repacks the bundle, unencrypted.
+    /// Blocks whose data didn't change are written back as they were read, so an unmodified bundle comes out byte for byte identical.
+    /// Changed blocks are compressed with LZ4, as Unity's LZMA and LZ4HC output can't be reproduced.
+    pub fn to_bytes(&self) -> Result<Vec<u8>> {
+        let (block_data, node_offsets) = self.layout_nodes();
+
+        // (uncompressed size, flags, compressed data) of each block
+        let mut blocks = Vec::new();
+        let mut position = 0;
+
+        for original in &self.blocks {
+            if position >= block_data.len() {
+                break;
+            }
+
+            let end = (position + original.uncompressed_size).min(block_data.len());
+            let chunk = &block_data[position..end];
+
+            if chunk.len() == original.uncompressed_size
+                && self.block_data.get(position..end) == Some(chunk)
+            {
+                blocks.push((chunk.len(), original.flags, original.compressed.clone()));
+            } else {
+                blocks.push(compress_block(chunk, original.flags));
+            }
+
+            position = end;
+        }
+
+        // Data that grew goes into new blocks like the last one
+        let last_flags = self
+            .blocks
+            .last()
+            .map(|block| block.flags)
+            .unwrap_or_default();
+        for chunk in block_data[position..].chunks(DEFAULT_BLOCK_SIZE) {
+            blocks.push(compress_block(chunk, last_flags));
+        }
+
+        let mut info = EndianWriter::new(true);
+        info.write_bytes(&self.data_hash);
+        info.write_i32(blocks.len() as i32);
+        for (uncompressed_size, flags, compressed) in &blocks {
+            info.write_u32(*uncompressed_size as u32);
+            info.write_u32(compressed.len() as u32);
+            info.write_u16(*flags);
+        }
+
+        info.write_i32(self.nodes.len() as i32);
+        for (node, offset) in self.nodes.iter().zip(node_offsets) {
+            info.write_i64(offset as i64);
+            info.write_i64(node.data.len() as i64);
+            info.write_u32(node.flags);
+            info.write_cstring(&node.path);
+        }
+
+        let mut flags = self.flags;
+        let compressed_blocks_info = if info.data == self.blocks_info {
+            self.compressed_blocks_info.clone()
+        } else {
+            let (compression, compressed) = compress(&info.data, flags & COMPRESSION_MASK);
+            flags = flags & !COMPRESSION_MASK | compression;
+            compressed
+        };
+
+        let mut writer = EndianWriter::new(true);
+        writer.write_cstring("UnityFS");
+        writer.write_u32(self.format_version);
+        writer.write_cstring(&self.unity_version);
+        writer.write_cstring(&self.unity_revision);
+        let size_position = writer.data.len();
+        writer.write_i64(0); // Filled in once everything is written
+        writer.write_u32(compressed_blocks_info.len() as u32);
+        writer.write_u32(info.data.len() as u32);
+        writer.write_u32(flags);
+
+        if self.format_version >= 7 {
+            writer.align(16);
+        }
+
+        if flags & BLOCKS_INFO_AT_END == 0 {
+            writer.write_bytes(&compressed_blocks_info);
+        }
+
+        if flags & BLOCK_INFO_NEEDS_PADDING != 0 {
+            writer.align(16);
+        }
+
+        for (_, _, compressed) in &blocks {
+            writer.write_bytes(compressed);
+        }
+
+        if flags & BLOCKS_INFO_AT_END != 0 {
+            writer.write_bytes(&compressed_blocks_info);
+        }
+
+        let size = writer.data.len() as i64;
+        writer.data[size_position..size_position + 8].copy_from_slice(&size.to_be_bytes());
+
+        Ok(writer.data)
+    }
+
+    /// Joins the data of every node back together, returning it with the new offset of each node.
+    fn layout_nodes(&self) -> (Vec<u8>, Vec<usize>) {
+        // Nodes that kept their size can go right back where they were
+        if self
+            .nodes
+            .iter()
+            .all(|node| node.data.len() == node.original_size)
+        {
+            let mut block_data = self.block_data.clone();
+            for node in &self.nodes {
+                block_data[node.original_offset..node.original_offset + node.original_size]
+                    .copy_from_slice(&node.data);
+            }
+
+            let offsets = self.nodes.iter().map(|node| node.original_offset).collect();
+            return (block_data, offsets);
+        }
+
+        let mut order: Vec<usize> = (0..self.nodes.len()).collect();
+        order.sort_by_key(|i| self.nodes[*i].original_offset);
+
+        let mut block_data = Vec::new();
+        let mut offsets = vec![0; self.nodes.len()];
+
+        for i in order {
+            offsets[i] = block_data.len();
+            block_data.extend_from_slice(&self.nodes[i].data);
+        }
+
+        (block_data, offsets)
+    }
+
+    /// Stores `data` in the resource node named `name`, creating it if the bundle doesn't have one, and returns the offset it was stored at.
+    /// `replacing` is overwritten in place if the new data is the same size, otherwise the data is appended.
+    pub fn write_resource(
+        &mut self,
+        name: &str,
+        replacing: Option<(usize, usize)>,
+        data: &[u8],
+    ) -> usize {
+        let index = match self
+            .nodes
+            .iter()
+            .position(|node| node.path.rsplit('/').next() == Some(name))
+        {
+            Some(index) => index,
+            None => {
+                self.nodes.push(BundleNode {
+                    path: name.to_string(),
+                    data: Vec::new(),
+                    flags: 0,
+                    original_offset: self.block_data.len(),
+                    original_size: 0,
+                });
+                self.nodes.len() - 1
+            }
+        };
+        let node = &mut self.nodes[index];
+
+        if let Some((offset, size)) = replacing
+            && size == data.len()
+            && offset
+                .checked_add(size)
+                .is_some_and(|end| end <= node.data.len())
+        {
+            node.data[offset..offset + size].copy_from_slice(data);
+            return offset;
+        }
+
+        // Unity keeps resources 16 byte aligned
+        node.data.resize(node.data.len().next_multiple_of(16), 0);
+        let offset = node.data.len();
+        node.data.extend_from_slice(data);
+        offset
+    }
+
+    /// Returns the node whose file name is `name`, such as the one referenced by `archive:/CAB-.../CAB-....resS`.
+    pub fn node(&self, name: &str) -> Option<&BundleNode> {
+        let name = name.rsplit('/').next().unwrap_or(name);
+
+        self.nodes
+            .iter()
+            .find(|node| node.path.rsplit('/').next() == Some(name))
+    }
+}
+
+/// Reverses the game's AssetBundle encryption, returning anything else untouched.
+/// All credit for figuring out decryption goes to https://github.com/mos9527
+pub fn decrypt_bundle(mut data: Vec<u8>) -> Vec<u8> {
+    if !data.starts_with(&SEKAI_MAGIC) {
+        return data;
+    }
+
+    data.drain(..SEKAI_MAGIC.len());
+
+    // The first 5 bytes of each of the first 16 8-byte blocks are inverted
+    for chunk in data.chunks_mut(8).take(16) {
+        for byte in chunk.iter_mut().take(5) {
+            *byte = !*byte;
+        }
+    }
+
+    data
+}
+
+fn decompress(data: &[u8], uncompressed_size: usize, compression: u32) -> Result<Vec<u8>> {
+    match compression {
+        COMPRESSION_NONE => Ok(data.to_vec()),
+        COMPRESSION_LZMA => {
+            // Unity stores the LZMA properties, but not the uncompressed size.
+            // The size comes from the file, so the output only grows as large as the data really decompresses to.
+            let mut output = Vec::with_capacity(uncompressed_size.min(DEFAULT_BLOCK_SIZE));
+            lzma_rs::lzma_decompress_with_options(
+                &mut Cursor::new(data),
+                &mut output,
+                &lzma_rs::decompress::Options {
+                    unpacked_size: lzma_rs::decompress::UnpackedSize::UseProvided(Some(
+                        uncompressed_size as u64,
+                    )),
+                    ..Default::default()
+                },
+            )
+            .map_err(|e| anyhow!("LZMA decompression failed: {e:?}"))?;
+            Ok(output)
+        }
+        COMPRESSION_LZ4 | COMPRESSION_LZ4HC => {
+            // LZ4 allocates the whole output up front, so refuse sizes it could never decompress to
+            if uncompressed_size > data.len().saturating_mul(LZ4_MAX_RATIO) {
+                bail!(
+                    "{} bytes of LZ4 data can't decompress to {uncompressed_size} bytes",
+                    data.len()
+                );
+            }
+
+            lz4_flex::block::decompress(data, uncompressed_size)
+                .map_err(|e| anyhow!("LZ4 decompression failed: {e}"))
+        }
+        other => bail!("Unsupported compression type {other}"),
+    }
+}
+
+/// Compresses a block the way a block with `flags` was, returning its (uncompressed size, flags, compressed data).
+fn compress_block(chunk: &[u8], flags: u16) -> (usize, u16, Vec<u8>) {
+    let (compression, compressed) = compress(chunk, flags as u32 & COMPRESSION_MASK);
+    let flags = flags as u32 & !COMPRESSION_MASK | compression;
+    (chunk.len(), flags as u16, compressed)
+}
+
+/// Compresses data that used `compression`, returning the compression actually used.
+/// Anything compressed ends up as LZ4, which the game loads just as well.
+fn compress(data: &[u8], compression: u32) -> (u32, Vec<u8>) {
+    match compression {
+        COMPRESSION_NONE => (COMPRESSION_NONE, data.to_vec()),
+        _ => (COMPRESSION_LZ4, lz4_flex::block::compress(data)),
+    }
+}
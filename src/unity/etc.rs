@@ -0,0 +1,363 @@
+use image::RgbaImage;
+
+use super::texture::for_each_block;
+
+/// Intensity modifiers of ETC1 color blocks, as (small, large) pairs.
+const COLOR_MODIFIERS: [[i32; 2]; 8] = [
+    [2, 8],
+    [5, 17],
+    [9, 29],
+    [13, 42],
+    [18, 60],
+    [24, 80],
+    [33, 106],
+    [47, 183],
+];
+
+/// Modifiers of EAC alpha blocks.
+const ALPHA_MODIFIERS: [[i32; 8]; 16] = [
+    [-3, -6, -9, -15, 2, 5, 8, 14],
+    [-3, -7, -10, -13, 2, 6, 9, 12],
+    [-2, -5, -8, -13, 1, 4, 7, 12],
+    [-2, -4, -6, -13, 1, 3, 5, 12],
+    [-3, -6, -8, -12, 2, 5, 7, 11],
+    [-3, -7, -9, -11, 2, 6, 8, 10],
+    [-4, -7, -8, -11, 3, 6, 7, 10],
+    [-3, -5, -8, -11, 2, 4, 7, 10],
+    [-2, -6, -8, -10, 1, 5, 7, 9],
+    [-2, -5, -8, -10, 1, 4, 7, 9],
+    [-2, -4, -8, -10, 1, 3, 7, 9],
+    [-2, -5, -7, -10, 1, 4, 6, 9],
+    [-3, -4, -7, -10, 2, 3, 6, 9],
+    [-1, -2, -3, -10, 0, 1, 2, 9],
+    [-4, -6, -8, -9, 3, 5, 7, 8],
+    [-3, -5, -7, -9, 2, 4, 6, 8],
+];
+
+/// Alpha table with a zero modifier, used for blocks of a single alpha value.
+const FLAT_ALPHA_TABLE: u64 = 13;
+const FLAT_ALPHA_INDEX: u64 = 4;
+
+/// Encodes an image as ETC1, which is also valid ETC2 RGB.
+/// Only the individual and differential modes are used, which is plenty for story images.
+pub fn encode_etc_rgb(image: &RgbaImage) -> Vec<u8> {
+    let mut output = Vec::new();
+
+    for_each_block(image, 4, 4, |pixels| {
+        output.extend_from_slice(&encode_color_block(pixels).to_be_bytes());
+    });
+
+    output
+}
+
+/// Encodes an image as ETC2 RGBA8, an EAC alpha block followed by a color block for every 4x4 pixels.
+pub fn encode_etc2_rgba8(image: &RgbaImage) -> Vec<u8> {
+    let mut output = Vec::new();
+
+    for_each_block(image, 4, 4, |pixels| {
+        output.extend_from_slice(&encode_alpha_block(pixels).to_be_bytes());
+        output.extend_from_slice(&encode_color_block(pixels).to_be_bytes());
+    });
+
+    output
+}
+
+/// ETC stores pixels column by column.
+fn pixel_bit(index: usize) -> usize {
+    (index % 4) * 4 + index / 4
+}
+
+fn encode_color_block(pixels: &[[u8; 4]]) -> u64 {
+    let mut best = (u64::MAX, 0);
+
+    for flip in [false, true] {
+        // Flipped blocks are split into top and bottom halves instead of left and right
+        let in_second_half = |index: usize| {
+            if flip { index / 4 >= 2 } else { index % 4 >= 2 }
+        };
+
+        let mut sums = [[0u32; 3]; 2];
+        for (index, pixel) in pixels.iter().enumerate() {
+            for channel in 0..3 {
+                sums[in_second_half(index) as usize][channel] += pixel[channel] as u32;
+            }
+        }
+        let averages = sums.map(|sum| sum.map(|value| value as f32 / 8.0));
+
+        let quantize = |value: f32, max: f32| (value * max / 255.0).round().clamp(0.0, max) as i32;
+        let base5 = averages.map(|average| average.map(|value| quantize(value, 31.0)));
+        let differences: [i32; 3] = std::array::from_fn(|c| base5[1][c] - base5[0][c]);
+        let differential = differences
+            .iter()
+            .all(|difference| (-4..=3).contains(difference));
+
+        let mut bits: u64 = 0;
+        let colors: [[i32; 3]; 2] = if differential {
+            for (channel, shift) in [59, 51, 43].into_iter().enumerate() {
+                bits |= (base5[0][channel] as u64) << shift;
+                bits |= ((differences[channel] & 7) as u64) << (shift - 3);
+            }
+            base5.map(|color| color.map(|value| value << 3 | value >> 2))
+        } else {
+            let base4 = averages.map(|average| average.map(|value| quantize(value, 15.0)));
+            for (channel, shift) in [60, 52, 44].into_iter().enumerate() {
+                bits |= (base4[0][channel] as u64) << shift;
+                bits |= (base4[1][channel] as u64) << (shift - 4);
+            }
+            base4.map(|color| color.map(|value| value << 4 | value))
+        };
+
+        bits |= (differential as u64) << 33 | (flip as u64) << 32;
+
+        let mut error = 0;
+        for (half, color) in colors.iter().enumerate() {
+            let mut best_table = (u64::MAX, 0, 0);
+
+            for (table, modifiers) in COLOR_MODIFIERS.iter().enumerate() {
+                let choices = [modifiers[0], modifiers[1], -modifiers[0], -modifiers[1]];
+                let mut table_error = 0;
+                let mut index_bits = 0;
+
+                for (index, pixel) in pixels.iter().enumerate() {
+                    if in_second_half(index) as usize != half {
+                        continue;
+                    }
+
+                    let (pixel_error, choice) = choices
+                        .iter()
+                        .enumerate()
+                        .map(|(choice, modifier)| {
+                            let error: u64 = (0..3)
+                                .map(|c| {
+                                    let value = (color[c] + modifier).clamp(0, 255);
+                                    ((value - pixel[c] as i32).pow(2)) as u64
+                                })
+                                .sum();
+                            (error, choice as u64)
+                        })
+                        .min()
+                        .unwrap();
+
+                    table_error += pixel_error;
+                    let bit = pixel_bit(index);
+                    index_bits |= (choice >> 1) << (16 + bit) | (choice & 1) << bit;
+                }
+
+                if table_error < best_table.0 {
+                    best_table = (table_error, table as u64, index_bits);
+                }
+            }
+
+            error += best_table.0;
+            bits |= best_table.1 << (37 - half * 3) | best_table.2;
+        }
+
+        if error < best.0 {
+            best = (error, bits);
+        }
+    }
+
+    best.1
+}
+
+fn encode_alpha_block(pixels: &[[u8; 4]]) -> u64 {
+    let alphas: Vec<i32> = pixels.iter().map(|pixel| pixel[3] as i32).collect();
+    let min = *alphas.iter().min().unwrap();
+    let max = *alphas.iter().max().unwrap();
+
+    if min == max {
+        let mut bits = (min as u64) << 56 | 1 << 52 | FLAT_ALPHA_TABLE << 48;
+        for index in 0..16 {
+            bits |= FLAT_ALPHA_INDEX << (45 - 3 * pixel_bit(index));
+        }
+        return bits;
+    }
+
+    let mut best = (u64::MAX, 0);
+
+    for (table, modifiers) in ALPHA_MODIFIERS.iter().enumerate() {
+        let spread = (modifiers[7] - modifiers[3]) as f32;
+        let multiplier = ((max - min) as f32 / spread).round() as i32;
+
+        for multiplier in (multiplier - 1..=multiplier + 1).filter(|m| (1..=15).contains(m)) {
+            let center = (modifiers[7] + modifiers[3]) * multiplier;
+            let base = ((min + max - center) as f32 / 2.0)
+                .round()
+                .clamp(0.0, 255.0) as i32;
+
+            let mut error = 0;
+            let mut bits = (base as u64) << 56 | (multiplier as u64) << 52 | (table as u64) << 48;
+
+            for (index, alpha) in alphas.iter().enumerate() {
+                let (pixel_error, choice) = modifiers
+                    .iter()
+                    .enumerate()
+                    .map(|(choice, modifier)| {
+                        let value = (base + modifier * multiplier).clamp(0, 255);
+                        (((value - alpha).pow(2)) as u64, choice as u64)
+                    })
+                    .min()
+                    .unwrap();
+
+                error += pixel_error;
+                bits |= choice << (45 - 3 * pixel_bit(index));
+            }
+
+            if error < best.0 {
+                best = (error, bits);
+            }
+        }
+    }
+
+    best.1
+}
+
+#[cfg(test)]
+mod tests {
+    use image::Rgba;
+
+    use super::*;
+
+    /// Decodes an ETC1 / ETC2 RGB block in individual or differential mode, following the ETC2 specification.
+    /// Fails on the T, H and planar modes, which a differential block overflowing its base colors would turn into.
+    fn decode_color_block(bits: u64) -> [[u8; 3]; 16] {
+        let field = |shift: u32, width: u32| ((bits >> shift) & ((1 << width) - 1)) as i32;
+        let differential = field(33, 1) == 1;
+        let flip = field(32, 1) == 1;
+
+        let colors: [[i32; 3]; 2] = if differential {
+            let base: [i32; 3] = [field(59, 5), field(51, 5), field(43, 5)];
+            let delta: [i32; 3] =
+                [field(56, 3), field(48, 3), field(40, 3)].map(|d| (d << 29) >> 29);
+            let second: [i32; 3] = std::array::from_fn(|c| base[c] + delta[c]);
+            assert!(
+                second.iter().all(|value| (0..32).contains(value)),
+                "differential block overflows into another ETC2 mode"
+            );
+            [base, second].map(|color| color.map(|value| value << 3 | value >> 2))
+        } else {
+            let first = [field(60, 4), field(52, 4), field(44, 4)];
+            let second = [field(56, 4), field(48, 4), field(40, 4)];
+            [first, second].map(|color| color.map(|value| value << 4 | value))
+        };
+        let tables = [field(37, 3) as usize, field(34, 3) as usize];
+
+        std::array::from_fn(|index| {
+            let (x, y) = (index % 4, index / 4);
+            let half = if flip { y >= 2 } else { x >= 2 } as usize;
+            let bit = (x * 4 + y) as u32;
+            let modifier = COLOR_MODIFIERS[tables[half]][field(bit, 1) as usize];
+            let modifier = if field(16 + bit, 1) == 1 {
+                -modifier
+            } else {
+                modifier
+            };
+            colors[half].map(|value| (value + modifier).clamp(0, 255) as u8)
+        })
+    }
+
+    /// Decodes an EAC alpha block, following the ETC2 specification.
+    fn decode_alpha_block(bits: u64) -> [u8; 16] {
+        let base = (bits >> 56) as i32;
+        let multiplier = (bits >> 52 & 0xF) as i32;
+        let table = &ALPHA_MODIFIERS[(bits >> 48 & 0xF) as usize];
+
+        std::array::from_fn(|index| {
+            let (x, y) = (index % 4, index / 4);
+            let choice = (bits >> (45 - 3 * (x * 4 + y)) & 7) as usize;
+            (base + table[choice] * multiplier).clamp(0, 255) as u8
+        })
+    }
+
+    /// Decodes ETC2 RGBA8 data back into an image.
+    fn decode_etc2_rgba8(data: &[u8], width: u32, height: u32) -> RgbaImage {
+        let mut image = RgbaImage::new(width, height);
+        let blocks_wide = width.div_ceil(4);
+
+        for (block, bytes) in data.chunks_exact(16).enumerate() {
+            let alpha = decode_alpha_block(u64::from_be_bytes(bytes[..8].try_into().unwrap()));
+            let color = decode_color_block(u64::from_be_bytes(bytes[8..].try_into().unwrap()));
+            let (block_x, block_y) = (
+                block as u32 % blocks_wide * 4,
+                block as u32 / blocks_wide * 4,
+            );
+
+            for index in 0..16 {
+                let (x, y) = (block_x + index as u32 % 4, block_y + index as u32 / 4);
+                if x < width && y < height {
+                    let [r, g, b] = color[index];
+                    image.put_pixel(x, y, Rgba([r, g, b, alpha[index]]));
+                }
+            }
+        }
+
+        image
+    }
+
+    /// Largest difference of any channel of any pixel.
+    fn max_error(a: &RgbaImage, b: &RgbaImage) -> u8 {
+        a.pixels()
+            .zip(b.pixels())
+            .flat_map(|(a, b)| a.0.into_iter().zip(b.0).map(|(a, b)| a.abs_diff(b)))
+            .max()
+            .unwrap()
+    }
+
+    #[test]
+    fn solid_color_is_kept() {
+        // Not a multiple of the block size, so edge blocks are covered too
+        let image = RgbaImage::from_pixel(6, 5, Rgba([200, 40, 120, 255]));
+        let data = encode_etc2_rgba8(&image);
+
+        assert_eq!(data.len(), 2 * 2 * 16);
+        assert!(max_error(&decode_etc2_rgba8(&data, 6, 5), &image) <= 4);
+
+        // A flat alpha block stores the alpha as is, with a zero modifier
+        assert_eq!(data[..8], [255, 0x1D, 0x92, 0x49, 0x24, 0x92, 0x49, 0x24]);
+    }
+
+    #[test]
+    fn gradient_stays_close() {
+        // ETC shifts every channel of a pixel by the same amount, so it does best where they rise together
+        let gray = RgbaImage::from_fn(8, 8, |x, y| {
+            let value = (x * 24 + y * 8) as u8;
+            Rgba([value, value, value, 255])
+        });
+        let warm = RgbaImage::from_fn(8, 8, |x, y| {
+            let value = (x * 24 + y * 8) as u8;
+            Rgba([value, value / 2 + 64, value / 4 + 32, 255])
+        });
+
+        assert!(max_error(&decode_etc2_rgba8(&encode_etc2_rgba8(&gray), 8, 8), &gray) <= 16);
+        assert!(max_error(&decode_etc2_rgba8(&encode_etc2_rgba8(&warm), 8, 8), &warm) <= 24);
+    }
+
+    #[test]
+    fn alpha_stays_close() {
+        let alpha_error = |image: &RgbaImage| {
+            decode_etc2_rgba8(&encode_etc2_rgba8(image), 4, 4)
+                .pixels()
+                .zip(image.pixels())
+                .map(|(a, b)| a[3].abs_diff(b[3]))
+                .max()
+                .unwrap()
+        };
+
+        // Cut out edges only use two alpha values
+        let edge = RgbaImage::from_fn(4, 4, |x, _| {
+            Rgba([255, 255, 255, if x < 2 { 0 } else { 255 }])
+        });
+        assert!(alpha_error(&edge) <= 4);
+
+        // Sixteen alpha values can't all be hit with eight, but should land close
+        let ramp = RgbaImage::from_fn(4, 4, |x, y| Rgba([255, 255, 255, (x + y * 4) as u8 * 17]));
+        assert!(alpha_error(&ramp) <= 16);
+    }
+
+    #[test]
+    fn rgb_matches_the_color_blocks_of_rgba8() {
+        let image = RgbaImage::from_fn(4, 4, |x, y| Rgba([x as u8 * 60, 0, y as u8 * 60, 255]));
+
+        assert_eq!(encode_etc_rgb(&image), encode_etc2_rgba8(&image)[8..]);
+    }
+}
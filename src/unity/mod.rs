@@ -0,0 +1,340 @@
+// Native reading and writing of Unity AssetBundles, so handling assets doesn't need Python or UnityPy.
+// Only UnityFS bundles with type trees (which is all the game uses) are supported.
+
+mod astc;
+pub mod bundle;
+mod etc;
+mod reader;
+pub mod serialized;
+pub mod texture;
+pub mod typetree;
+mod writer;
+
+use std::path::Path;
+
+use anyhow::{Context, Result, bail};
+use image::RgbaImage;
+use log::debug;
+use serde::de::DeserializeOwned;
+use serde_json::Value;
+
+use crate::unity::{
+    bundle::{BundleNode, UnityBundle},
+    serialized::{
+        CLASS_ASSET_BUNDLE, CLASS_MONO_BEHAVIOUR, CLASS_TEXTURE2D, ObjectInfo, SerializedFile,
+    },
+    texture::read_texture2d,
+};
+
+impl BundleNode {
+    /// Whether the node is a SerializedFile, rather than a resource node holding texture or audio data.
+    pub fn is_serialized_file(&self) -> bool {
+        !self.path.ends_with(".resS") && !self.path.ends_with(".resource")
+    }
+}
+
+impl UnityBundle {
+    /// Every SerializedFile in the bundle.
+    pub fn serialized_files(&self) -> Result<Vec<SerializedFile<'_>>> {
+        self.nodes
+            .iter()
+            .filter(|node| node.is_serialized_file())
+            .map(|node| {
+                SerializedFile::parse(&node.data)
+                    .with_context(|| format!("Could not parse {}", node.path))
+            })
+            .collect()
+    }
+
+    /// Decodes the Texture2D named `name`, if there is one.
+    pub fn texture(&self, name: &str) -> Result<Option<RgbaImage>> {
+        for file in self.serialized_files()? {
+            if let Some(object) = file.find_named(CLASS_TEXTURE2D, name)? {
+                return read_texture2d(self, &file, object).map(Some);
+            }
+        }
+
+        Ok(None)
+    }
+
+    /// Path ids and names of every MonoBehaviour running `query`'s script, in the order they're stored. `query.name` is ignored.
+    pub fn mono_behaviours(
+        &self,
+        query: &MonoBehaviourQuery,
+    ) -> Result<Vec<(i64, Option<String>)>> {
+        let mut matches = Vec::new();
+
+        for file in self.serialized_files()? {
+            for object in file
+                .objects
+                .iter()
+                .filter(|object| object.class_id == CLASS_MONO_BEHAVIOUR)
+            {
+                let is_match = match file.script_class(object)? {
+                    Some(script) => script == query.script,
+                    None => file.has_fields(object, query.fields)?,
+                };
+
+                if is_match {
+                    matches.push((object.path_id, file.object_name(object)?));
+                }
+            }
+        }
+
+        Ok(matches)
+    }
+
+    /// Writes `value` over the object `path_id` using the object's own type tree.
+    /// `value` must hold every field of the type tree, as a struct read through `read_typetree` does.
+    pub fn write_object(&mut self, path_id: i64, value: &Value) -> Result<()> {
+        self.edit_file_of(path_id, |file, object| {
+            let object_data = file.write_object(object, value)?;
+            file.replace_object(path_id, &object_data)
+        })
+    }
+
+    /// Adds an object of the same type as `like`, in the same file, holding `value`. Returns its path id.
+    pub fn add_object(&mut self, like: i64, value: &Value) -> Result<i64> {
+        let mut path_id = 0;
+
+        self.edit_file_of(like, |file, object| {
+            path_id = file
+                .objects
+                .iter()
+                .map(|object| object.path_id)
+                .max()
+                .unwrap_or(0)
+                + 1;
+
+            let object_data = file.write_object(object, value)?;
+            file.add_object(object, path_id, &object_data)
+        })?;
+
+        Ok(path_id)
+    }
+
+    /// Lists the object `path_id` in the bundle's container as `name`, in the same folder and with the same extension as `like`'s entry.
+    /// An entry `path_id` already had is renamed. Otherwise the preload info of `like`'s entry is copied, as the object is expected to be a copy of it.
+    pub fn set_container_name(&mut self, path_id: i64, like: i64, name: &str) -> Result<()> {
+        self.edit_file_of(like, |file, _| {
+            let asset_bundle = file
+                .objects
+                .iter()
+                .find(|object| object.class_id == CLASS_ASSET_BUNDLE)
+                .context("Bundle has no AssetBundle object to list its assets")?;
+            let mut value = serde_json::to_value(file.read_object(asset_bundle)?)?;
+
+            let container = value
+                .get_mut("m_Container")
+                .and_then(Value::as_array_mut)
+                .context("AssetBundle object has no m_Container")?;
+
+            let entry_of = |container: &[Value], path_id: i64| {
+                container.iter().position(|entry| {
+                    entry[1]["asset"]["m_FileID"].as_i64() == Some(0)
+                        && entry[1]["asset"]["m_PathID"].as_i64() == Some(path_id)
+                })
+            };
+
+            let like_entry = entry_of(container, like)
+                .map(|index| container[index].clone())
+                .with_context(|| format!("Object {like} isn't listed in the container"))?;
+            let mut entry = match entry_of(container, path_id) {
+                Some(index) => container.remove(index),
+                None => {
+                    let mut entry = like_entry.clone();
+                    entry[1]["asset"]["m_PathID"] = path_id.into();
+                    entry
+                }
+            };
+
+            let like_key = like_entry[0].as_str().unwrap_or_default();
+            let (folder, file_name) = like_key.rsplit_once('/').unwrap_or(("", like_key));
+            let extension = file_name.rsplit_once('.').map(|(_, extension)| extension);
+
+            let mut key = name.to_lowercase();
+            if let Some(extension) = extension {
+                key = format!("{key}.{extension}");
+            }
+            if !folder.is_empty() {
+                key = format!("{folder}/{key}");
+            }
+
+            entry[0] = key.clone().into();
+
+            // Unity stores the container sorted by asset name
+            let index = container.partition_point(|entry| entry[0].as_str() <= Some(key.as_str()));
+            container.insert(index, entry);
+
+            let object_data = file.write_object(asset_bundle, &value)?;
+            file.replace_object(asset_bundle.path_id, &object_data)
+        })
+    }
+
+    /// Replaces the SerializedFile holding the object `path_id` with what `edit` returns for it.
+    fn edit_file_of(
+        &mut self,
+        path_id: i64,
+        edit: impl FnOnce(&SerializedFile, &ObjectInfo) -> Result<Vec<u8>>,
+    ) -> Result<()> {
+        for node in self
+            .nodes
+            .iter_mut()
+            .filter(|node| node.is_serialized_file())
+        {
+            let file = SerializedFile::parse(&node.data)
+                .with_context(|| format!("Could not parse {}", node.path))?;
+
+            if let Some(object) = file.object(path_id) {
+                node.data = edit(&file, object)?;
+                return Ok(());
+            }
+        }
+
+        bail!("No object with path id {path_id}")
+    }
+}
+
+/// Which MonoBehaviour to pick out of an AssetBundle, for templates whose path ids aren't known ahead of time.
+pub struct MonoBehaviourQuery<'a> {
+    /// Class name of the script the MonoBehaviour runs
+    pub script: &'a str,
+    /// Preferred when several MonoBehaviours run the script, otherwise the first one is used
+    pub name: Option<&'a str>,
+    /// Fields the MonoBehaviour must have instead, when its script is stored in another bundle and can't be checked
+    pub fields: &'a [&'a str],
+}
+
+/// Finds the path id of the MonoBehaviour matching `query` in the AssetBundle at `bundle_path`.
+/// Without a `query.name`, the first one running the script is used.
+pub fn find_mono_behaviour(bundle_path: &Path, query: &MonoBehaviourQuery) -> Result<i64> {
+    let matches = UnityBundle::open(bundle_path)?.mono_behaviours(query)?;
+
+    let Some(name) = query.name else {
+        return match matches.first() {
+            Some((path_id, _)) => Ok(*path_id),
+            None => bail!("No {} in {}", query.script, bundle_path.display()),
+        };
+    };
+
+    match matches
+        .iter()
+        .find(|(_, found)| found.as_deref() == Some(name))
+    {
+        Some((path_id, _)) => Ok(*path_id),
+        None => bail!(
+            "No {} named {name} in {}, it has {}",
+            query.script,
+            bundle_path.display(),
+            matches
+                .iter()
+                .map(|(_, found)| found.as_deref().unwrap_or("(unnamed)"))
+                .collect::<Vec<_>>()
+                .join(", ")
+        ),
+    }
+}
+
+/// Reads the object `path_id` out of the AssetBundle at `bundle_path` into `T`, matching the typetree UnityPy would return.
+pub fn read_typetree<T: DeserializeOwned>(bundle_path: &Path, path_id: i64) -> Result<T> {
+    let bundle = UnityBundle::open(bundle_path)?;
+
+    for file in bundle.serialized_files()? {
+        if let Some(object) = file.object(path_id) {
+            let value = file.read_object(object)?;
+
+            return serde_json::from_value(serde_json::to_value(&value)?).with_context(|| {
+                format!(
+                    "Object {path_id} in {} does not have the expected fields",
+                    bundle_path.display()
+                )
+            });
+        }
+    }
+
+    bail!(
+        "No object with path id {path_id} in {}",
+        bundle_path.display()
+    )
+}
+
+/// Reads every object of the AssetBundle at `bundle_path` and writes it straight back, failing unless the repacked bundle is identical to the original.
+/// Used to check that a template can be written without corrupting it.
+pub fn verify_round_trip(bundle_path: &Path) -> Result<()> {
+    let original = bundle::decrypt_bundle(std::fs::read(bundle_path)?);
+    let mut bundle = UnityBundle::from_bytes(original.clone())?;
+
+    for node in bundle
+        .nodes
+        .iter_mut()
+        .filter(|node| node.is_serialized_file())
+    {
+        let file = SerializedFile::parse(&node.data)
+            .with_context(|| format!("Could not parse {}", node.path))?;
+        let mut data = node.data.clone();
+
+        for object in &file.objects {
+            let value = serde_json::to_value(file.read_object(object)?)?;
+            let object_data = file.write_object(object, &value)?;
+
+            if object_data != file.read_raw(object)? {
+                bail!(
+                    "Object {} in {} changed after being written back",
+                    object.path_id,
+                    node.path
+                );
+            }
+
+            data = SerializedFile::parse(&data)?.replace_object(object.path_id, &object_data)?;
+        }
+
+        if data != node.data {
+            bail!("{} changed after its objects were written back", node.path);
+        }
+
+        debug!(
+            "{} round trips with {} objects",
+            node.path,
+            file.objects.len()
+        );
+        node.data = data;
+    }
+
+    if bundle.to_bytes()? != original {
+        bail!("{} changed after being repacked", bundle_path.display());
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use super::*;
+
+    /// A small UnityFS bundle holding a SerializedFile with two MonoBehaviours and a .resS node.
+    fn fixture() -> PathBuf {
+        Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/round_trip.unity3d")
+    }
+
+    #[test]
+    fn unmodified_bundle_repacks_identically() {
+        let original = std::fs::read(fixture()).unwrap();
+        let bundle = UnityBundle::from_bytes(original.clone()).unwrap();
+
+        let node = bundle
+            .nodes
+            .iter()
+            .find(|node| node.is_serialized_file())
+            .unwrap();
+        assert_eq!(SerializedFile::parse(&node.data).unwrap().objects.len(), 2);
+
+        assert_eq!(bundle.to_bytes().unwrap(), original);
+    }
+
+    #[test]
+    fn fixture_round_trips_object_by_object() {
+        verify_round_trip(&fixture()).unwrap();
+    }
+}
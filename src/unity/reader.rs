@@ -0,0 +1,103 @@
+use anyhow::{Context, Result, bail};
+
+/// Cursor over a byte slice that reads Unity's primitives in either byte order.
+pub struct EndianReader<'a> {
+    data: &'a [u8],
+    pub position: usize,
+    pub big_endian: bool,
+}
+
+macro_rules! read_primitive {
+    ($name:ident, $ty:ty) => {
+        pub fn $name(&mut self) -> Result<$ty> {
+            let bytes = self.read_bytes(size_of::<$ty>())?.try_into().unwrap();
+
+            Ok(if self.big_endian {
+                <$ty>::from_be_bytes(bytes)
+            } else {
+                <$ty>::from_le_bytes(bytes)
+            })
+        }
+    };
+}
+
+impl<'a> EndianReader<'a> {
+    pub fn new(data: &'a [u8], big_endian: bool) -> Self {
+        EndianReader {
+            data,
+            position: 0,
+            big_endian,
+        }
+    }
+
+    read_primitive!(read_u16, u16);
+    read_primitive!(read_i16, i16);
+    read_primitive!(read_u32, u32);
+    read_primitive!(read_i32, i32);
+    read_primitive!(read_u64, u64);
+    read_primitive!(read_i64, i64);
+    read_primitive!(read_f32, f32);
+    read_primitive!(read_f64, f64);
+
+    pub fn read_u8(&mut self) -> Result<u8> {
+        Ok(self.read_bytes(1)?[0])
+    }
+
+    pub fn read_i8(&mut self) -> Result<i8> {
+        Ok(self.read_u8()? as i8)
+    }
+
+    pub fn read_bool(&mut self) -> Result<bool> {
+        Ok(self.read_u8()? != 0)
+    }
+
+    pub fn read_bytes(&mut self, count: usize) -> Result<&'a [u8]> {
+        let end = self
+            .position
+            .checked_add(count)
+            .filter(|end| *end <= self.data.len())
+            .with_context(|| {
+                format!(
+                    "Tried to read {count} bytes at {}, but there are only {}",
+                    self.position,
+                    self.data.len()
+                )
+            })?;
+
+        let bytes = &self.data[self.position..end];
+        self.position = end;
+
+        Ok(bytes)
+    }
+
+    /// Reads a null terminated UTF-8 string.
+    pub fn read_cstring(&mut self) -> Result<String> {
+        let Some(length) = self.data[self.position.min(self.data.len())..]
+            .iter()
+            .position(|b| *b == 0)
+        else {
+            bail!("Unterminated string at {}", self.position);
+        };
+
+        let string = String::from_utf8_lossy(self.read_bytes(length)?).into_owned();
+        self.position += 1;
+
+        Ok(string)
+    }
+
+    /// Reads an i32 length prefixed UTF-8 string, as used by type trees.
+    pub fn read_string(&mut self) -> Result<String> {
+        let length = self.read_i32()?;
+
+        if length < 0 {
+            bail!("Negative string length {length} at {}", self.position);
+        }
+
+        Ok(String::from_utf8_lossy(self.read_bytes(length as usize)?).into_owned())
+    }
+
+    /// Skips ahead to the next multiple of `alignment`.
+    pub fn align(&mut self, alignment: usize) {
+        self.position = self.position.next_multiple_of(alignment);
+    }
+}
@@ -0,0 +1,502 @@
+use anyhow::{Context, Result, bail};
+use serde_json::Value;
+
+use super::{
+    reader::EndianReader,
+    typetree::{TypeTreeNode, UnityValue},
+    writer::EndianWriter,
+};
+
+pub const CLASS_TEXTURE2D: i32 = 28;
+pub const CLASS_MONO_BEHAVIOUR: i32 = 114;
+pub const CLASS_MONO_SCRIPT: i32 = 115;
+pub const CLASS_ASSET_BUNDLE: i32 = 142;
+pub const CLASS_SPRITE: i32 = 213;
+
+/// A type used by objects in a SerializedFile, along with its type tree if the file was built with them.
+pub struct SerializedType {
+    pub class_id: i32,
+    pub type_tree: Option<TypeTreeNode>,
+}
+
+/// Where an object is stored within a SerializedFile.
+pub struct ObjectInfo {
+    pub path_id: i64,
+    pub byte_start: usize,
+    pub byte_size: usize,
+    /// Index into `SerializedFile::types`
+    pub type_index: usize,
+    pub class_id: i32,
+    /// Where byte_start is stored in the metadata, with byte_size right after it
+    metadata_position: usize,
+}
+
+/// A parsed SerializedFile (the CAB-... node of an AssetBundle), whose objects can be read through their type trees.
+pub struct SerializedFile<'a> {
+    data: &'a [u8],
+    big_endian: bool,
+    format_version: u32,
+    data_offset: usize,
+    /// Where the object count is stored, with the object table right after it
+    object_count_position: usize,
+    /// Where the object table ends
+    objects_end: usize,
+    pub types: Vec<SerializedType>,
+    pub objects: Vec<ObjectInfo>,
+}
+
+impl<'a> SerializedFile<'a> {
+    pub fn parse(data: &'a [u8]) -> Result<SerializedFile<'a>> {
+        let mut reader = EndianReader::new(data, true);
+
+        let _metadata_size = reader.read_u32()?;
+        let _file_size = reader.read_u32()?;
+        let format_version = reader.read_u32()?;
+        let mut data_offset = reader.read_u32()? as usize;
+
+        if format_version < 9 {
+            bail!("SerializedFile version {format_version} is too old to be supported");
+        }
+
+        let big_endian = reader.read_u8()? != 0;
+        reader.read_bytes(3)?; // Reserved
+
+        if format_version >= 22 {
+            let _metadata_size = reader.read_u32()?;
+            let _file_size = reader.read_i64()?;
+            data_offset = usize::try_from(reader.read_i64()?)?;
+            let _unknown = reader.read_i64()?;
+        }
+
+        if data_offset > data.len() {
+            bail!("SerializedFile data starts past its end, at {data_offset}");
+        }
+
+        reader.big_endian = big_endian;
+
+        let _unity_version = reader.read_cstring()?;
+        let _target_platform = reader.read_i32()?;
+
+        let enable_type_tree = if format_version >= 13 {
+            reader.read_bool()?
+        } else {
+            true
+        };
+
+        let type_count = reader.read_i32()?;
+        let mut types = Vec::new();
+
+        for _ in 0..type_count {
+            types.push(read_serialized_type(
+                &mut reader,
+                format_version,
+                enable_type_tree,
+                false,
+            )?);
+        }
+
+        let big_id_enabled = if (7..14).contains(&format_version) {
+            reader.read_i32()? != 0
+        } else {
+            false
+        };
+
+        let object_count_position = reader.position;
+        let object_count = reader.read_i32()?;
+        let mut objects = Vec::new();
+
+        for _ in 0..object_count {
+            let path_id = if big_id_enabled {
+                reader.read_i64()?
+            } else if format_version < 14 {
+                reader.read_i32()?.into()
+            } else {
+                reader.align(4);
+                reader.read_i64()?
+            };
+
+            let metadata_position = reader.position;
+            let byte_start = if format_version >= 22 {
+                reader.read_i64()?
+            } else {
+                reader.read_u32()?.into()
+            };
+
+            let byte_size = reader.read_u32()? as usize;
+
+            // Everything writing objects back relies on them lying inside the file
+            let byte_start = usize::try_from(byte_start)
+                .ok()
+                .and_then(|byte_start| byte_start.checked_add(data_offset))
+                .filter(|byte_start| {
+                    byte_start
+                        .checked_add(byte_size)
+                        .is_some_and(|end| end <= data.len())
+                })
+                .with_context(|| format!("Object {path_id} lies outside of the SerializedFile"))?;
+            let type_id = reader.read_i32()?;
+
+            let (type_index, class_id) = if format_version < 16 {
+                let class_id = reader.read_u16()? as i32;
+                let type_index = types
+                    .iter()
+                    .position(|serialized_type| serialized_type.class_id == type_id)
+                    .with_context(|| format!("Object {path_id} has unknown type {type_id}"))?;
+                (type_index, class_id)
+            } else {
+                let type_index = usize::try_from(type_id)
+                    .ok()
+                    .filter(|index| *index < types.len())
+                    .with_context(|| format!("Object {path_id} has unknown type {type_id}"))?;
+                (type_index, types[type_index].class_id)
+            };
+
+            if format_version < 11 {
+                let _is_destroyed = reader.read_u16()?;
+            }
+
+            if (11..17).contains(&format_version) {
+                let _script_type_index = reader.read_i16()?;
+            }
+
+            if format_version == 15 || format_version == 16 {
+                let _stripped = reader.read_u8()?;
+            }
+
+            objects.push(ObjectInfo {
+                path_id,
+                byte_start,
+                byte_size,
+                type_index,
+                class_id,
+                metadata_position,
+            });
+        }
+
+        let objects_end = reader.position;
+
+        // Script types, externals, ref types and user information follow, but aren't needed to read objects
+
+        Ok(SerializedFile {
+            data,
+            big_endian,
+            format_version,
+            data_offset,
+            object_count_position,
+            objects_end,
+            types,
+            objects,
+        })
+    }
+
+    pub fn object(&self, path_id: i64) -> Option<&ObjectInfo> {
+        self.objects.iter().find(|object| object.path_id == path_id)
+    }
+
+    /// Reads every field of an object.
+    pub fn read_object(&self, object: &ObjectInfo) -> Result<UnityValue> {
+        self.type_tree(object)?
+            .read_value(&mut self.object_reader(object)?)
+            .with_context(|| format!("Could not read object {}", object.path_id))
+    }
+
+    /// Serializes `value` with the type tree of `object`, returning the bytes to replace the object with.
+    pub fn write_object(&self, object: &ObjectInfo, value: &Value) -> Result<Vec<u8>> {
+        let mut writer = EndianWriter::new(self.big_endian);
+
+        self.type_tree(object)?
+            .write_value(value, &mut writer)
+            .with_context(|| format!("Could not write object {}", object.path_id))?;
+
+        Ok(writer.data)
+    }
+
+    /// Returns a copy of the whole file with the data of object `path_id` replaced by `object_data`.
+    /// Objects after it are moved along and the offsets and sizes in the metadata updated to match.
+    pub fn replace_object(&self, path_id: i64, object_data: &[u8]) -> Result<Vec<u8>> {
+        if self.object(path_id).is_none() {
+            bail!("No object with path id {path_id}");
+        }
+
+        let mut order: Vec<&ObjectInfo> = self.objects.iter().collect();
+        order.sort_by_key(|object| object.byte_start);
+
+        let mut output = self
+            .data
+            .get(..self.data_offset)
+            .context("Data offset lies outside of the file")?
+            .to_vec();
+        let mut original_end = self.data_offset;
+        let mut new_positions = Vec::new();
+
+        for object in order {
+            // Keep the original padding while nothing has moved, and pad to 8 bytes like Unity does once it has
+            if output.len() == original_end && object.byte_start >= original_end {
+                output.extend_from_slice(&self.data[original_end..object.byte_start]);
+            } else {
+                output.resize(output.len().next_multiple_of(8), 0);
+            }
+
+            let start = output.len();
+            if object.path_id == path_id {
+                output.extend_from_slice(object_data);
+            } else {
+                output.extend_from_slice(self.read_raw(object)?);
+            }
+
+            new_positions.push((object, start - self.data_offset, output.len() - start));
+            original_end = object.byte_start + object.byte_size;
+        }
+
+        // Anything after the last object
+        output.extend_from_slice(self.data.get(original_end..).unwrap_or_default());
+
+        for (object, start, size) in new_positions {
+            let mut writer = EndianWriter::new(self.big_endian);
+            if self.format_version >= 22 {
+                writer.write_i64(start as i64);
+            } else {
+                writer.write_u32(start as u32);
+            }
+            writer.write_u32(size as u32);
+
+            output[object.metadata_position..object.metadata_position + writer.data.len()]
+                .copy_from_slice(&writer.data);
+        }
+
+        // The header is always big endian
+        let file_size = output.len();
+        if self.format_version >= 22 {
+            output[24..32].copy_from_slice(&(file_size as i64).to_be_bytes());
+        } else {
+            output[4..8].copy_from_slice(&(file_size as u32).to_be_bytes());
+        }
+
+        Ok(output)
+    }
+
+    /// Returns a copy of the whole file with a new object `path_id` of the same type as `like`, holding `object_data`.
+    /// Its entry goes at the end of the object table and its data after every other object.
+    pub fn add_object(
+        &self,
+        like: &ObjectInfo,
+        path_id: i64,
+        object_data: &[u8],
+    ) -> Result<Vec<u8>> {
+        if self.format_version < 17 {
+            bail!(
+                "Adding objects to SerializedFile version {} isn't supported",
+                self.format_version
+            );
+        }
+
+        if self.object(path_id).is_some() {
+            bail!("There already is an object with path id {path_id}");
+        }
+
+        // Entries are aligned to 4 bytes, which every entry before already ends on
+        let entry_size: usize = if self.format_version >= 22 { 24 } else { 20 };
+        // Unity keeps the data aligned to 16 bytes, so the metadata grows by that much
+        let growth = entry_size.next_multiple_of(16);
+        let data_offset = self.data_offset + growth;
+
+        let data_end = self.data.len() + growth;
+        let start = data_end.next_multiple_of(8);
+
+        let mut entry = EndianWriter::new(self.big_endian);
+        entry.write_i64(path_id);
+        if self.format_version >= 22 {
+            entry.write_i64((start - data_offset) as i64);
+        } else {
+            entry.write_u32((start - data_offset) as u32);
+        }
+        entry.write_u32(object_data.len() as u32);
+        entry.write_i32(like.type_index as i32);
+
+        let mut output = Vec::with_capacity(start + object_data.len());
+        output.extend_from_slice(&self.data[..self.objects_end]);
+        output.extend_from_slice(&entry.data);
+        output.extend_from_slice(
+            self.data
+                .get(self.objects_end..self.data_offset)
+                .context("Data offset lies before the object table")?,
+        );
+        output.resize(data_offset, 0);
+        output.extend_from_slice(&self.data[self.data_offset..]);
+        output.resize(start, 0);
+        output.extend_from_slice(object_data);
+
+        let mut object_count = EndianWriter::new(self.big_endian);
+        object_count.write_i32(self.objects.len() as i32 + 1);
+        output[self.object_count_position..self.object_count_position + 4]
+            .copy_from_slice(&object_count.data);
+
+        // The header is always big endian
+        let (metadata_size_position, file_size) =
+            (if self.format_version >= 22 { 20 } else { 0 }, output.len());
+        let metadata_size = u32::from_be_bytes(
+            output[metadata_size_position..metadata_size_position + 4].try_into()?,
+        ) + entry_size as u32;
+        output[metadata_size_position..metadata_size_position + 4]
+            .copy_from_slice(&metadata_size.to_be_bytes());
+
+        if self.format_version >= 22 {
+            output[24..32].copy_from_slice(&(file_size as i64).to_be_bytes());
+            output[32..40].copy_from_slice(&(data_offset as i64).to_be_bytes());
+        } else {
+            output[4..8].copy_from_slice(&(file_size as u32).to_be_bytes());
+            output[12..16].copy_from_slice(&(data_offset as u32).to_be_bytes());
+        }
+
+        Ok(output)
+    }
+
+    /// Reads the fields of an object up to `name`, without reading whatever large data follows it.
+    pub fn read_field(&self, object: &ObjectInfo, name: &str) -> Result<Option<UnityValue>> {
+        let type_tree = self.type_tree(object)?;
+        let mut reader = self.object_reader(object)?;
+
+        for field in &type_tree.children {
+            let value = field.read_value(&mut reader)?;
+
+            if field.name == name {
+                return Ok(Some(value));
+            }
+        }
+
+        Ok(None)
+    }
+
+    /// Reads just the m_Name of an object.
+    pub fn object_name(&self, object: &ObjectInfo) -> Result<Option<String>> {
+        Ok(self
+            .read_field(object, "m_Name")?
+            .and_then(|name| name.as_str().map(str::to_owned)))
+    }
+
+    /// Whether the type tree of an object has every one of `fields` at its top level.
+    pub fn has_fields(&self, object: &ObjectInfo, fields: &[&str]) -> Result<bool> {
+        let type_tree = self.type_tree(object)?;
+
+        Ok(fields
+            .iter()
+            .all(|name| type_tree.children.iter().any(|field| field.name == *name)))
+    }
+
+    /// The class name of the script a MonoBehaviour runs, if its MonoScript is stored in this file.
+    pub fn script_class(&self, object: &ObjectInfo) -> Result<Option<String>> {
+        let Some(script) = self.read_field(object, "m_Script")? else {
+            return Ok(None);
+        };
+
+        // Any other file id points to a MonoScript in another file, usually another bundle
+        if script.field("m_FileID").and_then(UnityValue::as_i64) != Some(0) {
+            return Ok(None);
+        }
+
+        let Some(mono_script) = script
+            .field("m_PathID")
+            .and_then(UnityValue::as_i64)
+            .and_then(|path_id| self.object(path_id))
+            .filter(|mono_script| mono_script.class_id == CLASS_MONO_SCRIPT)
+        else {
+            return Ok(None);
+        };
+
+        Ok(self
+            .read_field(mono_script, "m_ClassName")?
+            .and_then(|class_name| class_name.as_str().map(str::to_owned)))
+    }
+
+    /// Finds the first object of `class_id` named `name`.
+    pub fn find_named(&self, class_id: i32, name: &str) -> Result<Option<&ObjectInfo>> {
+        for object in self
+            .objects
+            .iter()
+            .filter(|object| object.class_id == class_id)
+        {
+            if self.object_name(object)?.as_deref() == Some(name) {
+                return Ok(Some(object));
+            }
+        }
+
+        Ok(None)
+    }
+
+    fn type_tree(&self, object: &ObjectInfo) -> Result<&TypeTreeNode> {
+        self.types[object.type_index]
+            .type_tree
+            .as_ref()
+            .context("AssetBundle was built without type trees, which are needed to read it")
+    }
+
+    /// The serialized bytes of an object.
+    pub fn read_raw(&self, object: &ObjectInfo) -> Result<&'a [u8]> {
+        self.data
+            .get(object.byte_start..object.byte_start + object.byte_size)
+            .with_context(|| format!("Object {} lies outside of the file", object.path_id))
+    }
+
+    fn object_reader(&self, object: &ObjectInfo) -> Result<EndianReader<'a>> {
+        Ok(EndianReader::new(self.read_raw(object)?, self.big_endian))
+    }
+}
+
+fn read_serialized_type(
+    reader: &mut EndianReader,
+    format_version: u32,
+    enable_type_tree: bool,
+    is_ref_type: bool,
+) -> Result<SerializedType> {
+    let class_id = reader.read_i32()?;
+
+    if format_version >= 16 {
+        let _is_stripped_type = reader.read_u8()?;
+    }
+
+    let script_type_index = if format_version >= 17 {
+        reader.read_i16()?
+    } else {
+        -1
+    };
+
+    if format_version >= 13 {
+        if (is_ref_type && script_type_index >= 0)
+            || (format_version < 16 && class_id < 0)
+            || (format_version >= 16 && class_id == CLASS_MONO_BEHAVIOUR)
+        {
+            let _script_id = reader.read_bytes(16)?;
+        }
+        let _old_type_hash = reader.read_bytes(16)?;
+    }
+
+    let mut type_tree = None;
+
+    if enable_type_tree {
+        if format_version < 12 && format_version != 10 {
+            bail!(
+                "SerializedFile version {format_version} uses legacy type trees, which aren't supported"
+            );
+        }
+
+        type_tree = Some(TypeTreeNode::read_blob(reader, format_version)?);
+
+        if format_version >= 21 {
+            if is_ref_type {
+                let _class_name = reader.read_cstring()?;
+                let _namespace = reader.read_cstring()?;
+                let _assembly_name = reader.read_cstring()?;
+            } else {
+                let dependency_count = reader.read_i32()?;
+                for _ in 0..dependency_count {
+                    reader.read_i32()?;
+                }
+            }
+        }
+    }
+
+    Ok(SerializedType {
+        class_id,
+        type_tree,
+    })
+}
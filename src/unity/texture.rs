@@ -0,0 +1,498 @@
+use std::fmt;
+
+use anyhow::{Context, Result, bail};
+use image::{
+    RgbaImage,
+    imageops::{self, FilterType, flip_vertical_in_place},
+};
+use log::{debug, info};
+
+use super::{
+    astc::encode_astc,
+    bundle::UnityBundle,
+    etc::{encode_etc_rgb, encode_etc2_rgba8},
+    serialized::{CLASS_SPRITE, CLASS_TEXTURE2D, ObjectInfo, SerializedFile},
+    typetree::UnityValue,
+};
+
+const FORMAT_ETC_RGB4: i64 = 34;
+const FORMAT_ETC2_RGB: i64 = 45;
+const FORMAT_ETC2_RGBA8: i64 = 47;
+/// ASTC_4x4 through ASTC_12x12, followed by their deprecated RGBA variants
+const FORMATS_ASTC: std::ops::RangeInclusive<i64> = 48..=59;
+const ASTC_BLOCK_SIZES: [usize; 6] = [4, 5, 6, 8, 10, 12];
+
+/// Largest width or height Unity allows for a texture.
+const MAX_TEXTURE_SIZE: u32 = 16384;
+
+/// Returned when a texture uses a format that can't be encoded natively, so callers can fall back to UnityPy.
+#[derive(Debug)]
+pub struct UnsupportedTextureFormat(pub i64);
+
+impl fmt::Display for UnsupportedTextureFormat {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Texture format {} can't be encoded natively", self.0)
+    }
+}
+
+impl std::error::Error for UnsupportedTextureFormat {}
+
+/// Texture formats that are stored uncompressed, and can be decoded without a texture codec.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum TextureFormat {
+    Alpha8,
+    ARGB4444,
+    RGB24,
+    RGBA32,
+    ARGB32,
+    RGB565,
+    RGBA4444,
+    BGRA32,
+    R8,
+}
+
+impl TextureFormat {
+    fn from_id(id: i64) -> Option<TextureFormat> {
+        Some(match id {
+            1 => TextureFormat::Alpha8,
+            2 => TextureFormat::ARGB4444,
+            3 => TextureFormat::RGB24,
+            4 => TextureFormat::RGBA32,
+            5 => TextureFormat::ARGB32,
+            7 => TextureFormat::RGB565,
+            13 => TextureFormat::RGBA4444,
+            14 => TextureFormat::BGRA32,
+            63 => TextureFormat::R8,
+            _ => return None,
+        })
+    }
+
+    fn bytes_per_pixel(self) -> usize {
+        match self {
+            TextureFormat::Alpha8 | TextureFormat::R8 => 1,
+            TextureFormat::ARGB4444 | TextureFormat::RGB565 | TextureFormat::RGBA4444 => 2,
+            TextureFormat::RGB24 => 3,
+            TextureFormat::RGBA32 | TextureFormat::ARGB32 | TextureFormat::BGRA32 => 4,
+        }
+    }
+
+    fn write_rgba(self, [r, g, b, a]: [u8; 4], output: &mut Vec<u8>) {
+        let pack4 = |values: [u8; 4]| {
+            values
+                .iter()
+                .fold(0u16, |packed, value| packed << 4 | (*value >> 4) as u16)
+        };
+
+        match self {
+            TextureFormat::Alpha8 => output.push(a),
+            TextureFormat::R8 => output.push(r),
+            TextureFormat::RGB24 => output.extend_from_slice(&[r, g, b]),
+            TextureFormat::RGBA32 => output.extend_from_slice(&[r, g, b, a]),
+            TextureFormat::ARGB32 => output.extend_from_slice(&[a, r, g, b]),
+            TextureFormat::BGRA32 => output.extend_from_slice(&[b, g, r, a]),
+            TextureFormat::RGB565 => {
+                let value = (r as u16 >> 3) << 11 | (g as u16 >> 2) << 5 | b as u16 >> 3;
+                output.extend_from_slice(&value.to_le_bytes());
+            }
+            TextureFormat::RGBA4444 => output.extend_from_slice(&pack4([r, g, b, a]).to_le_bytes()),
+            TextureFormat::ARGB4444 => output.extend_from_slice(&pack4([a, r, g, b]).to_le_bytes()),
+        }
+    }
+
+    fn to_rgba(self, pixel: &[u8]) -> [u8; 4] {
+        let expand4 = |nibble: u8| nibble << 4 | nibble;
+
+        match self {
+            TextureFormat::Alpha8 => [255, 255, 255, pixel[0]],
+            TextureFormat::R8 => [pixel[0], 0, 0, 255],
+            TextureFormat::RGB24 => [pixel[0], pixel[1], pixel[2], 255],
+            TextureFormat::RGBA32 => [pixel[0], pixel[1], pixel[2], pixel[3]],
+            TextureFormat::ARGB32 => [pixel[1], pixel[2], pixel[3], pixel[0]],
+            TextureFormat::BGRA32 => [pixel[2], pixel[1], pixel[0], pixel[3]],
+            TextureFormat::RGB565 => {
+                let value = u16::from_le_bytes([pixel[0], pixel[1]]);
+                let (r, g, b) = (value >> 11, (value >> 5) & 0x3F, value & 0x1F);
+                [
+                    (r << 3 | r >> 2) as u8,
+                    (g << 2 | g >> 4) as u8,
+                    (b << 3 | b >> 2) as u8,
+                    255,
+                ]
+            }
+            TextureFormat::RGBA4444 => {
+                let value = u16::from_le_bytes([pixel[0], pixel[1]]);
+                [
+                    expand4((value >> 12) as u8 & 0xF),
+                    expand4((value >> 8) as u8 & 0xF),
+                    expand4((value >> 4) as u8 & 0xF),
+                    expand4(value as u8 & 0xF),
+                ]
+            }
+            TextureFormat::ARGB4444 => {
+                let value = u16::from_le_bytes([pixel[0], pixel[1]]);
+                [
+                    expand4((value >> 8) as u8 & 0xF),
+                    expand4((value >> 4) as u8 & 0xF),
+                    expand4(value as u8 & 0xF),
+                    expand4((value >> 12) as u8 & 0xF),
+                ]
+            }
+        }
+    }
+}
+
+/// Decodes the first mip of a Texture2D into an image, reading its data from the .resS node of `bundle` if it is streamed.
+pub fn read_texture2d(
+    bundle: &UnityBundle,
+    file: &SerializedFile,
+    object: &ObjectInfo,
+) -> Result<RgbaImage> {
+    let texture = file.read_object(object)?;
+
+    let int_field = |name: &str| {
+        texture
+            .field(name)
+            .and_then(|value| value.as_i64())
+            .with_context(|| format!("Texture2D has no {name}"))
+    };
+
+    let width = texture_dimension(int_field("m_Width")?)?;
+    let height = texture_dimension(int_field("m_Height")?)?;
+    let format_id = int_field("m_TextureFormat")?;
+
+    let Some(format) = TextureFormat::from_id(format_id) else {
+        bail!("Texture2D uses compressed texture format {format_id}, which can't be decoded");
+    };
+
+    let mut data = texture
+        .field("image data")
+        .and_then(|value| value.as_bytes())
+        .unwrap_or_default();
+
+    // Large textures keep their data in a separate node of the bundle
+    if data.is_empty()
+        && let Some(stream_data) = texture.field("m_StreamData")
+    {
+        let path = stream_data
+            .field("path")
+            .and_then(|value| value.as_str())
+            .unwrap_or_default();
+
+        if !path.is_empty() {
+            let range = |name: &str| {
+                stream_data
+                    .field(name)
+                    .and_then(|value| value.as_i64())
+                    .and_then(|value| usize::try_from(value).ok())
+            };
+
+            let node_data = &bundle
+                .node(path)
+                .with_context(|| format!("AssetBundle is missing {path}"))?
+                .data;
+
+            data = range("offset")
+                .zip(range("size"))
+                .and_then(|(offset, size)| node_data.get(offset..offset.checked_add(size)?))
+                .with_context(|| format!("Texture data lies outside of {path}"))?;
+        }
+    }
+
+    let data = (width as usize)
+        .checked_mul(height as usize)
+        .and_then(|pixel_count| pixel_count.checked_mul(format.bytes_per_pixel()))
+        .and_then(|size| data.get(..size))
+        .context("Texture2D has less data than its size needs")?;
+
+    let mut image = RgbaImage::new(width, height);
+    for (pixel, source) in image
+        .pixels_mut()
+        .zip(data.chunks_exact(format.bytes_per_pixel()))
+    {
+        pixel.0 = format.to_rgba(source);
+    }
+
+    // Unity stores textures bottom row first
+    flip_vertical_in_place(&mut image);
+
+    Ok(image)
+}
+
+/// Checks a width or height read from a Texture2D before anything is allocated for it.
+fn texture_dimension(value: i64) -> Result<u32> {
+    u32::try_from(value)
+        .ok()
+        .filter(|value| (1..=MAX_TEXTURE_SIZE).contains(value))
+        .with_context(|| format!("Texture2D has an invalid size of {value}"))
+}
+
+/// Calls `f` with the pixels of every `block_width` x `block_height` block of `image`, row by row.
+/// Blocks running past the edge repeat the edge pixels, as compressed formats always store whole blocks.
+pub fn for_each_block(
+    image: &RgbaImage,
+    block_width: usize,
+    block_height: usize,
+    mut f: impl FnMut(&[[u8; 4]]),
+) {
+    let (width, height) = (image.width() as usize, image.height() as usize);
+    let mut pixels = Vec::with_capacity(block_width * block_height);
+
+    for block_y in (0..height).step_by(block_height) {
+        for block_x in (0..width).step_by(block_width) {
+            pixels.clear();
+            for y in block_y..block_y + block_height {
+                for x in block_x..block_x + block_width {
+                    pixels.push(
+                        image
+                            .get_pixel(x.min(width - 1) as u32, y.min(height - 1) as u32)
+                            .0,
+                    );
+                }
+            }
+            f(&pixels);
+        }
+    }
+}
+
+/// Encodes an image, already flipped into Unity's row order, in texture format `format_id`.
+fn encode_texture(image: &RgbaImage, format_id: i64) -> Result<Vec<u8>> {
+    if let Some(format) = TextureFormat::from_id(format_id) {
+        let mut output = Vec::with_capacity(image.len() / 4 * format.bytes_per_pixel());
+        for pixel in image.pixels() {
+            format.write_rgba(pixel.0, &mut output);
+        }
+        return Ok(output);
+    }
+
+    Ok(match format_id {
+        FORMAT_ETC_RGB4 | FORMAT_ETC2_RGB => encode_etc_rgb(image),
+        FORMAT_ETC2_RGBA8 => encode_etc2_rgba8(image),
+        id if FORMATS_ASTC.contains(&id) => {
+            let block_size = ASTC_BLOCK_SIZES[(id - FORMATS_ASTC.start()) as usize % 6];
+            encode_astc(image, block_size, block_size)
+        }
+        _ => bail!(UnsupportedTextureFormat(format_id)),
+    })
+}
+
+/// Replaces the Texture2D named `name` with `image`, resized to the texture's dimensions and encoded in its format.
+/// Sprites of the texture are updated to cover the whole new image.
+/// Returns false if there is no such texture, and fails with `UnsupportedTextureFormat` if its format can't be encoded.
+pub fn replace_texture2d(bundle: &mut UnityBundle, name: &str, image: &RgbaImage) -> Result<bool> {
+    let Some((node_index, path_id, mut texture)) = find_texture(bundle, name)? else {
+        return Ok(false);
+    };
+
+    let int_field = |name: &str| {
+        texture
+            .field(name)
+            .and_then(|value| value.as_i64())
+            .with_context(|| format!("Texture2D has no {name}"))
+    };
+
+    let width = texture_dimension(int_field("m_Width")?)?;
+    let height = texture_dimension(int_field("m_Height")?)?;
+    let format_id = int_field("m_TextureFormat")?;
+
+    let mut image = fit_to_texture(image, name, width, height);
+
+    // Unity stores textures bottom row first
+    flip_vertical_in_place(&mut image);
+    let data = encode_texture(&image, format_id)?;
+
+    // Texture data is always streamed from the .resS node, like Unity does for anything but tiny textures
+    let serialized_path = bundle.nodes[node_index].path.clone();
+    let stream_data = texture.field("m_StreamData");
+    let previous_path = stream_data
+        .and_then(|value| value.field("path"))
+        .and_then(|value| value.as_str())
+        .filter(|path| !path.is_empty())
+        .map(str::to_owned);
+    let previous = stream_data.and_then(|value| {
+        Some((
+            usize::try_from(value.field("offset")?.as_i64()?).ok()?,
+            usize::try_from(value.field("size")?.as_i64()?).ok()?,
+        ))
+    });
+
+    let resource_path = previous_path
+        .clone()
+        .unwrap_or_else(|| format!("archive:/{serialized_path}/{serialized_path}.resS"));
+    let resource_name = resource_path.rsplit('/').next().unwrap_or(&resource_path);
+    let offset = bundle.write_resource(
+        resource_name,
+        previous.filter(|_| previous_path.is_some()),
+        &data,
+    );
+
+    debug!(
+        "Wrote {} bytes of {name} to {resource_name} at {offset}",
+        data.len()
+    );
+
+    // Mipmaps aren't generated, so only the first level is kept
+    set_number(&mut texture, &["m_CompleteImageSize"], data.len() as f64);
+    set_number(&mut texture, &["m_MipCount"], 1.0);
+    set_number(&mut texture, &["m_StreamData", "offset"], offset as f64);
+    set_number(&mut texture, &["m_StreamData", "size"], data.len() as f64);
+    if let Some(path) = texture
+        .field_mut("m_StreamData")
+        .and_then(|stream_data| stream_data.field_mut("path"))
+    {
+        *path = UnityValue::String(resource_path);
+    }
+    if let Some(image_data) = texture.field_mut("image data") {
+        *image_data = UnityValue::Bytes(Vec::new());
+    }
+
+    rewrite_object(bundle, node_index, path_id, &texture)?;
+    update_sprites(bundle, node_index, path_id, width, height)?;
+
+    Ok(true)
+}
+
+/// Returns the width and height of the Texture2D named `name`, whatever its format.
+pub fn texture_dimensions(bundle: &UnityBundle, name: &str) -> Result<Option<(u32, u32)>> {
+    let Some((_, _, texture)) = find_texture(bundle, name)? else {
+        return Ok(None);
+    };
+
+    let int_field = |name: &str| {
+        texture
+            .field(name)
+            .and_then(|value| value.as_i64())
+            .with_context(|| format!("Texture2D has no {name}"))
+    };
+
+    Ok(Some((
+        texture_dimension(int_field("m_Width")?)?,
+        texture_dimension(int_field("m_Height")?)?,
+    )))
+}
+
+/// Resizes `image` to the `width` x `height` of the texture `name` it replaces, unless it already is that size.
+pub fn fit_to_texture(image: &RgbaImage, name: &str, width: u32, height: u32) -> RgbaImage {
+    if image.dimensions() == (width, height) {
+        return image.clone();
+    }
+
+    info!(
+        "Resizing {name} from {}x{} to {width}x{height}",
+        image.width(),
+        image.height()
+    );
+    imageops::resize(image, width, height, FilterType::Lanczos3)
+}
+
+fn find_texture(bundle: &UnityBundle, name: &str) -> Result<Option<(usize, i64, UnityValue)>> {
+    for (node_index, node) in bundle.nodes.iter().enumerate() {
+        if !node.is_serialized_file() {
+            continue;
+        }
+
+        let file = SerializedFile::parse(&node.data)?;
+        if let Some(object) = file.find_named(CLASS_TEXTURE2D, name)? {
+            return Ok(Some((
+                node_index,
+                object.path_id,
+                file.read_object(object)?,
+            )));
+        }
+    }
+
+    Ok(None)
+}
+
+/// Points every Sprite of the texture `texture_path_id` at the whole texture.
+fn update_sprites(
+    bundle: &mut UnityBundle,
+    node_index: usize,
+    texture_path_id: i64,
+    width: u32,
+    height: u32,
+) -> Result<()> {
+    let sprites: Vec<(i64, UnityValue)> = {
+        let file = SerializedFile::parse(&bundle.nodes[node_index].data)?;
+        let mut sprites = Vec::new();
+
+        for object in file
+            .objects
+            .iter()
+            .filter(|object| object.class_id == CLASS_SPRITE)
+        {
+            let sprite = file.read_object(object)?;
+            let texture = sprite
+                .field("m_RD")
+                .and_then(|render_data| render_data.field("texture"));
+            let points_at = |field: &str| texture.and_then(|t| t.field(field)?.as_i64());
+
+            if points_at("m_FileID") == Some(0) && points_at("m_PathID") == Some(texture_path_id) {
+                sprites.push((object.path_id, sprite));
+            }
+        }
+
+        sprites
+    };
+
+    for (path_id, mut sprite) in sprites {
+        debug!("Updating rects of sprite {path_id}");
+
+        for rect in [&["m_Rect"][..], &["m_RD", "textureRect"]] {
+            for (field, value) in [
+                ("x", 0.0),
+                ("y", 0.0),
+                ("width", width as f64),
+                ("height", height as f64),
+            ] {
+                set_number(&mut sprite, &[rect, &[field]].concat(), value);
+            }
+        }
+        set_number(&mut sprite, &["m_RD", "textureRectOffset", "x"], 0.0);
+        set_number(&mut sprite, &["m_RD", "textureRectOffset", "y"], 0.0);
+
+        rewrite_object(bundle, node_index, path_id, &sprite)?;
+    }
+
+    Ok(())
+}
+
+/// Writes `value` over the object `path_id` of the SerializedFile in node `node_index`.
+fn rewrite_object(
+    bundle: &mut UnityBundle,
+    node_index: usize,
+    path_id: i64,
+    value: &UnityValue,
+) -> Result<()> {
+    let data = {
+        let file = SerializedFile::parse(&bundle.nodes[node_index].data)?;
+        let object = file
+            .object(path_id)
+            .with_context(|| format!("No object with path id {path_id}"))?;
+
+        let object_data = file.write_object(object, &serde_json::to_value(value)?)?;
+        file.replace_object(path_id, &object_data)?
+    };
+
+    bundle.nodes[node_index].data = data;
+    Ok(())
+}
+
+/// Sets the number at `path` within `value`, keeping the type it was read as.
+/// Fields the Unity version of the bundle doesn't have are skipped.
+fn set_number(value: &mut UnityValue, path: &[&str], number: f64) {
+    let mut current = value;
+    for name in path {
+        match current.field_mut(name) {
+            Some(field) => current = field,
+            None => return,
+        }
+    }
+
+    *current = match current {
+        UnityValue::Float(_) => UnityValue::Float(number),
+        UnityValue::Int(_) => UnityValue::Int(number as i64),
+        UnityValue::UInt(_) => UnityValue::UInt(number as u64),
+        UnityValue::Bool(_) => UnityValue::Bool(number != 0.0),
+        _ => return,
+    };
+}
@@ -0,0 +1,528 @@
+use anyhow::{Context, Result, bail};
+use indexmap::IndexMap;
+use serde::{Serialize, Serializer, ser::SerializeMap};
+use serde_json::Value;
+
+use super::{reader::EndianReader, writer::EndianWriter};
+
+/// Strings shared by every type tree, referenced by setting the top bit of a string offset.
+/// Their offsets are their position in this list joined with null terminators.
+const COMMON_STRINGS: &[&str] = &[
+    "AABB",
+    "AnimationClip",
+    "AnimationCurve",
+    "AnimationState",
+    "Array",
+    "Base",
+    "BitField",
+    "bitset",
+    "bool",
+    "char",
+    "ColorRGBA",
+    "Component",
+    "data",
+    "deque",
+    "double",
+    "dynamic_array",
+    "FastPropertyName",
+    "first",
+    "float",
+    "Font",
+    "GameObject",
+    "Generic Mono",
+    "GradientNEW",
+    "GUID",
+    "GUIStyle",
+    "int",
+    "list",
+    "long long",
+    "map",
+    "Matrix4x4f",
+    "MdFour",
+    "MonoBehaviour",
+    "MonoScript",
+    "m_ByteSize",
+    "m_Curve",
+    "m_EditorClassIdentifier",
+    "m_EditorHideFlags",
+    "m_Enabled",
+    "m_ExtensionPtr",
+    "m_GameObject",
+    "m_Index",
+    "m_IsArray",
+    "m_IsStatic",
+    "m_MetaFlag",
+    "m_Name",
+    "m_ObjectHideFlags",
+    "m_PrefabInternal",
+    "m_PrefabParentObject",
+    "m_Script",
+    "m_StaticEditorFlags",
+    "m_Type",
+    "m_Version",
+    "Object",
+    "pair",
+    "PPtr<Component>",
+    "PPtr<GameObject>",
+    "PPtr<Material>",
+    "PPtr<MonoBehaviour>",
+    "PPtr<MonoScript>",
+    "PPtr<Object>",
+    "PPtr<Prefab>",
+    "PPtr<Sprite>",
+    "PPtr<TextAsset>",
+    "PPtr<Texture>",
+    "PPtr<Texture2D>",
+    "PPtr<Transform>",
+    "Prefab",
+    "Quaternionf",
+    "Rectf",
+    "RectInt",
+    "RectOffset",
+    "second",
+    "set",
+    "short",
+    "size",
+    "SInt16",
+    "SInt32",
+    "SInt64",
+    "SInt8",
+    "staticvector",
+    "string",
+    "TextAsset",
+    "TextMesh",
+    "Texture",
+    "Texture2D",
+    "Transform",
+    "TypelessData",
+    "UInt16",
+    "UInt32",
+    "UInt64",
+    "UInt8",
+    "unsigned int",
+    "unsigned long long",
+    "unsigned short",
+    "vector",
+    "Vector2f",
+    "Vector3f",
+    "Vector4f",
+    "m_ScriptingClassIdentifier",
+    "Gradient",
+    "Type*",
+    "int2_storage",
+    "int3_storage",
+    "BoundsInt",
+    "m_CorrespondingSourceObject",
+    "m_PrefabInstance",
+    "m_PrefabAsset",
+    "FileSize",
+    "Hash128",
+];
+
+/// Set on nodes whose value is followed by padding up to a multiple of 4 bytes.
+const ALIGN_FLAG: i32 = 0x4000;
+
+/// One field of a type tree, describing how a serialized object is laid out.
+#[derive(Debug, Clone)]
+pub struct TypeTreeNode {
+    pub type_name: String,
+    pub name: String,
+    pub is_array: bool,
+    pub meta_flag: i32,
+    pub children: Vec<TypeTreeNode>,
+}
+
+/// A value read from a serialized object using its type tree.
+#[derive(Debug, Clone, PartialEq)]
+pub enum UnityValue {
+    Bool(bool),
+    Int(i64),
+    UInt(u64),
+    Float(f64),
+    String(String),
+    /// TypelessData and other raw byte arrays, such as texture data
+    Bytes(Vec<u8>),
+    Array(Vec<UnityValue>),
+    /// Keys and values of a map, in serialized order
+    Map(Vec<(UnityValue, UnityValue)>),
+    Struct(IndexMap<String, UnityValue>),
+}
+
+impl UnityValue {
+    /// Returns the field `name` of a struct value.
+    pub fn field(&self, name: &str) -> Option<&UnityValue> {
+        match self {
+            UnityValue::Struct(fields) => fields.get(name),
+            _ => None,
+        }
+    }
+
+    pub fn field_mut(&mut self, name: &str) -> Option<&mut UnityValue> {
+        match self {
+            UnityValue::Struct(fields) => fields.get_mut(name),
+            _ => None,
+        }
+    }
+
+    pub fn as_i64(&self) -> Option<i64> {
+        match self {
+            UnityValue::Int(value) => Some(*value),
+            UnityValue::UInt(value) => i64::try_from(*value).ok(),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            UnityValue::String(value) => Some(value),
+            _ => None,
+        }
+    }
+
+    pub fn as_bytes(&self) -> Option<&[u8]> {
+        match self {
+            UnityValue::Bytes(value) => Some(value),
+            _ => None,
+        }
+    }
+}
+
+/// Serializes like the dicts UnityPy returns from `read_typetree`, so `serde_json::to_value` and friends can turn it into typed structs.
+impl Serialize for UnityValue {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        match self {
+            UnityValue::Bool(value) => serializer.serialize_bool(*value),
+            UnityValue::Int(value) => serializer.serialize_i64(*value),
+            UnityValue::UInt(value) => serializer.serialize_u64(*value),
+            UnityValue::Float(value) => serializer.serialize_f64(*value),
+            UnityValue::String(value) => serializer.serialize_str(value),
+            UnityValue::Bytes(value) => serializer.collect_seq(value),
+            UnityValue::Array(values) => serializer.collect_seq(values),
+            UnityValue::Map(entries) => serializer.collect_seq(entries),
+            UnityValue::Struct(fields) => {
+                let mut map = serializer.serialize_map(Some(fields.len()))?;
+                for (name, value) in fields {
+                    map.serialize_entry(name, value)?;
+                }
+                map.end()
+            }
+        }
+    }
+}
+
+impl TypeTreeNode {
+    /// Reads a type tree stored in the blob format used since Unity 5.
+    pub fn read_blob(reader: &mut EndianReader, format_version: u32) -> Result<TypeTreeNode> {
+        let node_count = reader.read_i32()?;
+        let string_buffer_size = reader.read_i32()?;
+
+        let mut flat_nodes = Vec::new();
+
+        for _ in 0..node_count {
+            let _version = reader.read_u16()?;
+            let level = reader.read_u8()?;
+            let type_flags = reader.read_u8()?;
+            let type_offset = reader.read_u32()?;
+            let name_offset = reader.read_u32()?;
+            let _byte_size = reader.read_i32()?;
+            let _index = reader.read_i32()?;
+            let meta_flag = reader.read_i32()?;
+
+            if format_version >= 19 {
+                let _ref_type_hash = reader.read_u64()?;
+            }
+
+            flat_nodes.push((level, type_flags, type_offset, name_offset, meta_flag));
+        }
+
+        let string_buffer = reader.read_bytes(string_buffer_size as usize)?;
+
+        let flat_nodes = flat_nodes
+            .into_iter()
+            .map(|(level, type_flags, type_offset, name_offset, meta_flag)| {
+                let type_name = lookup_string(string_buffer, type_offset)?;
+
+                Ok((
+                    level,
+                    TypeTreeNode {
+                        is_array: type_flags & 1 != 0 || type_name == "Array",
+                        type_name,
+                        name: lookup_string(string_buffer, name_offset)?,
+                        meta_flag,
+                        children: Vec::new(),
+                    },
+                ))
+            })
+            .collect::<Result<Vec<_>>>()?;
+
+        build_tree(flat_nodes)
+    }
+
+    /// Reads a value laid out as described by this node.
+    pub fn read_value(&self, reader: &mut EndianReader) -> Result<UnityValue> {
+        let mut align = self.meta_flag & ALIGN_FLAG != 0;
+
+        let value = match self.type_name.as_str() {
+            "bool" => UnityValue::Bool(reader.read_bool()?),
+            "SInt8" => UnityValue::Int(reader.read_i8()?.into()),
+            "UInt8" | "char" => UnityValue::UInt(reader.read_u8()?.into()),
+            "SInt16" | "short" => UnityValue::Int(reader.read_i16()?.into()),
+            "UInt16" | "unsigned short" => UnityValue::UInt(reader.read_u16()?.into()),
+            "SInt32" | "int" => UnityValue::Int(reader.read_i32()?.into()),
+            "UInt32" | "unsigned int" | "Type*" => UnityValue::UInt(reader.read_u32()?.into()),
+            "SInt64" | "long long" => UnityValue::Int(reader.read_i64()?),
+            "UInt64" | "unsigned long long" | "FileSize" => UnityValue::UInt(reader.read_u64()?),
+            "float" => UnityValue::Float(reader.read_f32()?.into()),
+            "double" => UnityValue::Float(reader.read_f64()?),
+            "string" => {
+                align |= self.array_child()?.meta_flag & ALIGN_FLAG != 0;
+                UnityValue::String(reader.read_string()?)
+            }
+            "TypelessData" => {
+                let size = read_size(reader)?;
+                UnityValue::Bytes(reader.read_bytes(size)?.to_vec())
+            }
+            "map" => {
+                let array = self.array_child()?;
+                align |= array.meta_flag & ALIGN_FLAG != 0;
+
+                let pair = array.children.get(1).context("map has no pair node")?;
+                let (first, second) = match pair.children.as_slice() {
+                    [first, second] => (first, second),
+                    _ => bail!("map pair of {} does not have two fields", self.name),
+                };
+
+                let size = read_size(reader)?;
+                let mut entries = Vec::with_capacity(size.min(4096));
+                for _ in 0..size {
+                    entries.push((first.read_value(reader)?, second.read_value(reader)?));
+                }
+
+                UnityValue::Map(entries)
+            }
+            _ if self.children.first().is_some_and(|child| child.is_array) => {
+                let array = &self.children[0];
+                align |= array.meta_flag & ALIGN_FLAG != 0;
+
+                let element = array
+                    .children
+                    .get(1)
+                    .with_context(|| format!("Array {} has no element type", self.name))?;
+                let size = read_size(reader)?;
+
+                if element.is_byte() {
+                    UnityValue::Bytes(reader.read_bytes(size)?.to_vec())
+                } else {
+                    let mut values = Vec::with_capacity(size.min(4096));
+                    for _ in 0..size {
+                        values.push(element.read_value(reader)?);
+                    }
+                    UnityValue::Array(values)
+                }
+            }
+            _ => {
+                let mut fields = IndexMap::new();
+                for child in &self.children {
+                    fields.insert(child.name.clone(), child.read_value(reader)?);
+                }
+                UnityValue::Struct(fields)
+            }
+        };
+
+        if align {
+            reader.align(4);
+        }
+
+        Ok(value)
+    }
+
+    /// Writes `value` laid out as described by this node, the reverse of `read_value`.
+    /// `value` is shaped like what `read_value` serializes to, so any struct that deserialized from an object can be written back.
+    pub fn write_value(&self, value: &Value, writer: &mut EndianWriter) -> Result<()> {
+        let mut align = self.meta_flag & ALIGN_FLAG != 0;
+
+        match self.type_name.as_str() {
+            "bool" => writer.write_u8(match value {
+                Value::Bool(value) => *value as u8,
+                _ => self.int_value(value)? as u8,
+            }),
+            "SInt8" => writer.write_u8(self.int_value(value)? as i8 as u8),
+            "UInt8" | "char" => writer.write_u8(self.uint_value(value)? as u8),
+            "SInt16" | "short" => writer.write_i16(self.int_value(value)? as i16),
+            "UInt16" | "unsigned short" => writer.write_u16(self.uint_value(value)? as u16),
+            "SInt32" | "int" => writer.write_i32(self.int_value(value)? as i32),
+            "UInt32" | "unsigned int" | "Type*" => writer.write_u32(self.uint_value(value)? as u32),
+            "SInt64" | "long long" => writer.write_i64(self.int_value(value)?),
+            "UInt64" | "unsigned long long" | "FileSize" => {
+                writer.write_u64(self.uint_value(value)?)
+            }
+            "float" => writer.write_f32(self.float_value(value)? as f32),
+            "double" => writer.write_f64(self.float_value(value)?),
+            "string" => {
+                align |= self.array_child()?.meta_flag & ALIGN_FLAG != 0;
+                let string = value
+                    .as_str()
+                    .with_context(|| format!("{} must be a string", self.name))?;
+                writer.write_string(string);
+            }
+            "TypelessData" => {
+                let bytes = self.array_value(value)?;
+                writer.write_i32(bytes.len() as i32);
+                for byte in bytes {
+                    writer.write_u8(self.uint_value(byte)? as u8);
+                }
+            }
+            "map" => {
+                let array = self.array_child()?;
+                align |= array.meta_flag & ALIGN_FLAG != 0;
+
+                let pair = array.children.get(1).context("map has no pair node")?;
+                let (first, second) = match pair.children.as_slice() {
+                    [first, second] => (first, second),
+                    _ => bail!("map pair of {} does not have two fields", self.name),
+                };
+
+                let entries = self.array_value(value)?;
+                writer.write_i32(entries.len() as i32);
+                for entry in entries {
+                    match entry.as_array().map(Vec::as_slice) {
+                        Some([key, value]) => {
+                            first.write_value(key, writer)?;
+                            second.write_value(value, writer)?;
+                        }
+                        _ => bail!("Entries of map {} must be [key, value] pairs", self.name),
+                    }
+                }
+            }
+            _ if self.children.first().is_some_and(|child| child.is_array) => {
+                let array = &self.children[0];
+                align |= array.meta_flag & ALIGN_FLAG != 0;
+
+                let element = array
+                    .children
+                    .get(1)
+                    .with_context(|| format!("Array {} has no element type", self.name))?;
+
+                let values = self.array_value(value)?;
+                writer.write_i32(values.len() as i32);
+                for value in values {
+                    element.write_value(value, writer)?;
+                }
+            }
+            _ => {
+                for child in &self.children {
+                    let field = value.get(&child.name).with_context(|| {
+                        format!("{} is missing field {}", self.name, child.name)
+                    })?;
+                    child
+                        .write_value(field, writer)
+                        .with_context(|| format!("Could not write {}", child.name))?;
+                }
+            }
+        }
+
+        if align {
+            writer.align(4);
+        }
+
+        Ok(())
+    }
+
+    fn int_value(&self, value: &Value) -> Result<i64> {
+        value
+            .as_i64()
+            .or_else(|| value.as_bool().map(i64::from))
+            .with_context(|| format!("{} must be an integer, not {value}", self.name))
+    }
+
+    fn uint_value(&self, value: &Value) -> Result<u64> {
+        value
+            .as_u64()
+            .or_else(|| value.as_bool().map(u64::from))
+            .with_context(|| format!("{} must be a positive integer, not {value}", self.name))
+    }
+
+    fn float_value(&self, value: &Value) -> Result<f64> {
+        value
+            .as_f64()
+            .with_context(|| format!("{} must be a number, not {value}", self.name))
+    }
+
+    fn array_value<'v>(&self, value: &'v Value) -> Result<&'v Vec<Value>> {
+        value
+            .as_array()
+            .with_context(|| format!("{} must be an array", self.name))
+    }
+
+    fn array_child(&self) -> Result<&TypeTreeNode> {
+        self.children
+            .first()
+            .filter(|child| child.is_array)
+            .with_context(|| format!("{} {} has no Array node", self.type_name, self.name))
+    }
+
+    /// Unaligned single byte element, which is stored as raw bytes.
+    fn is_byte(&self) -> bool {
+        matches!(self.type_name.as_str(), "UInt8" | "char") && self.meta_flag & ALIGN_FLAG == 0
+    }
+}
+
+fn read_size(reader: &mut EndianReader) -> Result<usize> {
+    let size = reader.read_i32()?;
+    usize::try_from(size).with_context(|| format!("Negative array size {size}"))
+}
+
+fn lookup_string(string_buffer: &[u8], offset: u32) -> Result<String> {
+    if offset & 0x8000_0000 != 0 {
+        let common_offset = (offset & 0x7FFF_FFFF) as usize;
+        let mut position = 0;
+
+        for string in COMMON_STRINGS {
+            if position == common_offset {
+                return Ok(string.to_string());
+            }
+            position += string.len() + 1;
+        }
+
+        bail!("Unknown common string offset {common_offset}");
+    }
+
+    let rest = string_buffer
+        .get(offset as usize..)
+        .with_context(|| format!("String offset {offset} is outside of the string buffer"))?;
+    let end = rest.iter().position(|b| *b == 0).unwrap_or(rest.len());
+
+    Ok(String::from_utf8_lossy(&rest[..end]).into_owned())
+}
+
+/// Turns the pre-ordered list of (level, node) Unity stores into a tree.
+fn build_tree(flat_nodes: Vec<(u8, TypeTreeNode)>) -> Result<TypeTreeNode> {
+    // Nodes that may still get children, deepest last
+    let mut stack: Vec<(u8, TypeTreeNode)> = Vec::new();
+
+    for (level, node) in flat_nodes {
+        while let Some((top_level, _)) = stack.last()
+            && *top_level >= level
+        {
+            let (_, finished) = stack.pop().unwrap();
+            stack
+                .last_mut()
+                .context("Type tree has more than one root node")?
+                .1
+                .children
+                .push(finished);
+        }
+
+        stack.push((level, node));
+    }
+
+    while stack.len() > 1 {
+        let (_, finished) = stack.pop().unwrap();
+        stack.last_mut().unwrap().1.children.push(finished);
+    }
+
+    stack
+        .pop()
+        .map(|(_, root)| root)
+        .context("Type tree is empty")
+}
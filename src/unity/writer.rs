@@ -0,0 +1,61 @@
+/// Growable buffer that writes Unity's primitives in either byte order.
+pub struct EndianWriter {
+    pub data: Vec<u8>,
+    pub big_endian: bool,
+}
+
+macro_rules! write_primitive {
+    ($name:ident, $ty:ty) => {
+        pub fn $name(&mut self, value: $ty) {
+            if self.big_endian {
+                self.data.extend_from_slice(&value.to_be_bytes());
+            } else {
+                self.data.extend_from_slice(&value.to_le_bytes());
+            }
+        }
+    };
+}
+
+impl EndianWriter {
+    pub fn new(big_endian: bool) -> Self {
+        EndianWriter {
+            data: Vec::new(),
+            big_endian,
+        }
+    }
+
+    write_primitive!(write_u16, u16);
+    write_primitive!(write_i16, i16);
+    write_primitive!(write_u32, u32);
+    write_primitive!(write_i32, i32);
+    write_primitive!(write_u64, u64);
+    write_primitive!(write_i64, i64);
+    write_primitive!(write_f32, f32);
+    write_primitive!(write_f64, f64);
+
+    pub fn write_u8(&mut self, value: u8) {
+        self.data.push(value);
+    }
+
+    pub fn write_bytes(&mut self, bytes: &[u8]) {
+        self.data.extend_from_slice(bytes);
+    }
+
+    /// Writes a null terminated string.
+    pub fn write_cstring(&mut self, string: &str) {
+        self.data.extend_from_slice(string.as_bytes());
+        self.data.push(0);
+    }
+
+    /// Writes an i32 length prefixed string, as used by type trees.
+    pub fn write_string(&mut self, string: &str) {
+        self.write_i32(string.len() as i32);
+        self.data.extend_from_slice(string.as_bytes());
+    }
+
+    /// Pads with zeroes up to the next multiple of `alignment`.
+    pub fn align(&mut self, alignment: usize) {
+        self.data
+            .resize(self.data.len().next_multiple_of(alignment), 0);
+    }
+}
@@ -0,0 +1,320 @@
+// Bridge to UnityPy, used where the native AssetBundle code in unity/ can't handle an asset.
+// A single worker thread owns the Python module and runs every call in the order it was queued,
+// so concurrent exports can't interfere with each other.
+
+use std::{
+    collections::HashMap,
+    ffi::CString,
+    fmt,
+    path::{Path, PathBuf},
+    sync::{OnceLock, mpsc},
+    thread,
+};
+
+use anyhow::{Context, Result, anyhow, bail};
+use log::{debug, error};
+use pyo3::{
+    Bound, Py, PyAny, PyErr, Python,
+    types::{PyAnyMethods, PyBytes, PyBytesMethods, PyModule},
+};
+use pythonize::{depythonize, pythonize};
+use serde_json::Value;
+
+use crate::unity::MonoBehaviourQuery;
+
+/// Contains all relevant UnityPy code, as stateless functions.
+const PY_CODE: &str = include_str!("../python/story_to_assetbundle.py");
+
+/// Every bundle the worker has open, along with the loaded Python module.
+struct Worker {
+    module: Py<PyModule>,
+    bundles: HashMap<u64, Py<PyAny>>,
+    next_id: u64,
+}
+
+/// The worker, or why it could not be started.
+type WorkerState = Result<Worker, String>;
+type Job = Box<dyn FnOnce(&mut WorkerState) + Send>;
+
+static JOBS: OnceLock<mpsc::Sender<Job>> = OnceLock::new();
+
+/// How to find an object in a bundle opened by UnityPy.
+#[derive(Debug, Clone)]
+pub enum ObjectQuery {
+    Named {
+        type_name: String,
+        name: String,
+    },
+    /// Follows the same rules as the native `find_mono_behaviour`
+    MonoBehaviour {
+        script: String,
+        name: Option<String>,
+        fields: Vec<String>,
+    },
+}
+
+impl From<&MonoBehaviourQuery<'_>> for ObjectQuery {
+    fn from(query: &MonoBehaviourQuery) -> Self {
+        ObjectQuery::MonoBehaviour {
+            script: query.script.to_string(),
+            name: query.name.map(str::to_owned),
+            fields: query.fields.iter().map(|field| field.to_string()).collect(),
+        }
+    }
+}
+
+impl fmt::Display for ObjectQuery {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ObjectQuery::Named { type_name, name } => write!(f, "{type_name} named {name}"),
+            ObjectQuery::MonoBehaviour {
+                script,
+                name: Some(name),
+                ..
+            } => write!(f, "named {name} running {script}"),
+            ObjectQuery::MonoBehaviour { script, .. } => write!(f, "running {script}"),
+        }
+    }
+}
+
+/// An AssetBundle loaded by UnityPy. Closed once dropped.
+pub struct UnityPyBundle {
+    id: u64,
+    path: PathBuf,
+}
+
+impl UnityPyBundle {
+    pub fn open(path: &Path) -> Result<UnityPyBundle> {
+        let path_string = path.display().to_string();
+
+        let id = run(move |py, worker| {
+            let env = worker
+                .module
+                .bind(py)
+                .getattr("open_bundle")?
+                .call1((path_string,))?;
+
+            let id = worker.next_id;
+            worker.next_id += 1;
+            worker.bundles.insert(id, env.unbind());
+            Ok(id)
+        })
+        .with_context(|| format!("UnityPy could not open {}", path.display()))?;
+
+        debug!("Opened {} in UnityPy as bundle {id}", path.display());
+
+        Ok(UnityPyBundle {
+            id,
+            path: path.to_path_buf(),
+        })
+    }
+
+    /// Reads the typetree of an object, as UnityPy's `read_typetree` returns it.
+    pub fn read_object(&self, query: &ObjectQuery) -> Result<Value> {
+        let (id, query) = (self.id, query.clone());
+
+        run(move |py, worker| {
+            let object = worker.find(py, id, &query)?;
+            let typetree = worker
+                .module
+                .bind(py)
+                .getattr("read_typetree")?
+                .call1((object,))?;
+
+            Ok(depythonize(&typetree)?)
+        })
+        .with_context(|| format!("UnityPy could not read from {}", self.path.display()))
+    }
+
+    /// Replaces the typetree of an object. Only kept once the bundle is saved.
+    pub fn write_object(&self, query: &ObjectQuery, typetree: &Value) -> Result<()> {
+        let (id, query, typetree) = (self.id, query.clone(), typetree.clone());
+
+        run(move |py, worker| {
+            let object = worker.find(py, id, &query)?;
+            worker
+                .module
+                .bind(py)
+                .getattr("save_typetree")?
+                .call1((object, pythonize(py, &typetree)?))?;
+            Ok(())
+        })
+        .with_context(|| format!("UnityPy could not write to {}", self.path.display()))
+    }
+
+    /// Replaces the image of the Texture2D named `name` with the image at `image_path`.
+    /// Requires passing the path as in UnityPy saving from an buffer in memory is incredibly slow
+    pub fn replace_texture(&self, name: &str, image_path: &Path) -> Result<()> {
+        let id = self.id;
+        let query = texture_query(name);
+        let image_path = image_path.display().to_string();
+
+        run(move |py, worker| {
+            let object = worker.find(py, id, &query)?;
+            worker
+                .module
+                .bind(py)
+                .getattr("replace_texture")?
+                .call1((object, image_path))?;
+            Ok(())
+        })
+        .with_context(|| format!("UnityPy could not replace {name}"))
+    }
+
+    /// Decodes the Texture2D named `name` into PNG bytes.
+    pub fn export_texture(&self, name: &str) -> Result<Vec<u8>> {
+        let id = self.id;
+        let query = texture_query(name);
+
+        run(move |py, worker| {
+            let object = worker.find(py, id, &query)?;
+            let png = worker
+                .module
+                .bind(py)
+                .getattr("export_texture")?
+                .call1((object,))?
+                .downcast_into::<PyBytes>()
+                .map_err(PyErr::from)?;
+
+            Ok(png.as_bytes().to_vec())
+        })
+        .with_context(|| format!("UnityPy could not export {name}"))
+    }
+
+    /// Writes the bundle, with every change made so far, to `path`.
+    pub fn save(&self, path: &Path) -> Result<()> {
+        let id = self.id;
+        let path_string = path.display().to_string();
+
+        run(move |py, worker| {
+            let env = worker.bundle(py, id)?;
+            worker
+                .module
+                .bind(py)
+                .getattr("save_bundle")?
+                .call1((env, path_string))?;
+            Ok(())
+        })
+        .with_context(|| format!("UnityPy could not save {}", path.display()))
+    }
+}
+
+impl Drop for UnityPyBundle {
+    fn drop(&mut self) {
+        let id = self.id;
+
+        // Nothing to wait for, so the job is just queued
+        if let Some(jobs) = JOBS.get() {
+            let _ = jobs.send(Box::new(move |worker: &mut WorkerState| {
+                if let Ok(worker) = worker {
+                    worker.bundles.remove(&id);
+                }
+            }));
+        }
+    }
+}
+
+impl Worker {
+    fn bundle<'py>(&self, py: Python<'py>, id: u64) -> Result<Bound<'py, PyAny>> {
+        Ok(self
+            .bundles
+            .get(&id)
+            .with_context(|| format!("UnityPy bundle {id} was already closed"))?
+            .bind(py)
+            .clone())
+    }
+
+    fn find<'py>(
+        &self,
+        py: Python<'py>,
+        id: u64,
+        query: &ObjectQuery,
+    ) -> Result<Bound<'py, PyAny>> {
+        let module = self.module.bind(py);
+        let bundle = self.bundle(py, id)?;
+
+        let object = match query {
+            ObjectQuery::Named { type_name, name } => module
+                .getattr("find_object")?
+                .call1((bundle, type_name, name))?,
+            ObjectQuery::MonoBehaviour {
+                script,
+                name,
+                fields,
+            } => module.getattr("find_mono_behaviour")?.call1((
+                bundle,
+                script,
+                name,
+                fields.clone(),
+            ))?,
+        };
+
+        if object.is_none() {
+            bail!("No object {query}");
+        }
+
+        Ok(object)
+    }
+}
+
+fn texture_query(name: &str) -> ObjectQuery {
+    ObjectQuery::Named {
+        type_name: "Texture2D".to_string(),
+        name: name.to_string(),
+    }
+}
+
+/// Queues `f` on the worker and waits for its result.
+fn run<T: Send + 'static>(
+    f: impl for<'py> FnOnce(Python<'py>, &mut Worker) -> Result<T> + Send + 'static,
+) -> Result<T> {
+    let (reply, result) = mpsc::channel();
+
+    let job: Job = Box::new(move |worker: &mut WorkerState| {
+        let outcome = match worker {
+            Ok(worker) => Python::attach(|py| f(py, worker)),
+            Err(e) => Err(anyhow!("UnityPy is unavailable: {e}")),
+        };
+
+        let _ = reply.send(outcome);
+    });
+
+    JOBS.get_or_init(start_worker)
+        .send(job)
+        .map_err(|_| anyhow!("UnityPy worker has stopped"))?;
+
+    result
+        .recv()
+        .map_err(|_| anyhow!("UnityPy worker stopped before finishing"))?
+}
+
+fn start_worker() -> mpsc::Sender<Job> {
+    let (sender, receiver) = mpsc::channel::<Job>();
+
+    thread::Builder::new()
+        .name("unitypy".to_string())
+        .spawn(move || {
+            let mut worker = Python::attach(|py| {
+                let filename = CString::new("story_to_assetbundle.py").unwrap();
+                let modname = CString::new("story_to_assetbundle").unwrap();
+
+                PyModule::from_code(py, &CString::new(PY_CODE).unwrap(), &filename, &modname)
+                    .map(|module| Worker {
+                        module: module.unbind(),
+                        bundles: HashMap::new(),
+                        next_id: 0,
+                    })
+                    .map_err(|e| {
+                        error!("Could not load UnityPy! Is it installed? Err: {e}");
+                        e.to_string()
+                    })
+            });
+
+            for job in receiver {
+                job(&mut worker);
+            }
+        })
+        .expect("failed to spawn UnityPy worker thread");
+
+    sender
+}
@@ -1,5 +1,6 @@
-use std::collections::HashMap;
+use std::{collections::HashMap, fs::File};
 
+use anyhow::Result;
 use indexmap::IndexMap;
 use serde::{Deserialize, Serialize};
 
@@ -52,6 +53,32 @@ pub struct AssetConfig {
     pub needed_live2d_files: Vec<String>,
 }
 
+/// The parts of versions.json MikuMikuLoader reads, the rest is ignored
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Versions {
+    pub app_hash: String,
+    pub asset_version: String,
+    pub data_version: String,
+    pub asset_hash: String,
+}
+
+/// Reads the versions.json downloaded into the asset path
+pub fn load_versions(config: &Config) -> Result<Versions> {
+    let versions_path = format!("{}/versions.json", config.advanced.assets.asset_path);
+    let version_file = File::open(versions_path)?;
+
+    Ok(serde_json::from_reader(version_file)?)
+}
+
+/// Reads the asset version from versions.json, or an empty one if it can't be read.
+/// Without versions.json, mods are just not checked against the asset version.
+pub fn load_asset_version(config: &Config) -> String {
+    load_versions(config)
+        .map(|versions| versions.asset_version)
+        .unwrap_or_default()
+}
+
 #[allow(dead_code)] // Not all items will be used, but all are required for deserialization
 #[derive(Debug, Deserialize)]
 #[serde(rename_all = "camelCase")]
@@ -135,6 +135,8 @@ document.getElementById("generate-mod").addEventListener("click", function (even
 
     const storyFile = document.getElementById("modpackfile").value;
     const modpackName = document.getElementById("modpackname").value;
+    const targetEvent = document.getElementById("target-event").value;
+    const targetScenarioId = document.getElementById("target-scenario-id").value;
 
     const data = JSON.stringify({
         file_name: storyFile,
@@ -144,6 +146,10 @@ document.getElementById("generate-mod").addEventListener("click", function (even
         title_background: title_background,
         logo: logo,
         data: scenesData,
+        target: {
+            event: targetEvent,
+            scenario_id: targetScenarioId || null,
+        },
     });
 
     console.log(`Trying to submit ${data}`);
@@ -14,21 +14,57 @@ fetch('/mod-list')
         if (Object.keys(modListJson).length > 0) {
             for (let i = 0; i < modListJson.length; i++) {
                 const item = document.createElement("li");
-                const [path_dirty, modName, modType, enabled] = modListJson[i];
-                const path = path_dirty.replace("/", "%2F");
-
-                item.innerHTML = `
-          <h2>
-            <strong>${modName}</strong><br>
-            Type: <strong>${modType}</strong><br>
-            <label class="toggle-switch">
-              <input id="${path}" type="checkbox" />
-              <span class="slider"></span>
-            </label>
-          </h2>`;
+                const modEntry = modListJson[i];
+                const metadata = modEntry.metadata;
+                const enabled = modEntry.enabled;
+                const path = modEntry.path.replace("/", "%2F");
+                const stem = modEntry.path.split("/").pop().replace(/\.toml$/, "");
+
+                // Mods can come from other people's .mmlpack archives, so nothing they set is parsed as HTML
+                const heading = document.createElement("h2");
+                appendField(heading, null, modEntry.mod_name);
+                appendField(heading, "Type: ", modEntry.mod_type);
+
+                if (metadata.author) {
+                    appendField(heading, "Author: ", metadata.author);
+                }
+                if (metadata.version) {
+                    appendField(heading, "Version: ", metadata.version);
+                }
+                if (metadata.description) {
+                    appendNote(heading, metadata.description, "font-size: 18px;");
+                }
+                if (modEntry.incompatibility) {
+                    appendNote(heading, `Will not be loaded because ${modEntry.incompatibility}`, "font-size: 18px; color: yellow");
+                }
+
+                const toggle = document.createElement("label");
+                toggle.className = "toggle-switch";
+                const checkbox = document.createElement("input");
+                checkbox.id = path;
+                checkbox.type = "checkbox";
+                const slider = document.createElement("span");
+                slider.className = "slider";
+                toggle.append(checkbox, slider);
+                heading.append(toggle, document.createElement("br"));
+
+                const exportLink = document.createElement("a");
+                exportLink.href = "/export-mod/" + path;
+                exportLink.textContent = "Export";
+
+                const removeLink = document.createElement("a");
+                removeLink.href = "#";
+                removeLink.textContent = "Remove";
+                removeLink.addEventListener("click", event => {
+                    event.preventDefault();
+                    removeMod(stem);
+                });
+
+                heading.append(exportLink, " ", removeLink);
+                item.appendChild(heading);
 
                 list.appendChild(item);
-                document.getElementById(path).checked = enabled;
+                checkbox.checked = enabled;
                 var entry = [path, enabled];
 
                 previousToggles.push(entry);
@@ -43,21 +79,153 @@ fetch('/mod-list')
         console.error("Failed to load mod list:", err);
     });
 
-function updateModStatus() {
+// Appends "label<strong>value</strong><br>" to parent, with value as plain text
+function appendField(parent, label, value) {
+    if (label) {
+        parent.append(label);
+    }
+
+    const strong = document.createElement("strong");
+    strong.textContent = value;
+    parent.append(strong, document.createElement("br"));
+}
+
+function appendNote(parent, text, style) {
+    const note = document.createElement("span");
+    note.style.cssText = style;
+    note.textContent = text;
+    parent.append(note, document.createElement("br"));
+}
+
+function loadConflicts() {
+    fetch('/mod-conflicts')
+        .then(res => res.json())
+        .then(conflicts => {
+            const conflictList = document.getElementById("conflicts");
+            conflictList.innerHTML = "";
+
+            for (const conflict of conflicts) {
+                const item = document.createElement("p");
+                item.innerText = `${conflict.resource_path} is injected by multiple mods! ${conflict.winner} overrides ${conflict.overridden.join(", ")}. Raise a mod's priority to change this.`;
+                conflictList.appendChild(item);
+            }
+        })
+        .catch(err => {
+            console.error("Failed to load mod conflicts:", err);
+        });
+}
+
+function loadDependencyProblems() {
+    fetch('/mod-dependencies')
+        .then(res => res.json())
+        .then(graph => {
+            const conflictList = document.getElementById("dependency-problems");
+            conflictList.innerHTML = "";
+
+            for (const node of graph) {
+                for (const dependency of node.dependencies) {
+                    if (dependency.problem) {
+                        const item = document.createElement("p");
+                        item.innerText = `${node.mod_name} will not be loaded because ${dependency.problem}.`;
+                        conflictList.appendChild(item);
+                    }
+                }
+            }
+        })
+        .catch(err => {
+            console.error("Failed to load mod dependencies:", err);
+        });
+}
+
+function loadModErrors() {
+    fetch('/mod-errors')
+        .then(res => res.json())
+        .then(errors => {
+            const errorList = document.getElementById("mod-errors");
+            errorList.innerHTML = "";
+
+            for (const error of errors) {
+                const item = document.createElement("p");
+                const location = error.line ? `${error.path} (line ${error.line}, column ${error.column})` : error.path;
+                item.innerText = `Could not load ${location}: ${error.message}`;
+                errorList.appendChild(item);
+            }
+        })
+        .catch(err => {
+            console.error("Failed to load mod errors:", err);
+        });
+}
+
+loadConflicts();
+loadDependencyProblems();
+loadModErrors();
+
+async function updateModStatus() {
     console.log(previousToggles);
     for (var i in previousToggles) {
         console.log(previousToggles[i][0]);
         if (previousToggles[i][1] != document.getElementById(previousToggles[i][0]).checked) {
             previousToggles[i][1] = document.getElementById(previousToggles[i][0]).checked;
             console.log("making request to " + previousToggles[i][0]);
-            fetch("/toggle-mod/" + previousToggles[i][0]);
+            const toggleRes = await fetch("/toggle-mod/" + previousToggles[i][0]);
+
+            // Mods with missing dependencies are refused by the server, so put the switch back
+            if (!toggleRes.ok) {
+                alert(await toggleRes.text());
+                previousToggles[i][1] = !previousToggles[i][1];
+                document.getElementById(previousToggles[i][0]).checked = previousToggles[i][1];
+            }
         }
     }
 
-    fetch("/set-param/restart");
-    alert("Updated preferences.");
+    const res = await fetch("/reload-mods");
+    alert(await res.text());
+    loadConflicts();
+    loadDependencyProblems();
+    loadModErrors();
 }
 
+async function importMod() {
+    const file = document.getElementById("import-selector").files[0];
+
+    if (file === undefined) {
+        alert("Please select a .mmlpack file to import.");
+        return;
+    }
+
+    const res = await fetch("/import-mod", {
+        method: "POST",
+        body: file
+    });
+    alert(await res.text());
+    location.reload();
+}
+
+async function removeMod(stem) {
+    if (!confirm(`Remove ${stem} and all of its AssetBundles? This can't be undone.`)) {
+        return;
+    }
+
+    const res = await fetch("/mods/" + encodeURIComponent(stem), {
+        method: "DELETE"
+    });
+    alert(await res.text());
+    location.reload();
+}
+
+async function rebuildMods() {
+    alert("Rebuilding story mods from their source stories. This may take a while.");
+
+    const res = await fetch("/rebuild-mods");
+    alert(await res.text());
+    loadConflicts();
+    loadDependencyProblems();
+    loadModErrors();
+}
+
+document.getElementById("import-mod").onclick = importMod;
+document.getElementById("rebuild-mods").onclick = rebuildMods;
+
 document.getElementById("submit-mods").onclick = updateModStatus.bind(document.getElementById("submit-mods"));
 
 updateStats();
\ No newline at end of file